@@ -0,0 +1,8 @@
+MEMORY
+{
+  /* Bank 1 sector 0, the boot record follows in sector 1 and the slots from 0x08040000 */
+  FLASH  : ORIGIN = 0x08000000, LENGTH = 128K
+
+  /* DTCM  */
+  RAM    : ORIGIN = 0x20000000, LENGTH = 128K
+}
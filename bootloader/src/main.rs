@@ -0,0 +1,108 @@
+//! Boots the firmware slot selected by the boot record, see `firmware_logic::data::boot`.
+//!
+//! An image activated by an update is booted at most `MAX_BOOT_ATTEMPTS` times before it confirms
+//! itself, the boots are counted in backup SRAM. The watchdog is started for these boots so that a
+//! firmware that hangs is reset, and counted, too. After that the previous image is booted again.
+//!
+//! Linked at the start of the flash, in place of the firmware. The firmware is then built with
+//! `FLASH_ORIGIN=FIRST` or `FLASH_ORIGIN=SECOND` and given its image header by `image_tool sign`.
+
+#![no_std]
+#![no_main]
+
+#[macro_use]
+mod macros;
+
+#[cfg(feature = "defmt")]
+use defmt_rtt as _;
+use cortex_m_rt::entry;
+#[cfg(any(feature = "rtt", feature = "defmt"))]
+use firmware_logic::data::boot::MAX_BOOT_ATTEMPTS;
+use firmware_logic::data::boot::{
+    count_boot, select_slot, BootAttempts, BootRecords, Slot, BOOT_ATTEMPTS_ADDRESS,
+    BOOT_RECORD_OFFSET, SLOT_SIZE,
+};
+use panic_probe as _;
+use stm32h7xx_hal::flash::FlashExt;
+use stm32h7xx_hal::pac;
+
+#[entry]
+fn main() -> ! {
+    #[cfg(feature = "rtt")]
+    rtt_target::rtt_init_print!();
+    let device = pac::Peripherals::take().unwrap();
+    let core = cortex_m::Peripherals::take().unwrap();
+
+    let (mut flash_bank1, _) = device.FLASH.split();
+    let mut boot_records = BootRecords::new(BOOT_RECORD_OFFSET);
+    let mut record = match boot_records.load(&mut flash_bank1.unlocked()) {
+        Ok(record) => record,
+        Err(_err) => {
+            rtt_warn!("Error reading the boot record");
+            None
+        }
+    };
+    if record.is_none() {
+        rtt_warn!("No boot record, booting the first valid slot");
+    }
+
+    // Backup SRAM is only written with the backup domain write protection disabled
+    device.RCC.ahb4enr.modify(|_, w| w.bkpramen().set_bit());
+    device.PWR.cr1.modify(|_, w| w.dbp().set_bit());
+    let mut attempts = unsafe { load_attempts() };
+    if let Some(rollback) = record.as_ref().and_then(|record| count_boot(record, &mut attempts)) {
+        rtt_warn!("The active image failed {} boots, rolling back", MAX_BOOT_ATTEMPTS);
+        if boot_records.save(&mut flash_bank1.unlocked(), &rollback).is_err() {
+            rtt_warn!("Error writing the boot record");
+        }
+        record = Some(rollback);
+    }
+    unsafe { store_attempts(attempts) };
+
+    let Some(slot) = select_slot(record.as_ref(), slot_contents) else {
+        rtt_warn!("No valid firmware in either slot");
+        loop {
+            cortex_m::asm::wfi();
+        }
+    };
+    if record.as_ref().is_some_and(|record| record.active != slot) {
+        rtt_warn!("The active slot is invalid, falling back to the other one");
+    }
+    rtt_debug!("Booting slot at {:x}", slot.address());
+    if record.as_ref().is_some_and(|record| record.active == slot && !record.confirmed) {
+        rtt_debug!("The image is unconfirmed, starting the watchdog");
+        start_watchdog(&device.IWDG1);
+    }
+
+    unsafe {
+        core.SCB.vtor.write(slot.vector_table());
+        cortex_m::asm::bootload(slot.vector_table() as *const u32)
+    }
+}
+
+/// Resets the card unless fed within about 32 s, it cannot be stopped until then. The firmware
+/// feeds it from its logic task.
+fn start_watchdog(iwdg: &pac::IWDG1) {
+    unsafe {
+        iwdg.kr.write(|w| w.bits(0xCCCC));
+        // Unlock the prescaler and reload registers, then divide the 32 kHz LSI by 256
+        iwdg.kr.write(|w| w.bits(0x5555));
+        iwdg.pr.write(|w| w.bits(6));
+        iwdg.rlr.write(|w| w.bits(0xFFF));
+        while iwdg.sr.read().bits() != 0 {}
+        iwdg.kr.write(|w| w.bits(0xAAAA));
+    }
+}
+
+unsafe fn load_attempts() -> BootAttempts {
+    BootAttempts::from_words(core::ptr::read_volatile(BOOT_ATTEMPTS_ADDRESS as *const [u32; 3]))
+}
+
+unsafe fn store_attempts(attempts: BootAttempts) {
+    core::ptr::write_volatile(BOOT_ATTEMPTS_ADDRESS as *mut [u32; 3], attempts.to_words());
+}
+
+/// Both slots are memory mapped.
+fn slot_contents(slot: Slot) -> &'static [u8] {
+    unsafe { core::slice::from_raw_parts(slot.address() as *const u8, SLOT_SIZE as usize) }
+}
@@ -0,0 +1,25 @@
+use firmware_logic::Command;
+use serde_json::{json, Value};
+
+/// Parses a command written as its variant name followed by its fields, e.g. `AnchorUp On`,
+/// `BilgePump 2 Toggle` or `EngineStart All On`.
+///
+/// Numbers are device positions, every other word is passed on as a variant name. The command is
+/// then deserialized, so any `Command` of `firmware_logic` is accepted without being listed here.
+pub fn parse_command(words: &[String]) -> Result<Command, String> {
+    let (variant, fields) = words.split_first().ok_or("missing command")?;
+    let mut fields: Vec<Value> = fields
+        .iter()
+        .map(|field| match field.parse::<usize>() {
+            Ok(position) => json!({ "Device": position }),
+            Err(_) => Value::String(field.clone()),
+        })
+        .collect();
+
+    let value = match fields.len() {
+        0 => Value::String(variant.clone()),
+        1 => json!({ variant.as_str(): fields.remove(0) }),
+        _ => json!({ variant.as_str(): fields }),
+    };
+    serde_json::from_value(value).map_err(|err| format!("`{}`: {err}", words.join(" ")))
+}
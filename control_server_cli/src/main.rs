@@ -0,0 +1,280 @@
+//! Stands in for the control server on the bench.
+//!
+//! Built for the host, like the simulator:
+//! `cargo run -p control_server_cli --target x86_64-unknown-linux-gnu -- --help`.
+
+mod command;
+mod print;
+mod update;
+
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, ErrorKind, Read, Write},
+    net::{Ipv4Addr, TcpStream, UdpSocket},
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::{Parser, Subcommand};
+use firmware_logic::{
+    data::{settings::Settings, status_report::StatusReport},
+    CommandResponse, FirmwareDatagram, ServerReporting, UserCommand, Wingman2HardwareStatus,
+};
+use postcard::accumulator::{CobsAccumulator, FeedResult};
+use simulator::replay::{read_recording, replay, write_recording};
+
+use crate::command::parse_command;
+use crate::print::{print_changes, print_journal, print_snapshot};
+
+/// Commands are sent to this TCP port of the card.
+const CARD_TCP_PORT: u16 = 6973;
+/// The card and the control server both listen on this UDP port.
+const UDP_PORT: u16 = 6972;
+const HEARTBEAT_PERIOD: Duration = Duration::from_millis(250);
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Parser)]
+#[command(about = "Stands in for the control server on the bench")]
+struct Cli {
+    /// Address of the card.
+    #[arg(long, default_value = "10.0.90.194")]
+    card: Ipv4Addr,
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// Sends a command once and prints the response, e.g. `send BilgePump 2 Off`.
+    Send {
+        #[arg(required = true, trailing_var_arg = true)]
+        command: Vec<String>,
+    },
+    /// Repeats a command to keep a button pressed, e.g. `hold --seconds 3 EngineStart 1 On`.
+    Hold {
+        #[arg(long, default_value_t = 1.0)]
+        seconds: f32,
+        /// Milliseconds between two commands, must stay well below the 500 ms eviction delay.
+        #[arg(long, default_value_t = 100)]
+        repeat: u64,
+        #[arg(required = true, trailing_var_arg = true)]
+        command: Vec<String>,
+    },
+    /// Sends heartbeats and prints the status, reporting and journal sent by the card.
+    Monitor {
+        /// Also fetches and prints the journal.
+        #[arg(long)]
+        journal: bool,
+        /// Asks the card to record its cycles and writes them to this file.
+        #[arg(long)]
+        record: Option<PathBuf>,
+    },
+    /// Replays a recording through the logic and prints the outputs that differ.
+    Replay { recording: PathBuf },
+    /// Writes a firmware image to the slot that is not running, the card boots it on its next
+    /// reset. The image is a binary linked with `FLASH_ORIGIN` set to that slot, signed by
+    /// `image_tool sign`.
+    Update { image: PathBuf },
+    /// Saves the settings in a JSON file on the card, which applies them on its next reset. Without
+    /// a file, prints the defaults to start from.
+    Settings { settings: Option<PathBuf> },
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    match cli.action {
+        Action::Send { command } => {
+            let command = parse_command(&command)?;
+            let mut stream = connect(cli.card)?;
+            send_command(&mut stream, UserCommand::new(command, None))?;
+            stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+            match read_responses(&mut stream, |response| println!("{response:?}"))? {
+                0 => Err("no response from the card".into()),
+                _ => Ok(()),
+            }
+        }
+        Action::Hold {
+            seconds,
+            repeat,
+            command,
+        } => {
+            let command = parse_command(&command)?;
+            let mut stream = connect(cli.card)?;
+            let mut responses = stream.try_clone()?;
+            thread::spawn(move || {
+                read_responses(&mut responses, |response| println!("{response:?}"))
+            });
+
+            let end = Instant::now() + Duration::from_secs_f32(seconds);
+            while Instant::now() < end {
+                send_command(&mut stream, UserCommand::new(command, None))?;
+                thread::sleep(Duration::from_millis(repeat));
+            }
+            Ok(())
+        }
+        Action::Monitor { journal, record } => monitor(cli.card, journal, record),
+        Action::Replay { recording } => {
+            let cycles = read_recording(File::open(recording)?)?;
+            println!("{} cycles", cycles.len());
+            let mismatches = replay(cycles);
+            for mismatch in &mismatches {
+                println!("{mismatch:?}");
+            }
+            match mismatches.len() {
+                0 => Ok(()),
+                count => Err(format!("{count} outputs differ").into()),
+            }
+        }
+        Action::Update { image } => update::upload(cli.card, &std::fs::read(image)?),
+        Action::Settings { settings: None } => {
+            println!("{}", serde_json::to_string_pretty(&Settings::default())?);
+            Ok(())
+        }
+        Action::Settings {
+            settings: Some(settings),
+        } => update::save_settings(cli.card, serde_json::from_reader(File::open(settings)?)?),
+    }
+}
+
+fn connect(card: Ipv4Addr) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect((card, CARD_TCP_PORT))?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+/// The card stamps commands with its own clock when they are received.
+fn send_command(stream: &mut TcpStream, user_command: UserCommand) -> Result<(), Box<dyn Error>> {
+    stream.write_all(&postcard::to_stdvec_cobs(&user_command)?)?;
+    Ok(())
+}
+
+/// Prints responses until the stream is closed or times out, returns how many were read.
+fn read_responses(
+    stream: &mut TcpStream,
+    mut on_response: impl FnMut(CommandResponse),
+) -> io::Result<usize> {
+    let mut accumulator = CobsAccumulator::<64>::new();
+    let mut buf = [0u8; 256];
+    let mut count = 0;
+    loop {
+        let size = match stream.read(&mut buf) {
+            Ok(0) => return Ok(count),
+            Ok(size) => size,
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(count)
+            }
+            Err(err) => return Err(err),
+        };
+
+        let mut window = &buf[..size];
+        while !window.is_empty() {
+            window = match accumulator.feed::<CommandResponse>(window) {
+                FeedResult::Consumed => break,
+                FeedResult::OverFull(remaining) | FeedResult::DeserError(remaining) => {
+                    eprintln!("undecodable response");
+                    remaining
+                }
+                FeedResult::Success { data, remaining } => {
+                    count += 1;
+                    on_response(data);
+                    remaining
+                }
+            };
+        }
+    }
+}
+
+fn monitor(card: Ipv4Addr, journal: bool, record: Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, UDP_PORT))?;
+    socket.set_read_timeout(Some(HEARTBEAT_PERIOD))?;
+    let mut recording = record.map(File::create).transpose()?.map(BufWriter::new);
+
+    let mut heartbeat = ServerReporting {
+        journal_request: journal.then_some(0),
+        record: recording.is_some(),
+    };
+    let mut next_heartbeat = Instant::now();
+    let mut snapshot: Option<Wingman2HardwareStatus> = None;
+    let mut printed: Option<Wingman2HardwareStatus> = None;
+    let mut printed_reporting = String::new();
+    let mut journal_boot = None;
+    let mut buf = [0u8; 2_048];
+
+    loop {
+        if Instant::now() >= next_heartbeat {
+            socket.send_to(&postcard::to_stdvec(&heartbeat)?, (card, UDP_PORT))?;
+            next_heartbeat += HEARTBEAT_PERIOD;
+            if let Some(recording) = &mut recording {
+                recording.flush()?;
+            }
+        }
+
+        let size = match socket.recv_from(&mut buf) {
+            Ok((size, _)) => size,
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                continue
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let status = match postcard::from_bytes::<FirmwareDatagram>(&buf[..size]) {
+            Ok(FirmwareDatagram::Status(StatusReport::Snapshot(status))) => {
+                snapshot = Some(status.clone());
+                status
+            }
+            Ok(FirmwareDatagram::Status(StatusReport::Delta(delta))) => {
+                let Some(snapshot) = &snapshot else {
+                    continue;
+                };
+                let mut status = snapshot.clone();
+                if !delta.apply_to(snapshot, &mut status) {
+                    continue;
+                }
+                status
+            }
+            Ok(FirmwareDatagram::Reporting(reporting)) => {
+                let reporting = format!("{reporting:#?}");
+                if reporting != printed_reporting {
+                    println!("{reporting}");
+                    printed_reporting = reporting;
+                }
+                continue;
+            }
+            Ok(FirmwareDatagram::Journal(page)) => {
+                if journal_boot.is_some_and(|boot_id| boot_id != page.boot_id) {
+                    // The sequences started over, the page answered a cursor from the last boot
+                    println!("the card rebooted, fetching its journal again");
+                    journal_boot = Some(page.boot_id);
+                    heartbeat.journal_request = Some(page.first_sequence);
+                    continue;
+                }
+                journal_boot = Some(page.boot_id);
+                print_journal(&page);
+                heartbeat.journal_request = Some(
+                    page.entries
+                        .last()
+                        .map_or(page.next_sequence, |entry| entry.sequence.wrapping_add(1)),
+                );
+                continue;
+            }
+            Ok(FirmwareDatagram::Recording(cycle)) => {
+                if let Some(recording) = &mut recording {
+                    write_recording(&mut *recording, [&cycle])?;
+                }
+                continue;
+            }
+            Err(err) => {
+                eprintln!("undecodable datagram of {size} bytes: {err}");
+                continue;
+            }
+        };
+
+        match &printed {
+            Some(before) if before.step <= status.step => print_changes(before, &status),
+            _ => print_snapshot(&status),
+        }
+        printed = Some(status);
+    }
+}
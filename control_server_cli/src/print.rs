@@ -0,0 +1,108 @@
+use firmware_logic::{
+    data::journal::{JournalEntry, JournalPage},
+    data::status_report::PackedDigital,
+    HardwareDigital, Wingman2HardwareStatus,
+};
+use uom::si::electric_potential::volt;
+
+/// Prints every channel the card reported on.
+pub fn print_snapshot(status: &Wingman2HardwareStatus) {
+    println!(
+        "step {} at {} ms, {}",
+        status.step,
+        status.now.as_millis(),
+        status.configuration
+    );
+    print_digitals("DI", &status.digital_inputs, |_, digital| {
+        digital.level.is_some()
+    });
+    print_digitals("DO", &status.digital_outputs, |_, digital| {
+        digital.level.is_some()
+    });
+    for (address, analog) in status.analog_inputs.iter().enumerate() {
+        let volts = analog.voltage.get::<volt>();
+        if volts != 0.0 {
+            println!("  AI {address:>2}: {volts:.2} V");
+        }
+    }
+}
+
+/// Prints the channels that changed since `before` was printed.
+pub fn print_changes(before: &Wingman2HardwareStatus, after: &Wingman2HardwareStatus) {
+    let analogs_changed: Vec<(usize, f32)> = after
+        .analog_inputs
+        .iter()
+        .zip(&before.analog_inputs)
+        .map(|(after, before)| (after.voltage.get::<volt>(), before.voltage.get::<volt>()))
+        .enumerate()
+        .filter(|(_, (after, before))| (after - before).abs() >= 0.05)
+        .map(|(address, (after, _))| (address, after))
+        .collect();
+    let unchanged = |after: &[HardwareDigital], before: &[HardwareDigital]| {
+        (0..after.len()).all(|address| !changed_from(before)(address, &after[address]))
+    };
+    if unchanged(&after.digital_inputs, &before.digital_inputs)
+        && unchanged(&after.digital_outputs, &before.digital_outputs)
+        && analogs_changed.is_empty()
+    {
+        return;
+    }
+
+    println!("step {} at {} ms", after.step, after.now.as_millis());
+    print_digitals(
+        "DI",
+        &after.digital_inputs,
+        changed_from(&before.digital_inputs),
+    );
+    print_digitals(
+        "DO",
+        &after.digital_outputs,
+        changed_from(&before.digital_outputs),
+    );
+    for (address, volts) in analogs_changed {
+        println!("  AI {address:>2}: {volts:.2} V");
+    }
+}
+
+fn changed_from(before: &[HardwareDigital]) -> impl Fn(usize, &HardwareDigital) -> bool + '_ {
+    move |address, digital| PackedDigital::from(digital) != PackedDigital::from(&before[address])
+}
+
+fn print_digitals(
+    name: &str,
+    digitals: &[HardwareDigital],
+    filter: impl Fn(usize, &HardwareDigital) -> bool,
+) {
+    for (address, digital) in digitals.iter().enumerate() {
+        if filter(address, digital) {
+            println!(
+                "  {name} {address:>2}: {:<5} state {:<12} supply {:<10} temp {:<7} fault {}",
+                show(digital.level),
+                show(digital.state),
+                show(digital.supply),
+                show(digital.temp),
+                show(digital.fault),
+            );
+        }
+    }
+}
+
+fn show(value: Option<impl std::fmt::Debug>) -> String {
+    value.map_or_else(|| "-".into(), |value| format!("{value:?}"))
+}
+
+pub fn print_journal(page: &JournalPage) {
+    for JournalEntry {
+        sequence,
+        timestamp,
+        device,
+        event,
+        reason,
+    } in &page.entries
+    {
+        println!(
+            "#{sequence:<6} {:>10} ms  {device:?}  {event:?}  ({reason:?})",
+            timestamp.as_millis()
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    error::Error,
+    io::{self, ErrorKind, Read, Write},
+    net::{Ipv4Addr, TcpStream},
+    time::Duration,
+};
+
+use firmware_logic::data::{
+    image::ImageHeader,
+    settings::Settings,
+    update::{image_crc, UpdateError, UpdateRequest, UpdateResponse, UPDATE_CHUNK_SIZE},
+};
+use postcard::accumulator::{CobsAccumulator, FeedResult};
+
+/// Firmware updates are sent to this TCP port of the card.
+const CARD_FLASH_TCP_PORT: u16 = 6971;
+/// The card may erase a flash sector before answering, which takes a second or two.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Chunks whose CRC did not match on the card are sent again this many times.
+const CHUNK_RETRIES: usize = 3;
+
+/// Writes `image`, a binary linked for the slot that is not running and signed by `image_tool`, to
+/// the card. The card boots it on its next reset.
+pub fn upload(card: Ipv4Addr, image: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut stream = TcpStream::connect((card, CARD_FLASH_TCP_PORT))?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+    let mut responses = Responses::new(stream.try_clone()?);
+
+    let begin = UpdateRequest::Begin {
+        length: image.len().try_into()?,
+        crc: image_crc(image),
+    };
+    expect(&mut stream, &mut responses, &begin, |response| {
+        *response == UpdateResponse::Ready
+    })?;
+
+    let mut offset = 0;
+    for chunk in image.chunks(UPDATE_CHUNK_SIZE) {
+        let request = UpdateRequest::Chunk {
+            offset,
+            crc: image_crc(chunk),
+            data: chunk.try_into().expect("chunks fit a request"),
+        };
+        offset += chunk.len() as u32;
+        let written = |response: &UpdateResponse| {
+            *response
+                == UpdateResponse::ChunkWritten {
+                    next_offset: offset,
+                }
+        };
+        let mut retries = 0;
+        while let Err(error) = expect(&mut stream, &mut responses, &request, written) {
+            match error {
+                UploadError::Failed(UpdateError::ChunkCrc) if retries < CHUNK_RETRIES => {
+                    retries += 1
+                }
+                error => return Err(error.into()),
+            }
+        }
+        print!("\r{offset} / {} bytes", image.len());
+        io::stdout().flush()?;
+    }
+    println!();
+
+    let verified = |response: &UpdateResponse| matches!(response, UpdateResponse::Verified(_));
+    if let UpdateResponse::Verified(header) = expect(
+        &mut stream,
+        &mut responses,
+        &UpdateRequest::Finish,
+        verified,
+    )? {
+        println!("verified {}", describe(&header));
+    }
+    match responses.next()? {
+        UpdateResponse::Activated => {
+            println!("activated, reset the card to boot it");
+            Ok(())
+        }
+        response => Err(UploadError::Unexpected(response).into()),
+    }
+}
+
+/// Saves `settings` on the card, which applies them on its next reset.
+pub fn save_settings(card: Ipv4Addr, settings: Settings) -> Result<(), Box<dyn Error>> {
+    let mut stream = TcpStream::connect((card, CARD_FLASH_TCP_PORT))?;
+    stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+    let mut responses = Responses::new(stream.try_clone()?);
+    expect(
+        &mut stream,
+        &mut responses,
+        &UpdateRequest::SaveSettings(settings),
+        |response| *response == UpdateResponse::SettingsSaved,
+    )?;
+    println!("saved, reset the card to apply them");
+    Ok(())
+}
+
+/// Version, commit and configurations of a verified image.
+fn describe(header: &ImageHeader) -> String {
+    let git_hash = match header.git_hash {
+        Some(hash) => hash.iter().map(|byte| format!("{byte:02x}")).collect(),
+        None => "unknown commit".to_string(),
+    };
+    let dirty = if header.git_dirty { " (dirty)" } else { "" };
+    format!(
+        "{} {git_hash}{dirty} for {:?}",
+        header.version, header.configurations
+    )
+}
+
+#[derive(Debug)]
+enum UploadError {
+    Failed(UpdateError),
+    Unexpected(UpdateResponse),
+    Io(io::Error),
+}
+
+impl std::fmt::Display for UploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UploadError::Failed(error) => write!(f, "the card refused the update: {error:?}"),
+            UploadError::Unexpected(response) => write!(f, "unexpected response {response:?}"),
+            UploadError::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for UploadError {}
+
+impl From<io::Error> for UploadError {
+    fn from(error: io::Error) -> Self {
+        UploadError::Io(error)
+    }
+}
+
+/// Sends `request` and waits for the response, which must satisfy `expected`.
+fn expect(
+    stream: &mut TcpStream,
+    responses: &mut Responses,
+    request: &UpdateRequest,
+    expected: impl Fn(&UpdateResponse) -> bool,
+) -> Result<UpdateResponse, UploadError> {
+    let frame = postcard::to_stdvec_cobs(request)
+        .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+    stream.write_all(&frame)?;
+    match responses.next()? {
+        UpdateResponse::Failed(error) => Err(UploadError::Failed(error)),
+        response if expected(&response) => Ok(response),
+        response => Err(UploadError::Unexpected(response)),
+    }
+}
+
+/// COBS framed `UpdateResponse`s read from the card.
+struct Responses {
+    stream: TcpStream,
+    accumulator: CobsAccumulator<256>,
+    pending: Vec<u8>,
+}
+
+impl Responses {
+    fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            accumulator: CobsAccumulator::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    fn next(&mut self) -> io::Result<UpdateResponse> {
+        loop {
+            let mut window = &self.pending[..];
+            while !window.is_empty() {
+                window = match self.accumulator.feed::<UpdateResponse>(window) {
+                    FeedResult::Consumed => break,
+                    FeedResult::OverFull(remaining) | FeedResult::DeserError(remaining) => {
+                        eprintln!("undecodable response");
+                        remaining
+                    }
+                    FeedResult::Success { data, remaining } => {
+                        self.pending = remaining.to_vec();
+                        return Ok(data);
+                    }
+                };
+            }
+            self.pending.clear();
+
+            let mut buf = [0u8; 256];
+            match self.stream.read(&mut buf)? {
+                0 => return Err(ErrorKind::UnexpectedEof.into()),
+                size => self.pending.extend_from_slice(&buf[..size]),
+            }
+        }
+    }
+}
@@ -13,7 +13,7 @@
 use std::env;
 use std::fs::File;
 use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 fn main() {
     built::write_built_file().expect("Failed to acquire build-time information");
@@ -32,17 +32,18 @@ fn main() {
 
     let modified_content = match env::var("FLASH_ORIGIN") {
         Ok(val) => {
+            // The image header takes the first 1K of the slot, see `firmware_logic::data::image`
             let flash_origin = match val.as_str() {
-                "SECOND" => "0x08100000",
-                "FIRST" => "0x08040000",
+                "SECOND" => "0x08100400",
+                "FIRST" => "0x08040400",
                 _ => panic!("Invalid value for FLASH_ORIGIN. Use 'FIRST' or 'SECOND'."),
             };
-            // Replace the FLASH: ORIGIN placeholder or value
+            // Replace the FLASH: ORIGIN placeholder or value, slots are six sectors, see memory.x
             memory_x_content
                 .lines()
                 .map(|line| {
                     if line.starts_with("  FLASH  : ORIGIN = 0x") {
-                        format!("FLASH  : ORIGIN = {}, LENGTH = 1M", flash_origin)
+                        format!("  FLASH  : ORIGIN = {}, LENGTH = 767K", flash_origin)
                     } else {
                         line.to_string()
                     }
@@ -61,4 +62,53 @@ fn main() {
         .unwrap();
 
     println!("cargo:rustc-link-search={}", out.display());
+    println!("cargo:rerun-if-env-changed=FLASH_ORIGIN");
+
+    write_update_key(out);
+}
+
+/// Bakes the public key images are signed with into `update_key.rs`. `UPDATE_PUBLIC_KEY` is the
+/// path of a key written by `image_tool keygen` and must be set, unless the `bench-key` feature
+/// picks a key generated locally with `image_tool keygen keys/bench`, never committed.
+fn write_update_key(out: &Path) {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    println!("cargo:rerun-if-env-changed=UPDATE_PUBLIC_KEY");
+    let mut key_file = File::create(out.join("update_key.rs")).unwrap();
+    let key_path = match env::var_os("UPDATE_PUBLIC_KEY") {
+        Some(path) => PathBuf::from(path),
+        None if env::var_os("CARGO_FEATURE_BENCH_KEY").is_some() => {
+            manifest_dir.join("keys").join("bench.pub")
+        }
+        None => {
+            // Fails the firmware build on the line including the key, not the build script
+            writeln!(
+                key_file,
+                "compile_error!(\"UPDATE_PUBLIC_KEY is not set, point it at the public key the \
+                 images are signed with, or enable the `bench-key` feature for a bench key\");\n\
+                 pub const UPDATE_PUBLIC_KEY: [u8; 32] = [0; 32];"
+            )
+            .unwrap();
+            return;
+        }
+    };
+    println!("cargo:rerun-if-changed={}", key_path.display());
+
+    let hex = std::fs::read_to_string(&key_path)
+        .unwrap_or_else(|err| panic!("Cannot read {}: {err}", key_path.display()));
+    let hex = hex.trim();
+    assert!(
+        hex.len() == 64 && hex.bytes().all(|byte| byte.is_ascii_hexdigit()),
+        "{} is not an Ed25519 public key in hexadecimal",
+        key_path.display()
+    );
+    let bytes: Vec<String> = (0..32)
+        .map(|index| format!("0x{}", &hex[index * 2..index * 2 + 2]))
+        .collect();
+
+    writeln!(
+        key_file,
+        "pub const UPDATE_PUBLIC_KEY: [u8; 32] = [{}];",
+        bytes.join(", ")
+    )
+    .unwrap();
 }
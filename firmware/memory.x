@@ -6,10 +6,15 @@ MEMORY
   /* STM32H742xI/743xI/753xI       */
   /* STM32H745xI/747xI/755xI/757xI */
   /* STM32H7A3xI/7B3xI             */
-  /* FLASH  : ORIGIN = 0x08040000, LENGTH = 1M */
-  /* FLASH  : ORIGIN = 0x08040000, LENGTH = 1M */
+  /* FLASH  : ORIGIN = 0x08040400, LENGTH = 767K */
+  /* FLASH  : ORIGIN = 0x08100400, LENGTH = 767K */
   FLASH  : ORIGIN = 0x08000000, LENGTH = 1M
 
+  /* Bank 1 sector 0 holds the bootloader, sector 1 the boot record */
+  /* FLASH_ORIGIN=FIRST links at bank 1 sectors 2 to 7, SECOND at bank 2 sectors 0 to 5 */
+  /* Both after the 1K image header at the start of the slot */
+  /* Bank 2 sectors 6 and 7 hold the settings and are never linked */
+
   /* STM32H742xG/743xG       */
   /* STM32H745xG/STM32H747xG */
   /* STM32H7A3xG             */
@@ -32,8 +37,8 @@ MEMORY
   SRAM3 : ORIGIN = 0x30040000, LENGTH = 32K
   SRAM4 : ORIGIN = 0x38000000, LENGTH = 64K
 
-  /* Backup SRAM */
-  BSRAM : ORIGIN = 0x38800000, LENGTH = 4K
+  /* Backup SRAM, the first 16 bytes hold the boot attempts counted by the bootloader */
+  BSRAM : ORIGIN = 0x38800010, LENGTH = 4K - 16
 
   /* Instruction TCM */
   ITCM  : ORIGIN = 0x00000000, LENGTH = 64K
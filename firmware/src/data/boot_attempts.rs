@@ -0,0 +1,26 @@
+use firmware_logic::data::boot::{BootAttempts, BOOT_ATTEMPTS_ADDRESS};
+use stm32h7xx_hal::pac;
+
+const ADDRESS: *mut [u32; 3] = BOOT_ATTEMPTS_ADDRESS as *mut [u32; 3];
+
+/// Reads the boots counted by the bootloader from backup SRAM. Its rollback report is kept until
+/// `clear_rollback_report` once it was reported.
+pub fn read_boot_attempts() -> BootAttempts {
+    // Enabled by the bootloader already, unless the firmware was flashed by a debugger
+    unsafe {
+        (*pac::RCC::ptr()).ahb4enr.modify(|_, w| w.bkpramen().set_bit());
+        (*pac::PWR::ptr()).cr1.modify(|_, w| w.dbp().set_bit());
+    }
+    BootAttempts::from_words(unsafe { core::ptr::read_volatile(ADDRESS) })
+}
+
+/// Takes the rollback report of the bootloader so that it is not reported again after the next
+/// reset, call once the control server received it.
+pub fn clear_rollback_report() {
+    let attempts = BootAttempts::from_words(unsafe { core::ptr::read_volatile(ADDRESS) });
+    let taken = BootAttempts {
+        rolled_back_from: None,
+        ..attempts
+    };
+    unsafe { core::ptr::write_volatile(ADDRESS, taken.to_words()) };
+}
@@ -1 +1,5 @@
 include!(concat!(env!("OUT_DIR"), "/built.rs"));
+
+/// Found in the binary by `image_tool sign`, which copies it into the image header.
+pub static BUILD_INFO: firmware_logic::data::image::BuildInfo =
+    firmware_logic::data::image::BuildInfo::new(PKG_VERSION, GIT_COMMIT_HASH, GIT_DIRTY);
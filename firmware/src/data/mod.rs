@@ -1 +1,3 @@
-pub(crate) mod built_info;
+pub mod boot_attempts;
+pub mod built_info;
+pub mod settings;
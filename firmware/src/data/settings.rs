@@ -0,0 +1,23 @@
+use firmware_logic::data::settings::{Settings, SettingsStore};
+use stm32h7xx_hal::flash::LockedFlashBank;
+
+/// Sectors 6 and 7 of bank 2, offsets within the bank. Nothing else is linked there, see `memory.x`.
+pub const SETTINGS_SECTORS: (u32, u32) = (0xC_0000, 0xE_0000);
+
+/// Loads the settings kept in flash, a blank card or one whose copies are both invalid starts from
+/// the defaults until settings are saved. The store returned saves them, see `FirmwareUpdate`.
+pub fn load_settings(bank: &mut LockedFlashBank) -> (Settings, SettingsStore) {
+    let mut store = SettingsStore::new(SETTINGS_SECTORS.0, SETTINGS_SECTORS.1);
+    let settings = match store.load(bank) {
+        Ok(Some(settings)) => settings,
+        Ok(None) => {
+            rtt_warn!("No valid settings in flash, using the defaults");
+            Settings::default()
+        }
+        Err(_err) => {
+            rtt_warn!("Error reading the settings from flash, using the defaults");
+            Settings::default()
+        }
+    };
+    (settings, store)
+}
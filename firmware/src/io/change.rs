@@ -0,0 +1,53 @@
+//! Input change notification, from the EXTI interrupt of the MAX7301 INT line to the IO scan.
+
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Poll, Waker};
+
+use cortex_m::interrupt::{self, Mutex};
+
+/// Set by the interrupt handler, taken by the IO scan.
+pub static INPUT_CHANGE: InputChange = InputChange::new();
+
+/// A flag with a single waiter.
+pub struct InputChange {
+    changed: AtomicBool,
+    waker: Mutex<RefCell<Option<Waker>>>,
+}
+
+impl InputChange {
+    const fn new() -> Self {
+        Self {
+            changed: AtomicBool::new(false),
+            waker: Mutex::new(RefCell::new(None)),
+        }
+    }
+
+    /// Flags a change and wakes the waiter, called from the interrupt handler.
+    pub fn notify(&self) {
+        self.changed.store(true, Ordering::Release);
+        interrupt::free(|cs| {
+            if let Some(waker) = self.waker.borrow(cs).borrow_mut().take() {
+                waker.wake();
+            }
+        });
+    }
+
+    /// Completes once a change was flagged, right away for one flagged since the last wait.
+    pub async fn wait(&self) {
+        poll_fn(|cx| {
+            if self.changed.swap(false, Ordering::Acquire) {
+                return Poll::Ready(());
+            }
+            interrupt::free(|cs| *self.waker.borrow(cs).borrow_mut() = Some(cx.waker().clone()));
+            // The interrupt may have come in before the waker was stored
+            if self.changed.swap(false, Ordering::Acquire) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
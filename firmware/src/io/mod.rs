@@ -1 +1,3 @@
+pub mod change;
 pub mod dio;
+pub mod scan;
@@ -0,0 +1,217 @@
+use core::fmt::Debug;
+
+use asm_delay_embedded_time::AsmDelay;
+use cortex_m::peripheral::DWT;
+use embedded_time::rate::Hertz;
+use firmware_logic::drivers::dio::{update_watched_inputs, Dio};
+use firmware_logic::data::io_population::IoPopulation;
+use firmware_logic::data::io_scan::{IoScanStatus, IoScanTiming};
+use firmware_logic::{HardwareDigital, IoBank, IoError, IoLevel, Wingman2HardwareStatus, DIGITAL_INPUTS};
+use stm32h7xx_hal::device::SPI4;
+use stm32h7xx_hal::gpio::{Output, Pin, Speed};
+use stm32h7xx_hal::prelude::*;
+use stm32h7xx_hal::rcc::rec::Spi4;
+use stm32h7xx_hal::rcc::CoreClocks;
+use stm32h7xx_hal::spi::{self, Spi};
+use strum::IntoEnumIterator;
+
+use crate::io::dio::DioSpi;
+
+/// The DI and DO controllers of the six banks on SPI4, selected through the SEL0..3 decoder.
+pub type BoardDio = Dio<
+    DioSpi,
+    Pin<'E', 11, Output>,
+    Pin<'E', 7, Output>,
+    Pin<'E', 8, Output>,
+    Pin<'E', 9, Output>,
+    Pin<'E', 10, Output>,
+    Pin<'E', 15, Output>,
+    AsmDelay,
+>;
+
+/// The cycle counter runs at the core clock.
+const CYCLES_PER_US: u32 = 200;
+
+/// Ports 24 to 30 of every DO bank, read as the watched digital inputs, wake the scan on a change.
+const WATCHED_INPUTS: u8 = 0x7F;
+
+/// Scans the IO banks: reads every input and the status of every output, then writes the output
+/// levels commanded by the logic.
+///
+/// The banks are read into a scratch copy so that `Wingman2HardwareStatus` is only locked to
+/// exchange it, the SPI transfers take a while. Controllers that failed the boot self-test are
+/// skipped, their channels stay unknown.
+pub struct IoScan {
+    dio: BoardDio,
+    population: IoPopulation,
+    inputs: [HardwareDigital; DIGITAL_INPUTS],
+    outputs: [HardwareDigital; 48],
+    levels: [Option<IoLevel>; 48],
+    started_at: u32,
+    failed_reads: u32,
+    failed_writes: u32,
+    timing: IoScanTiming,
+}
+
+impl IoScan {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        spi4: SPI4,
+        peripherals_spi4: Spi4,
+        sck: Pin<'E', 12>,
+        miso: Pin<'E', 13>,
+        mosi: Pin<'E', 14>,
+        ncs: Pin<'E', 11>,
+        sel: (Pin<'E', 7>, Pin<'E', 8>, Pin<'E', 9>, Pin<'E', 10>),
+        ld: Pin<'E', 15>,
+        clocks: &CoreClocks,
+    ) -> Self {
+        let sck = sck.into_alternate().speed(Speed::Medium);
+        let miso = miso.into_alternate().speed(Speed::Medium);
+        let mosi = mosi.into_alternate().speed(Speed::Medium);
+        let mut ncs = ncs.into_push_pull_output();
+        ncs.set_high();
+        let mut ld = ld.into_push_pull_output();
+        ld.set_high();
+
+        // The mode is switched per transfer, the MAX7301 and the SN65HVS881 differ
+        let spi: Spi<_, _, u8> =
+            spi4.spi((sck, miso, mosi), spi::MODE_0, 4.MHz(), peripherals_spi4, clocks);
+        let mut dio = Dio::new(
+            sel.0.into_push_pull_output(),
+            sel.1.into_push_pull_output(),
+            sel.2.into_push_pull_output(),
+            sel.3.into_push_pull_output(),
+            DioSpi(spi),
+            ncs,
+            ld,
+            AsmDelay::new(Hertz(200_000_000u32)),
+        );
+        // A missing bank fails here and then on every scan, the scan counts the failures
+        if dio.init().is_err() {
+            rtt_warn!("Error initializing the IO banks");
+        }
+        dio.set_write_verify(true);
+        let population = dio.probe();
+        for (bank, probed) in IoBank::iter().zip(population.banks) {
+            if !probed.inputs.is_usable() || !probed.outputs.is_usable() {
+                rtt_warn!("IO bank {} failed the self-test", bank as u8 + 1);
+            }
+            if probed.outputs.is_usable()
+                && dio.enable_change_detection(bank, WATCHED_INPUTS).is_err()
+            {
+                rtt_warn!("Error enabling the input change detection");
+            }
+        }
+
+        Self {
+            dio,
+            population,
+            inputs: core::array::from_fn(|_| HardwareDigital::default()),
+            outputs: core::array::from_fn(|_| HardwareDigital::default()),
+            levels: [None; 48],
+            started_at: 0,
+            failed_reads: 0,
+            failed_writes: 0,
+            timing: IoScanTiming::default(),
+        }
+    }
+
+    /// Starts a scan, reading the inputs, the output status and the watched inputs of every bank.
+    ///
+    /// The change detection is rearmed first, a change while the banks are read wakes the next
+    /// scan.
+    pub fn read_banks(&mut self) {
+        self.started_at = DWT::cycle_count();
+        self.failed_reads = 0;
+        self.failed_writes = 0;
+
+        for bank in IoBank::iter() {
+            if !self.population.banks[bank as usize].outputs.is_usable() {
+                continue;
+            }
+            checked(self.dio.rearm_change_detection(bank), &mut self.failed_writes);
+        }
+
+        for (bank, inputs) in IoBank::iter().zip(self.inputs.chunks_mut(8)) {
+            if !self.population.banks[bank as usize].inputs.is_usable() {
+                continue;
+            }
+            let read = checked(self.dio.read_di_bank(bank), &mut self.failed_reads);
+            for (input, channel) in inputs.iter_mut().enumerate() {
+                channel.level = read.map(|read| read.inputs[input]);
+                channel.supply = read.map(|read| read.supply);
+                channel.temp = read.map(|read| read.temp);
+            }
+        }
+
+        for (bank, outputs) in IoBank::iter().zip(self.outputs.chunks_mut(8)) {
+            if !self.population.banks[bank as usize].outputs.is_usable() {
+                continue;
+            }
+            let read = checked(self.dio.read_do_bank(bank), &mut self.failed_reads);
+            for (output, channel) in outputs.iter_mut().enumerate() {
+                channel.state = read.and_then(|read| read.states).map(|states| states[output]);
+                channel.supply = read.map(|read| read.supply);
+                channel.temp = read.map(|read| read.temp);
+            }
+            update_watched_inputs(&mut self.inputs, bank, read.as_ref());
+        }
+    }
+
+    /// Hands the banks read to `hardware_status` and takes the output levels the logic commanded.
+    pub fn exchange(&mut self, hardware_status: &mut Wingman2HardwareStatus) {
+        hardware_status.digital_inputs.clone_from(&self.inputs);
+        for (output, scanned) in hardware_status.digital_outputs.iter_mut().zip(&self.outputs) {
+            output.state = scanned.state;
+            output.supply = scanned.supply;
+            output.temp = scanned.temp;
+            output.fault = scanned.fault;
+        }
+        self.levels = core::array::from_fn(|address| hardware_status.digital_outputs[address].level);
+    }
+
+    /// Completes the scan, writing the output levels taken by `exchange`. The outputs are read
+    /// back as they are written, their faults are handed over by the next `exchange`.
+    pub fn write_banks(&mut self) {
+        let banks = IoBank::iter().zip(self.levels.chunks(8).zip(self.outputs.chunks_mut(8)));
+        for (bank, (levels, outputs)) in banks {
+            if !self.population.banks[bank as usize].outputs.is_usable() {
+                continue;
+            }
+            // Outputs not commanded yet are left off
+            let levels = core::array::from_fn(|output| levels[output].unwrap_or(IoLevel::HiZ));
+            let faults = checked(self.dio.write_do_bank(bank, levels), &mut self.failed_writes);
+            for (output, channel) in outputs.iter_mut().enumerate() {
+                channel.fault = faults.and_then(|faults| faults[output]);
+            }
+        }
+
+        let duration_us = DWT::cycle_count().wrapping_sub(self.started_at) / CYCLES_PER_US;
+        self.timing.record(duration_us, self.failed_reads, self.failed_writes);
+    }
+
+    /// Records that the next scan was woken by an input change.
+    pub fn record_change_wakeup(&mut self) {
+        self.timing.record_change_wakeup();
+    }
+
+    pub fn status(&self) -> IoScanStatus {
+        self.timing.status()
+    }
+
+    /// Controllers found by the boot self-test.
+    pub fn population(&self) -> IoPopulation {
+        self.population
+    }
+}
+
+fn checked<T, SPIE: Debug, HALE: Debug>(
+    result: Result<T, IoError<SPIE, HALE>>,
+    failures: &mut u32,
+) -> Option<T> {
+    if result.is_err() {
+        *failures += 1;
+    }
+    result.ok()
+}
@@ -1,4 +1,9 @@
 #![no_main]
 #![no_std]
 
+// The binary logs at every level, the modules shared with it only warn
+#[allow(unused_macros)]
+#[macro_use]
+mod macros;
+
 pub mod data;
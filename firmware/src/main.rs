@@ -17,19 +17,31 @@ use rtic::app;
 
 #[app(device = stm32h7xx_hal::stm32, peripherals = true, dispatchers = [EXTI0, EXTI1, EXTI2])]
 mod app {
+    use crate::data::boot_attempts::read_boot_attempts;
+    use crate::data::built_info::BUILD_INFO;
+    use crate::data::settings::load_settings;
+    use crate::io::change::INPUT_CHANGE;
+    use crate::io::scan::IoScan;
     use crate::net::ethernet::Ethernet;
+    use crate::net::firmware_update::FirmwareUpdate;
     use crate::net::net_storage::NetStorage;
     use crate::oled_display::OledDisplay;
     #[cfg(feature = "defmt")]
     use defmt_rtt as _;
+    use firmware_logic::data::io_population::IoSelfTest;
+    use firmware_logic::data::io_scan::IO_SCAN_INTERVAL;
+    use firmware_logic::data::recording::Recorder;
     use firmware_logic::data::user_commands::UserCommands;
-    use firmware_logic::{Configuration, ControllerLogic, FirmwareLogic, FirmwareReporting, Wingman2HardwareStatus, Wingman2IOCardStatus, Wingman3HardwareStatus};
+    use firmware_logic::{ControllerLogic, FirmwareLogic, FirmwareReporting, Wingman2HardwareStatus, Wingman2IOCardStatus, Wingman3HardwareStatus};
     use panic_probe as _;
     use rtic_monotonics::systick::Systick;
     use rtic_monotonics::Monotonic;
+    use smoltcp::wire::EthernetAddress;
     use stm32h7xx_hal::delay::DelayFromCountDownTimer;
-    use stm32h7xx_hal::gpio::Speed;
+    use stm32h7xx_hal::flash::FlashExt;
+    use stm32h7xx_hal::gpio::{Edge, ExtiPin, Input, Pin, Speed};
     use stm32h7xx_hal::prelude::*;
+    use stm32h7xx_hal::signature::Uid;
 
     // Set up PLL to 168MHz from 16MHz HSI
     #[shared]
@@ -39,6 +51,7 @@ mod app {
         logic: FirmwareLogic,
         reporting: FirmwareReporting,
         user_commands: UserCommands,
+        recorder: Recorder,
         ethernet: Ethernet,
         shared_hardware_status: Wingman3HardwareStatus,
     }
@@ -46,6 +59,9 @@ mod app {
     #[local]
     struct LocalResources {
         oled_display: OledDisplay,
+        firmware_update: FirmwareUpdate,
+        io_scan: IoScan,
+        io_interrupt: Pin<'E', 3, Input>,
     }
 
     #[init(local = [card_status: Wingman2IOCardStatus = Wingman2IOCardStatus::default(), net_storage: NetStorage = NetStorage::new() ])]
@@ -81,6 +97,20 @@ mod app {
 
         rtt_debug!("Core Initialized");
 
+        let (flash_bank1, flash_bank2) = cx.device.FLASH.split();
+        let mut flash_bank2 = flash_bank2.expect("STM32H747 has two flash banks");
+        let (settings, settings_store) = load_settings(&mut flash_bank2);
+        rtt_debug!("Settings loaded");
+        let firmware_update = FirmwareUpdate::new(flash_bank1, flash_bank2, settings_store, settings.configuration, read_boot_attempts());
+        // Only read by `image_tool` from the binary, keep the linker from dropping it
+        core::hint::black_box(&BUILD_INFO);
+        let mac_address = settings.network.mac_address(Uid::read());
+        // Lets the control server tell a reboot from a journal that stopped growing
+        let boot_id = cx.device.RNG.constrain(ccdr.peripheral.RNG, &ccdr.clocks).value().unwrap_or_else(|_err| {
+            rtt_warn!("Error reading the random number generator");
+            0
+        });
+
         let systick_token = rtic_monotonics::create_systick_token!();
         Systick::start(cx.core.SYST, 200_000_000, systick_token);
 
@@ -118,8 +148,28 @@ mod app {
         let gpioi = cx.device.GPIOI.split(ccdr.peripheral.GPIOI);
         let gpioj = cx.device.GPIOJ.split(ccdr.peripheral.GPIOJ);
         let gpiok = cx.device.GPIOK.split(ccdr.peripheral.GPIOK);
+        rtt_debug!("Initializing the IO banks ...");
+        let io_scan = IoScan::new(
+            cx.device.SPI4,
+            ccdr.peripheral.SPI4,
+            gpioe.pe12,
+            gpioe.pe13,
+            gpioe.pe14,
+            gpioe.pe11,
+            (gpioe.pe7, gpioe.pe8, gpioe.pe9, gpioe.pe10),
+            gpioe.pe15,
+            &ccdr.clocks,
+        );
+        // The INT outputs of the six MAX7301 are OR'ed onto PE3
+        let mut syscfg = cx.device.SYSCFG;
+        let mut exti = cx.device.EXTI;
+        let mut io_interrupt = gpioe.pe3.into_pull_down_input();
+        io_interrupt.make_interrupt_source(&mut syscfg);
+        io_interrupt.trigger_on_edge(&mut exti, Edge::Rising);
+        io_interrupt.enable_interrupt(&mut exti);
+
         rtt_debug!("Initializing the display ...");
-        let oled_display = OledDisplay::new(
+        let mut oled_display = OledDisplay::new(
             cx.device.SPI1,
             ccdr.peripheral.SPI1,
             gpiog.pg11,
@@ -130,6 +180,7 @@ mod app {
             &mut delay1,
             ccdr.clocks,
         );
+        oled_display.set_mac_address(mac_address);
 
         let rmii_ref_clk = gpioa.pa1.into_alternate().speed(Speed::VeryHigh);
         let rmii_mdio = gpioa.pa2.into_alternate().speed(Speed::VeryHigh);
@@ -170,22 +221,37 @@ mod app {
             ethernet_timer,
             &ccdr.clocks,
             cx.local.net_storage,
+            EthernetAddress(mac_address),
+            &settings.network,
+            &settings.tunables,
         );
 
+        // Controllers whose devices sit on a bank that failed the self-test do not run
+        let io_population = io_scan.population();
+        let mut hardware_status = Wingman2HardwareStatus::new(settings.configuration);
+        hardware_status.io_population = io_population;
+        let reporting = FirmwareReporting {
+            io_self_test: IoSelfTest::new(io_population, settings.configuration),
+            boot: firmware_update.boot_status().clone(),
+            ..FirmwareReporting::default()
+        };
+
         ethernet_sync_control_server::spawn().unwrap();
         apply_logic::spawn().unwrap();
+        scan_io::spawn().unwrap();
         (
             SharedResources {
-                hardware_status: Wingman2HardwareStatus::new(Configuration::Unconfigured),
+                hardware_status,
                 user_commands: UserCommands::default(),
-                logic: FirmwareLogic::default(),
-                reporting: FirmwareReporting::default(),
-                card_status: Wingman2IOCardStatus::default(),
+                recorder: Recorder::default(),
+                logic: FirmwareLogic::with_boot_id(boot_id),
+                reporting,
+                card_status: Wingman2IOCardStatus::new(settings.configuration),
                 ethernet,
-                shared_hardware_status: Wingman3HardwareStatus::new(Configuration::Unconfigured),
+                shared_hardware_status: Wingman3HardwareStatus::new(settings.configuration),
 
             },
-            LocalResources { oled_display, /*dio*/ },
+            LocalResources { oled_display, firmware_update, io_scan, io_interrupt },
         )
     }
 
@@ -200,33 +266,53 @@ mod app {
         }
     }
 
-    #[task(priority = 1, local = [oled_display], shared = [ethernet, hardware_status])]
+    #[task(priority = 1, local = [oled_display], shared = [ethernet, hardware_status, card_status])]
     async fn display_task(mut cx: display_task::Context) {
         // (&mut cx.shared.hardware_status, &mut cx.shared.shared_hardware_status).lock(|hardware_status, shared_hardware_status| {
         //     *shared_hardware_status = hardware_status.clone();
         // });
 
-        cx.shared
-            .hardware_status
-            .lock(|hardware_status|
-        cx.local.oled_display.set_timestamp(hardware_status.now));
+        cx.shared.hardware_status.lock(|hardware_status| {
+            cx.local.oled_display.set_timestamp(hardware_status.now);
+            cx.local.oled_display.set_link(hardware_status.ethernet_link.mode);
+        });
+        cx.shared.card_status.lock(|card_status| {
+            cx.local.oled_display.set_network_status(card_status.is_connected_to_control_server);
+            cx.local.oled_display.set_control_server_qos(card_status.control_server_qos);
+        });
         cx.local.oled_display.update();
     }
 
-    #[task(priority = 2, shared = [ethernet, user_commands, hardware_status, reporting])]
+    #[task(priority = 2, local = [firmware_update], shared = [ethernet, user_commands, recorder, hardware_status, reporting, logic, card_status])]
     async fn ethernet_sync_control_server(mut cx: ethernet_sync_control_server::Context) {
         loop {
             // let reporting = cx.shared.reporting.lock(|reporting| reporting.clone());
-            (&mut cx.shared.ethernet, &mut cx.shared.user_commands, &mut cx.shared.hardware_status, &mut cx.shared.reporting).lock(
-                |ethernet, user_commands, hardware_status, reporting| {
-                    ethernet.synchronize_control_server_socket(
+            (&mut cx.shared.ethernet, &mut cx.shared.user_commands, &mut cx.shared.recorder, &mut cx.shared.hardware_status, &mut cx.shared.reporting, &mut cx.shared.logic, &mut cx.shared.card_status).lock(
+                |ethernet, user_commands, recorder, hardware_status, reporting, logic, card_status| {
+                    let reported = ethernet.synchronize_control_server_socket(
                         user_commands,
+                        recorder,
                         hardware_status,
                         reporting,
+                        logic,
                     );
+                    if reported {
+                        cx.local.firmware_update.boot_status_reported();
+                    }
+                    cx.local.firmware_update.confirm_when_healthy(
+                        hardware_status.now,
+                        ethernet.is_connected_to_control_server(),
+                    );
+                    reporting.boot = cx.local.firmware_update.boot_status().clone();
+                    ethernet.update_card_status(card_status);
+                    reporting.card = card_status.clone();
+                    hardware_status.ethernet_link = *ethernet.link();
                 },
             );
-            Systick::delay(25.millis().into()).await;
+            // Erasing a slot sector blocks for a while, the logic and the IO scan keep running
+            cx.shared.ethernet.lock(|ethernet| ethernet.synchronize_flash_socket(cx.local.firmware_update));
+            // Status changes are sent as soon as they are applied, see `StatusReporter`
+            Systick::delay(5.millis().into()).await;
         }
     }
 
@@ -235,10 +321,13 @@ mod app {
         hardware_status.now = <Systick as Monotonic>::now().into();
     }
 
-    fn apply_status_to_update_hardware(hardware_status: &Wingman2HardwareStatus) {
+    /// The bootloader starts the watchdog for an image that has not confirmed itself yet, see
+    /// `FirmwareUpdate`. Reloading it is harmless when it was not started.
+    fn feed_watchdog() {
+        unsafe { (*stm32h7xx_hal::pac::IWDG1::ptr()).kr.write(|w| w.bits(0xAAAA)) };
     }
 
-    #[task(priority = 3, shared = [ethernet, hardware_status, user_commands, logic, reporting])]
+    #[task(priority = 3, shared = [hardware_status, user_commands, recorder, logic])]
     async fn apply_logic(mut cx: apply_logic::Context) {
         (&mut cx.shared.hardware_status, &mut cx.shared.logic).lock(|hardware_status, logic| {
             update_hardware_status(hardware_status);
@@ -246,18 +335,44 @@ mod app {
         });
 
         loop {
-            (&mut cx.shared.hardware_status, &mut cx.shared.logic).lock(
-                |hardware_status, logic| {
+            (&mut cx.shared.hardware_status, &mut cx.shared.logic, &mut cx.shared.recorder, &mut cx.shared.user_commands).lock(
+                |hardware_status, logic, recorder, user_commands| {
                     update_hardware_status(hardware_status);
-                    cx.shared.user_commands.lock(|user_commands| {
-                        logic.apply_user_commands(user_commands, hardware_status);
-                    });
-                    logic.update(hardware_status);
-                    apply_status_to_update_hardware(hardware_status);
+                    logic.run_cycle(user_commands, recorder, hardware_status);
                 },
             );
+            feed_watchdog();
 
             Systick::delay(5.millis().into()).await;
         }
     }
+
+    /// A watched input changed, wakes `scan_io` ahead of its interval.
+    #[task(binds = EXTI3, priority = 4, local = [io_interrupt])]
+    fn input_changed(cx: input_changed::Context) {
+        cx.local.io_interrupt.clear_interrupt_pending_bit();
+        INPUT_CHANGE.notify();
+    }
+
+    /// Scans the IO banks at the rate the logic is applied at, or right away when a watched input
+    /// changes. Shares the priority of `apply_logic` so that neither preempts the other in the
+    /// middle of a cycle.
+    ///
+    /// A change while the INT line is already held high by another bank raises no edge, it is
+    /// picked up by the next periodic scan.
+    #[task(priority = 3, local = [io_scan], shared = [hardware_status, reporting])]
+    async fn scan_io(mut cx: scan_io::Context) {
+        loop {
+            let io_scan = &mut *cx.local.io_scan;
+            io_scan.read_banks();
+            cx.shared.hardware_status.lock(|hardware_status| io_scan.exchange(hardware_status));
+            io_scan.write_banks();
+            cx.shared.reporting.lock(|reporting| reporting.io_scan = io_scan.status());
+
+            let interval = (IO_SCAN_INTERVAL.as_millis() as u32).millis();
+            if Systick::timeout_after(interval.into(), INPUT_CHANGE.wait()).await.is_ok() {
+                io_scan.record_change_wakeup();
+            }
+        }
+    }
 }
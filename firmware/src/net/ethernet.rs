@@ -1,19 +1,21 @@
+use super::firmware_update::FirmwareUpdate;
 use super::net_storage::NetStorage;
-use crate::lan8720a::LAN8720A;
+use crate::lan8720a::Smi;
 
 use core::ops::Add;
 use cortex_m::prelude::_embedded_hal_blocking_delay_DelayMs;
-use firmware_logic::{data::user_commands::UserCommands, FirmwareReporting, ServerReporting, Timestamp, UserCommand, Wingman2HardwareStatus};
+use firmware_logic::{data::{connection::ConnectionMonitor, frames::{CommandFrames, FrameError}, link::{LinkEvent, LinkMode, LinkMonitor, LinkSpeed, LinkStatus}, ping::ServerProbe, recording::Recorder, settings::{NetworkSettings, Tunables}, status_report::StatusReporter, update::UpdateResponse, user_commands::UserCommands}, drivers::lan8720a::{PhyDiagnostics, LAN8720A}, AddressSource, CommandResponse, FirmwareDatagram, FirmwareLogic, FirmwareReporting, NackReason, NetworkStatus, ServerReporting, Timestamp, Wingman2HardwareStatus, Wingman2IOCardStatus};
 use rtic_monotonics::{systick::Systick, Monotonic};
 use smoltcp::{
     iface::{Interface, SocketHandle, SocketSet},
-    socket::{tcp, udp},
-    wire::{EthernetAddress, IpAddress, IpCidr, IpEndpoint, Ipv4Address, Ipv4Cidr},
+    phy::{ChecksumCapabilities, Device, RxToken, TxToken},
+    socket::{dhcpv4, icmp, tcp, udp},
+    wire::{EthernetAddress, Icmpv4Packet, Icmpv4Repr, IpCidr, IpEndpoint, IpListenEndpoint, Ipv4Address, Ipv4Cidr},
 };
 use stm32h7xx_hal::{
     delay::DelayFromCountDownTimer,
     device::{ETHERNET_DMA, ETHERNET_MAC, ETHERNET_MTL, TIM1},
-    ethernet::{self, EthernetDMA, EthernetMAC, PinsRMII, PHY},
+    ethernet::{self, EthernetDMA, EthernetMAC, PinsRMII},
     rcc::{rec, CoreClocks},
     timer::Timer,
 };
@@ -26,26 +28,25 @@ static mut DES_RING: ethernet::DesRing<ETH_DES_RING_TD, ETH_DES_RING_RD> = ether
 
 pub type EthDMA = EthernetDMA<ETH_DES_RING_TD, ETH_DES_RING_RD>;
 
-/// Locally administered MAC address
-const MAC_ADDRESS: EthernetAddress = EthernetAddress([0x02, 0x00, 0x11, 0x22, 0x33, 0x44]);
-const IP_ADDRESS: Ipv4Address = Ipv4Address::new(10, 0, 90, 194); // /24 subnet mask
 const FLASH_TCP_IP_LISTENING_PORT: u16 = 6971;
 const CONTROL_SERVER_UDP_LISTENING_PORT: u16 = 6972;
 const CONTROL_SERVER_TCP_LISTENING_PORT: u16 = 6973;
-
-const SERVER_IP_ADDRESS: IpAddress = IpAddress::v4(10, 0, 90, 149);
-
-const CONTROL_SERVER_UDP_ENDPOINT: IpEndpoint =
-    IpEndpoint::new(SERVER_IP_ADDRESS, CONTROL_SERVER_UDP_LISTENING_PORT);
-
-const CONTROL_SERVER_TCP_ENDPOINT: IpEndpoint = IpEndpoint::new(
-    IpAddress::Ipv4(IP_ADDRESS),
-    CONTROL_SERVER_TCP_LISTENING_PORT,
-);
+/// Falls back to the static address when no DHCP lease was obtained within this delay.
+const DHCP_TIMEOUT: smoltcp::time::Duration = smoltcp::time::Duration::from_secs(10);
+/// Identifies the echo requests of the card, replies to others are not for the probe.
+const PING_IDENT: u16 = 0x574D;
+const PING_INTERVAL: Timestamp = Timestamp::new(1_000);
+const PING_TIMEOUT: Timestamp = Timestamp::new(2_000);
+const PING_PAYLOAD: &[u8] = b"wingman io2";
+/// The frame of the PHY loopback self-test comes back within microseconds.
+const LOOPBACK_TIMEOUT_MS: u16 = 10;
 
 pub const NUM_FLASH_TCP_SOCKETS: usize = 1;
 pub const NUM_CONTROL_SERVER_SOCKETS: usize = 2;
-pub const NUM_SOCKETS: usize = NUM_FLASH_TCP_SOCKETS + NUM_CONTROL_SERVER_SOCKETS;
+pub const NUM_DHCP_SOCKETS: usize = 1;
+pub const NUM_ICMP_SOCKETS: usize = 1;
+pub const NUM_SOCKETS: usize =
+    NUM_FLASH_TCP_SOCKETS + NUM_CONTROL_SERVER_SOCKETS + NUM_DHCP_SOCKETS + NUM_ICMP_SOCKETS;
 
 pub const UDP_RX_SOCKET_BUFFER_SIZE: usize = 4_096;
 pub const UDP_TX_SOCKET_BUFFER_SIZE: usize = 4_096;
@@ -54,21 +55,46 @@ pub const UDP_SOCKET_METADATA_COUNT: usize = 10;
 pub const TCP_RX_SOCKET_BUFFER_SIZE: usize = 4_096;
 pub const TCP_TX_SOCKET_BUFFER_SIZE: usize = 4_096;
 
+/// Reassembly buffer for COBS framed commands received from the control server.
+/// A frame that does not fit is dropped and counted as a frame error.
+pub const CONTROL_SERVER_FRAME_BUFFER_SIZE: usize = 512;
+
+/// Largest UDP payload that fits an Ethernet frame without IP fragmentation.
+pub const MAX_DATAGRAM_SIZE: usize = 1_472;
+
+/// The ICMP socket only carries the echo requests to the control server and their replies, the
+/// interface answers echo requests to the card itself.
 pub const ICMP_RX_BUFFER_SIZE: usize = 512;
 pub const ICMP_TX_BUFFER_SIZE: usize = 512;
-pub const ICMP_SOCKET_METADATA_COUNT: usize = 512;
+pub const ICMP_SOCKET_METADATA_COUNT: usize = 8;
 
 pub struct Ethernet {
     timer: Timer<TIM1>,
     eth_dma: EthDMA,
-    eth_mac: EthernetMAC,
+    phy: LAN8720A<Smi<EthernetMAC>>,
+    link_monitor: LinkMonitor,
     iface: Interface,
     flash_tcp_socket_handle: SocketHandle,
     control_server_udp_socket_handle: SocketHandle,
     control_server_tcp_socket_handle: SocketHandle,
+    icmp_socket_handle: SocketHandle,
     socket_set: SocketSet<'static>,
-
-    latest_control_server_timestamp: Option<smoltcp::time::Instant>,
+    dhcp_socket_handle: Option<SocketHandle>,
+    /// Unspecified while waiting for a DHCP lease.
+    ip_address: Ipv4Address,
+    static_address: Ipv4Cidr,
+    static_gateway: Option<Ipv4Address>,
+    unconfigured_since: smoltcp::time::Instant,
+    network_status: NetworkStatus,
+    control_server_udp_endpoint: IpEndpoint,
+    /// Status reports are sent as they become due, firmware reporting at this interval.
+    reporting_period: smoltcp::time::Duration,
+    control_server_frames: CommandFrames<CONTROL_SERVER_FRAME_BUFFER_SIZE>,
+    control_server_frame_errors: u32,
+    status_reporter: StatusReporter,
+    latest_reporting_timestamp: Option<smoltcp::time::Instant>,
+    control_server_probe: ServerProbe,
+    control_server_connection: ConnectionMonitor,
     is_connected_to_control_server: bool,
 }
 
@@ -83,6 +109,9 @@ impl Ethernet {
         timer: Timer<TIM1>,
         clocks: &CoreClocks,
         storage: &'static mut NetStorage,
+        mac_address: EthernetAddress,
+        network: &NetworkSettings,
+        tunables: &Tunables,
     ) -> Self {
         rtt_debug!("Initializing Ethernet ...");
 
@@ -93,21 +122,33 @@ impl Ethernet {
                 eth_dma,
                 pins,
                 &mut DES_RING,
-                MAC_ADDRESS,
+                mac_address,
                 prec,
                 clocks,
             )
         };
 
-        let mut phy = LAN8720A::new(eth_mac);
+        let mut delay = DelayFromCountDownTimer::new(timer);
+
+        let mut phy = LAN8720A::new(Smi(eth_mac));
         phy.phy_reset();
+        let mut phy_diagnostics = PhyDiagnostics::default();
+        match phy.identify() {
+            Ok(id) => phy_diagnostics.id = Some(id),
+            Err(_err) => rtt_warn!("ETH PHY is not a LAN8720A"),
+        }
+        // The MAC starts at 100 Mbit/s full duplex, as the PHY in loopback
+        match phy.loopback_self_test(mac_address.0, |frame, received| {
+            loopback_exchange(&mut eth_dma, &mut delay, frame, received)
+        }) {
+            Ok(()) => phy_diagnostics.loopback_passed = true,
+            Err(_err) => rtt_warn!("ETH PHY loopback self-test failed"),
+        }
         phy.phy_init();
 
         let mut loop_time = smoltcp::time::Instant::from_millis(0);
 
-        let mut delay = DelayFromCountDownTimer::new(timer);
-
-        // TEST: wait for linkup on Ethernet
+        // Gives DHCP a link to start with, the link is followed by `monitor_link` from then on
         loop {
             if phy.link_established() {
                 rtt_debug!("ETH Link Established");
@@ -122,20 +163,17 @@ impl Ethernet {
             }
         }
 
-        let config = smoltcp::iface::Config::new(MAC_ADDRESS.into());
+        let ip_address = Ipv4Address::UNSPECIFIED;
+        let config = smoltcp::iface::Config::new(mac_address.into());
 
         let timestamp = smoltcp::time::Instant::from_millis(Systick::now().ticks() as i64);
-        let mut iface = smoltcp::iface::Interface::new(config, &mut eth_dma, timestamp);
-        iface.update_ip_addrs(|ip_addresses| {
-            if let Err(_err) = ip_addresses.push(IpCidr::Ipv4(Ipv4Cidr::new(IP_ADDRESS, 24))) {
-                rtt_debug!("failed to push ip address");
-            } else {
-                // rtt_debug!("pushed back ip address");
-            }
-            // i.e. mask 255.255.255.0
-        });
+        let iface = smoltcp::iface::Interface::new(config, &mut eth_dma, timestamp);
 
         let mut socket_set = SocketSet::new(&mut storage.sockets[..]);
+        // With DHCP the address is set once a lease is obtained or none came in time, see `poll_dhcp`
+        let dhcp_socket_handle = network
+            .dhcp
+            .then(|| socket_set.add(dhcpv4::Socket::new()));
 
         let rx_buffer =
             tcp::SocketBuffer::new(&mut storage.flash_tcp_socket_storage.rx_storage[..]);
@@ -144,7 +182,7 @@ impl Ethernet {
         let mut flash_tcp_socket: tcp::Socket<'_> = tcp::Socket::new(rx_buffer, tx_buffer);
         if flash_tcp_socket.local_endpoint().is_some() {
             rtt_debug!("Binding TCP socket...");
-            let local_endpoint = IpEndpoint::new(IP_ADDRESS.into(), FLASH_TCP_IP_LISTENING_PORT);
+            let local_endpoint = listen_endpoint(ip_address, FLASH_TCP_IP_LISTENING_PORT);
             match flash_tcp_socket.listen(local_endpoint) {
                 Ok(()) => {
                     rtt_debug!("TCP socket listening");
@@ -168,9 +206,8 @@ impl Ethernet {
         }
     
         let stack_end = stack_start - stack_size; // Calculate the bottom of the stack
-        let mut failed = false;
         if sp < stack_end {
-            failed = true;
+            rtt_warn!("The stack overflowed");
         }
         let flash_tcp_socket_handle = socket_set.add(flash_tcp_socket);
 
@@ -193,8 +230,7 @@ impl Ethernet {
         let mut control_center_tcp_socket: tcp::Socket<'_> = tcp::Socket::new(rx_buffer, tx_buffer);
         if control_center_tcp_socket.local_endpoint().is_some() {
             rtt_debug!("Binding TCP socket...");
-            let local_endpoint =
-                IpEndpoint::new(IP_ADDRESS.into(), CONTROL_SERVER_TCP_LISTENING_PORT);
+            let local_endpoint = listen_endpoint(ip_address, CONTROL_SERVER_TCP_LISTENING_PORT);
             match control_center_tcp_socket.listen(local_endpoint) {
                 Ok(()) => {
                     rtt_debug!("TCP socket listening");
@@ -206,20 +242,157 @@ impl Ethernet {
         }
         let control_server_tcp_socket_handle = socket_set.add(control_center_tcp_socket);
 
+        let rx_buffer = icmp::PacketBuffer::new(
+            &mut storage.icmp_socket_storage.rx_metadata[..],
+            &mut storage.icmp_socket_storage.rx_storage[..],
+        );
+        let tx_buffer = icmp::PacketBuffer::new(
+            &mut storage.icmp_socket_storage.tx_metadata[..],
+            &mut storage.icmp_socket_storage.tx_storage[..],
+        );
+        let mut icmp_socket = icmp::Socket::new(rx_buffer, tx_buffer);
+        if let Err(_e) = icmp_socket.bind(icmp::Endpoint::Ident(PING_IDENT)) {
+            rtt_debug!("ICMP socket bind error");
+        }
+        let icmp_socket_handle = socket_set.add(icmp_socket);
+
         rtt_debug!("Ethernet initialized");
 
-        Self {
+        let mut ethernet = Self {
             timer: delay.free(),
             eth_dma,
-            eth_mac: phy.free(),
+            phy,
+            link_monitor: LinkMonitor::default(),
             iface,
             socket_set,
+            dhcp_socket_handle,
+            ip_address,
+            static_address: Ipv4Cidr::new(Ipv4Address(network.ip_address), network.prefix_length),
+            static_gateway: network.gateway.map(Ipv4Address),
+            unconfigured_since: timestamp,
+            network_status: NetworkStatus {
+                mac_address: mac_address.0,
+                phy: phy_diagnostics,
+                ..Default::default()
+            },
+            control_server_udp_endpoint: IpEndpoint::new(
+                Ipv4Address(network.control_server).into(),
+                network.control_server_port,
+            ),
+            reporting_period: smoltcp::time::Duration::from_millis(
+                tunables.reporting_period_ms.into(),
+            ),
             flash_tcp_socket_handle,
             control_server_udp_socket_handle,
             control_server_tcp_socket_handle,
-            latest_control_server_timestamp: None,
+            icmp_socket_handle,
+            control_server_frames: CommandFrames::default(),
+            control_server_frame_errors: 0,
+            status_reporter: StatusReporter::default(),
+            latest_reporting_timestamp: None,
+            control_server_probe: ServerProbe::new(PING_INTERVAL, PING_TIMEOUT),
+            control_server_connection: ConnectionMonitor::new(Timestamp::new(
+                tunables.control_server_timeout_ms.into(),
+            )),
             is_connected_to_control_server: false,
+        };
+        if ethernet.dhcp_socket_handle.is_none() {
+            ethernet.use_static_address();
         }
+        ethernet
+    }
+
+    /// Switches to a new DHCP lease, or to the static address when none was obtained in time.
+    fn poll_dhcp(&mut self, timestamp: smoltcp::time::Instant) {
+        let Some(handle) = self.dhcp_socket_handle else {
+            return;
+        };
+        let event = self
+            .socket_set
+            .get_mut::<dhcpv4::Socket>(handle)
+            .poll()
+            .map(|event| match event {
+                dhcpv4::Event::Configured(config) => {
+                    Some((config.address, config.router, config.server.address))
+                }
+                dhcpv4::Event::Deconfigured => None,
+            });
+
+        match event {
+            Some(Some((cidr, router, server))) => {
+                rtt_debug!("DHCP lease obtained");
+                let source = AddressSource::Dhcp {
+                    server: server.0,
+                    leased_at: Timestamp::new(timestamp.total_millis() as u64),
+                };
+                self.set_address(Some(cidr), router, source);
+            }
+            Some(None) => {
+                rtt_warn!("DHCP lease lost");
+                self.unconfigured_since = timestamp;
+                self.set_address(None, None, AddressSource::Unconfigured);
+            }
+            None => {
+                if self.network_status.source == AddressSource::Unconfigured
+                    && self.unconfigured_since + DHCP_TIMEOUT <= timestamp
+                {
+                    rtt_warn!("No DHCP lease, falling back to the static address");
+                    self.use_static_address();
+                }
+            }
+        }
+    }
+
+    fn use_static_address(&mut self) {
+        self.set_address(
+            Some(self.static_address),
+            self.static_gateway,
+            AddressSource::Static,
+        );
+    }
+
+    /// Moves the interface to another address. Sockets bound to the previous one are closed, they
+    /// listen and bind again on the next synchronization.
+    fn set_address(
+        &mut self,
+        cidr: Option<Ipv4Cidr>,
+        gateway: Option<Ipv4Address>,
+        source: AddressSource,
+    ) {
+        self.iface.update_ip_addrs(|ip_addresses| {
+            ip_addresses.clear();
+            if let Some(cidr) = cidr {
+                if let Err(_err) = ip_addresses.push(IpCidr::Ipv4(cidr)) {
+                    rtt_debug!("failed to push ip address");
+                }
+            }
+        });
+        self.iface.routes_mut().remove_default_ipv4_route();
+        if let Some(gateway) = gateway {
+            if let Err(_err) = self.iface.routes_mut().add_default_ipv4_route(gateway) {
+                rtt_debug!("failed to add the default route");
+            }
+        }
+
+        let ip_address = cidr.map_or(Ipv4Address::UNSPECIFIED, |cidr| cidr.address());
+        if ip_address != self.ip_address {
+            self.ip_address = ip_address;
+            for handle in [self.flash_tcp_socket_handle, self.control_server_tcp_socket_handle] {
+                self.socket_set.get_mut::<tcp::Socket>(handle).abort();
+            }
+            self.socket_set
+                .get_mut::<udp::Socket>(self.control_server_udp_socket_handle)
+                .close();
+            self.control_server_probe.reset();
+        }
+        self.network_status = NetworkStatus {
+            mac_address: self.network_status.mac_address,
+            ip_address: ip_address.0,
+            prefix_length: cidr.map_or(0, |cidr| cidr.prefix_len()),
+            gateway: gateway.map(|gateway| gateway.0),
+            source,
+            phy: self.network_status.phy,
+        };
     }
 
     pub fn loop_update(&mut self) {
@@ -233,60 +406,164 @@ impl Ethernet {
         }
     }
 
+    pub fn link(&self) -> &LinkStatus {
+        self.link_monitor.status()
+    }
+
+    /// Follows the link of the PHY, configuring the MAC for the mode negotiated whenever it comes
+    /// up. Autonegotiation is restarted while the link is down, see `LinkMonitor`.
+    fn monitor_link(&mut self, now: Timestamp) {
+        match self.link_monitor.poll(now, &mut self.phy) {
+            Some(LinkEvent::Up(mode)) => {
+                rtt_debug!("ETH Link up");
+                configure_mac(mode);
+            }
+            Some(LinkEvent::Down) => rtt_warn!("ETH Link lost"),
+            None => {}
+        }
+    }
+
     pub fn is_connected_to_control_server(&self) -> bool {
         self.is_connected_to_control_server
     }
 
+    /// Refreshes `card_status` from the traffic of the control server and the pings to it.
+    pub fn update_card_status(&self, card_status: &mut Wingman2IOCardStatus) {
+        card_status.update(
+            Timestamp::new(Systick::now().ticks()),
+            &self.control_server_connection,
+            &self.control_server_probe.statistics(),
+        );
+    }
+
+    /// Takes the echo replies of the control server and sends the next echo request when due.
+    fn probe_control_server(&mut self, timestamp: smoltcp::time::Instant) {
+        let now = Timestamp::new(timestamp.total_millis() as u64);
+        let server = self.control_server_udp_endpoint.addr;
+        let icmp_socket = self.socket_set.get_mut::<icmp::Socket>(self.icmp_socket_handle);
+
+        while let Ok((payload, source)) = icmp_socket.recv() {
+            let Ok(packet) = Icmpv4Packet::new_checked(payload) else {
+                continue;
+            };
+            if let Ok(Icmpv4Repr::EchoReply { ident: PING_IDENT, seq_no, .. }) =
+                Icmpv4Repr::parse(&packet, &ChecksumCapabilities::default())
+            {
+                if source == server {
+                    self.control_server_probe.reply(seq_no, now);
+                }
+            }
+        }
+
+        // Nothing to measure before the card has an address
+        if self.ip_address.is_unspecified() || !icmp_socket.can_send() {
+            return;
+        }
+        let Some(seq_no) = self.control_server_probe.poll(now) else {
+            return;
+        };
+        let request = Icmpv4Repr::EchoRequest {
+            ident: PING_IDENT,
+            seq_no,
+            data: PING_PAYLOAD,
+        };
+        match icmp_socket.send(request.buffer_len(), server) {
+            Ok(buf) => {
+                let mut packet = Icmpv4Packet::new_unchecked(buf);
+                request.emit(&mut packet, &ChecksumCapabilities::default());
+            }
+            Err(_err) => rtt_debug!("Error sending echo request to control server"),
+        }
+    }
+
+    /// Number of control server frames that could not be decoded since boot.
+    pub fn control_server_frame_errors(&self) -> u32 {
+        self.control_server_frame_errors
+    }
+
+    /// Returns true when `reporting` was sent to a connected control server.
     pub fn synchronize_control_server_socket(
         &mut self,
         user_commands: &mut UserCommands,
+        recorder: &mut Recorder,
         hardware_status: &Wingman2HardwareStatus,
         reporting: &FirmwareReporting,
-    ) {
+        logic: &mut FirmwareLogic,
+    ) -> bool {
         let timestamp = smoltcp::time::Instant::from_millis(Systick::now().ticks() as i64);
+        let now = Timestamp::new(timestamp.total_millis() as u64);
 
-        self.is_connected_to_control_server = self
-            .latest_control_server_timestamp
-            .map(|latest| latest.add(smoltcp::time::Duration::from_millis(5_000)) > timestamp)
-            .unwrap_or(false);
+        let was_connected = self.is_connected_to_control_server;
+        self.is_connected_to_control_server = self.control_server_connection.is_connected(now);
+        if self.is_connected_to_control_server && !was_connected {
+            // The control server may have missed any number of deltas, start it from a snapshot
+            self.status_reporter.request_snapshot();
+        }
+        self.monitor_link(now);
 
         self.iface
             .poll(timestamp, &mut self.eth_dma, &mut self.socket_set);
+        self.poll_dhcp(timestamp);
+        self.probe_control_server(timestamp);
 
         let tcp_socket = self
             .socket_set
             .get_mut::<tcp::Socket>(self.control_server_tcp_socket_handle);
 
         if !tcp_socket.is_open() {
-            tcp_socket.listen(CONTROL_SERVER_TCP_ENDPOINT).unwrap();
+            tcp_socket
+                .listen(listen_endpoint(self.ip_address, CONTROL_SERVER_TCP_LISTENING_PORT))
+                .unwrap();
+            // Drop any partial frame left over from the previous connection
+            self.control_server_frames.reset();
         }
         if !tcp_socket.may_recv() && tcp_socket.may_send() {
             tcp_socket.close();
         }
 
-        if tcp_socket.can_recv() {
+        // Commands are COBS framed, a single read may hold several frames or only part of one.
+        while tcp_socket.can_recv() {
             let mut buf = [0u8; 1024];
-            match tcp_socket.recv_slice(&mut buf) {
-                Ok(size) => {
-                    match postcard::from_bytes::<UserCommand>(&buf[..size])
-                    {
-                        Ok(mut command_received) => {
-                            let timestamp = Timestamp::new(Systick::now().ticks());
-                            command_received.set_timestamp(timestamp);
-                        }
-                        Err(_err) => {
-                            rtt_debug!("Error receiving from control server");
-                            // TODO: SHOW ERROR ON DISPLAY
-                        } // self.latest_control_server_timestamp = Some(timestamp);
-                          // card_response.update_control_center_qos();
-
-                          // card_status.update_from(&card_response);
-                    }
+            let size = match tcp_socket.recv_slice(&mut buf) {
+                Ok(size) => size,
+                Err(_err) => {
+                    rtt_debug!("Error receiving from control server {}", _err);
+                    break;
                 }
-                Err(err) => {
-                    rtt_debug!("Error receiving from control server {}", err);
-                }
-            }
+            };
+
+            self.control_server_frames.feed(&buf[..size], |frame| {
+                let mut command_received = match frame {
+                    Ok(command_received) => command_received,
+                    Err(FrameError::TooLong) => {
+                        rtt_warn!("Control server frame too long, dropped");
+                        self.control_server_frame_errors += 1;
+                        send_command_response(
+                            tcp_socket,
+                            &CommandResponse::Nack(None, NackReason::DecodeError),
+                        );
+                        return;
+                    }
+                    Err(FrameError::Undecodable) => {
+                        rtt_warn!("Error decoding frame from control server");
+                        self.control_server_frame_errors += 1;
+                        send_command_response(
+                            tcp_socket,
+                            &CommandResponse::Nack(None, NackReason::DecodeError),
+                        );
+                        // TODO: SHOW ERROR ON DISPLAY
+                        return;
+                    }
+                };
+
+                let timestamp = Timestamp::new(Systick::now().ticks());
+                command_received.set_timestamp(timestamp);
+                self.control_server_connection.command(timestamp);
+
+                let received = logic.receive_command(command_received, user_commands, recorder, hardware_status);
+                let response = CommandResponse::for_command(command_received.command, received);
+                send_command_response(tcp_socket, &response);
+            });
         }
 
         let udp_socket = self
@@ -296,49 +573,190 @@ impl Ethernet {
         if !udp_socket.endpoint().is_specified() {
             rtt_debug!("Binding UDP socket...");
             let local_endpoint =
-                IpEndpoint::new(IP_ADDRESS.into(), CONTROL_SERVER_UDP_LISTENING_PORT);
+                listen_endpoint(self.ip_address, CONTROL_SERVER_UDP_LISTENING_PORT);
             match udp_socket.bind(local_endpoint) {
                 Ok(()) => rtt_debug!("UDP socket bound."),
                 Err(_e) => rtt_debug!("UDP socket bind error"),
             };
         }
         
+        let mut journal_request = None;
         if udp_socket.can_recv() {
             if let Ok((data, _)) = udp_socket.recv() {
-                let _server_reporting = postcard::from_bytes::<ServerReporting>(&data).unwrap();
+                if let Some(server_reporting) = ServerReporting::from_datagram(data) {
+                    // The control server sends its reporting as a heartbeat
+                    self.control_server_connection.heartbeat(now);
+                    journal_request = server_reporting.journal_request;
+                    recorder.set_enabled(server_reporting.record);
+                } else {
+                    rtt_warn!("Error decoding reporting from control server");
+                }
             }
         }
 
+        let mut reported = false;
         if udp_socket.can_send() {
-            let mut buf = [0u8; 1024];
-            let buf = postcard::to_slice(hardware_status, &mut buf).unwrap();
-            send_udp_slice(udp_socket, buf, CONTROL_SERVER_UDP_ENDPOINT);
+            let endpoint = self.control_server_udp_endpoint;
+            if let Some(sequence) = journal_request {
+                let page = logic.journal().page_from(sequence);
+                send_udp_datagram(udp_socket, endpoint, &FirmwareDatagram::Journal(page));
+            }
+            // Cycles left in the recorder are sent on the next call, see `Recorder::dropped_cycles`
+            while udp_socket.can_send() {
+                let Some(cycle) = recorder.pop() else {
+                    break;
+                };
+                send_udp_datagram(udp_socket, endpoint, &FirmwareDatagram::Recording(cycle));
+            }
 
+            if let Some(report) = self.status_reporter.report(hardware_status) {
+                send_udp_datagram(udp_socket, endpoint, &FirmwareDatagram::Status(report));
+            }
+
+            let reporting_due = self
+                .latest_reporting_timestamp
+                .map(|latest| latest.add(self.reporting_period) <= timestamp)
+                .unwrap_or(true);
+            if reporting_due {
+                self.latest_reporting_timestamp = Some(timestamp);
+                self.phy.update_diagnostics(&mut self.network_status.phy);
+                let reporting = FirmwareReporting {
+                    network: self.network_status.clone(),
+                    control_server_pings: self.control_server_probe.statistics(),
+                    ..reporting.clone()
+                };
+                send_udp_datagram(udp_socket, endpoint, &FirmwareDatagram::Reporting(reporting));
+                reported = self.is_connected_to_control_server;
+            }
+        }
+        reported
+    }
+
+    /// Services firmware updates, call after `synchronize_control_server_socket` which polls the
+    /// interface. Erasing a sector of the slot may block for a second or two.
+    pub fn synchronize_flash_socket(&mut self, firmware_update: &mut FirmwareUpdate) {
+        let tcp_socket = self
+            .socket_set
+            .get_mut::<tcp::Socket>(self.flash_tcp_socket_handle);
+
+        if !tcp_socket.is_open() {
+            tcp_socket
+                .listen(listen_endpoint(self.ip_address, FLASH_TCP_IP_LISTENING_PORT))
+                .unwrap();
+            firmware_update.reset();
+        }
+        if !tcp_socket.may_recv() && tcp_socket.may_send() {
+            tcp_socket.close();
+        }
+
+        while tcp_socket.can_recv() {
             let mut buf = [0u8; 1024];
-            let buf = postcard::to_slice(reporting, &mut buf).unwrap();
-            send_udp_slice(udp_socket, buf, CONTROL_SERVER_UDP_ENDPOINT);
+            let size = match tcp_socket.recv_slice(&mut buf) {
+                Ok(size) => size,
+                Err(_err) => {
+                    rtt_debug!("Error receiving firmware update {}", _err);
+                    break;
+                }
+            };
+            firmware_update.feed(&buf[..size], |response| {
+                send_update_response(tcp_socket, response)
+            });
         }
     }
 }
 
+/// Sends `frame` and waits a few milliseconds for a frame to come back, see
+/// `LAN8720A::loopback_self_test`. Returns the length of the frame received.
+fn loopback_exchange(
+    eth_dma: &mut EthDMA,
+    delay: &mut DelayFromCountDownTimer<Timer<TIM1>>,
+    frame: &[u8],
+    received: &mut [u8],
+) -> Option<usize> {
+    let timestamp = smoltcp::time::Instant::from_millis(Systick::now().ticks() as i64);
+    eth_dma
+        .transmit(timestamp)?
+        .consume(frame.len(), |buffer| buffer.copy_from_slice(frame));
+    for _ in 0..LOOPBACK_TIMEOUT_MS {
+        if let Some((rx_token, _tx_token)) = eth_dma.receive(timestamp) {
+            return Some(rx_token.consume(|buffer| {
+                let length = buffer.len().min(received.len());
+                received[..length].copy_from_slice(&buffer[..length]);
+                buffer.len()
+            }));
+        }
+        delay.delay_ms(1u16);
+    }
+    None
+}
+
+/// Sets the speed and duplex mode of the MAC, `ethernet::new` configures 100 Mbit/s full duplex.
+fn configure_mac(mode: LinkMode) {
+    // Only the network task touches the MAC once it was split off the DMA
+    let mac = unsafe { &*ETHERNET_MAC::ptr() };
+    mac.maccr.modify(|_, w| {
+        w.fes()
+            .bit(mode.speed == LinkSpeed::Mbps100)
+            .dm()
+            .bit(mode.full_duplex)
+    });
+}
+
+/// Listens on `ip_address` once the card has one, on any address until then.
+fn listen_endpoint(ip_address: Ipv4Address, port: u16) -> IpListenEndpoint {
+    IpListenEndpoint {
+        addr: (!ip_address.is_unspecified()).then(|| ip_address.into()),
+        port,
+    }
+}
+
+fn send_command_response(socket: &mut tcp::Socket, response: &CommandResponse) {
+    let mut buf = [0u8; CommandResponse::MAX_FRAME_SIZE];
+    match postcard::to_slice_cobs(response, &mut buf) {
+        Ok(buf) => send_tcp_slice(socket, buf),
+        Err(_err) => rtt_warn!("Command response does not fit, dropped"),
+    }
+}
+
+fn send_update_response(socket: &mut tcp::Socket, response: &UpdateResponse) {
+    let mut buf = [0u8; 128];
+    match postcard::to_slice_cobs(response, &mut buf) {
+        Ok(buf) => send_tcp_slice(socket, buf),
+        Err(_err) => rtt_debug!("Error encoding firmware update response"),
+    }
+}
+
+/// Queues a whole COBS frame or none of it, a frame cut short would garble the ones after it.
 fn send_tcp_slice(socket: &mut tcp::Socket, buf: &[u8]) {
+    if socket.send_capacity() - socket.send_queue() < buf.len() {
+        rtt_warn!("TCP send buffer full, frame dropped");
+        return;
+    }
     match socket.send_slice(buf) {
         Ok(_) => {
-            rtt_debug!("Sent status to control server");
+            rtt_debug!("Sent frame on TCP socket");
         }
-        Err(err) => {
-            rtt_debug!("Error sending value to control server {}", err);
+        Err(_err) => {
+            rtt_debug!("Error sending frame on TCP socket {}", _err);
         }
     }
 }
 
+fn send_udp_datagram(socket: &mut udp::Socket, endpoint: IpEndpoint, datagram: &FirmwareDatagram) {
+    let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+    match postcard::to_slice(datagram, &mut buf) {
+        Ok(buf) => send_udp_slice(socket, buf, endpoint),
+        Err(_err) => rtt_warn!("Datagram to control server does not fit, dropped"),
+    }
+}
+
 fn send_udp_slice(socket: &mut udp::Socket, buf: &[u8], endpoint: IpEndpoint) {
     match socket.send_slice(buf, endpoint) {
         Ok(_) => {
             rtt_debug!("Sent status to control server");
         }
-        Err(err) => {
-            rtt_debug!("Error sending value to control server {}", err);
+        Err(_err) => {
+            rtt_debug!("Error sending value to control server {}", _err);
         }
     }
 }
@@ -0,0 +1,199 @@
+use firmware_logic::data::{
+    boot::{BootAttempts, BootConfirmation, BootRecord, BootRecords, Slot, BOOT_RECORD_OFFSET, SLOT_SIZE},
+    frames::{FrameError, Frames},
+    settings::SettingsStore,
+    update::{UpdateError, UpdateRequest, UpdateResponse, Updater, UPDATE_FRAME_SIZE},
+};
+use firmware_logic::{BootStatus, Configuration, Timestamp};
+use stm32h7xx_hal::flash::LockedFlashBank;
+
+use crate::data::boot_attempts::clear_rollback_report;
+
+include!(concat!(env!("OUT_DIR"), "/update_key.rs"));
+
+/// Writes a firmware image received on the flash TCP socket to the slot that is not running, and
+/// activates it in the boot record once verified. The new image runs from the next reset, as do
+/// settings saved on the same socket.
+///
+/// Images must be signed with the key matching `UPDATE_PUBLIC_KEY`, see `build.rs`, and built for
+/// the configuration of the card. A new image confirms itself in the boot record once it ran
+/// healthy, see `BootConfirmation`, or the bootloader rolls back to the previous one.
+pub struct FirmwareUpdate {
+    /// Holds the boot record and the first slot.
+    bank1: LockedFlashBank,
+    /// Holds the second slot and the settings.
+    bank2: LockedFlashBank,
+    settings_store: SettingsStore,
+    /// `None` when the running image was not linked for a slot, updates are refused then.
+    running: Option<Slot>,
+    updater: Updater,
+    boot_records: BootRecords,
+    confirmation: BootConfirmation,
+    boot_status: BootStatus,
+    /// The rollback report is left in backup SRAM until the control server received it.
+    rollback_unreported: bool,
+    frames: Frames<UpdateRequest, UPDATE_FRAME_SIZE>,
+}
+
+impl FirmwareUpdate {
+    /// `attempts` are the boots counted by the bootloader, see `read_boot_attempts`.
+    pub fn new(
+        mut bank1: LockedFlashBank,
+        bank2: LockedFlashBank,
+        settings_store: SettingsStore,
+        configuration: Configuration,
+        attempts: BootAttempts,
+    ) -> Self {
+        // The vector table is at the start of the running image
+        let vector_table = unsafe { (*cortex_m::peripheral::SCB::PTR).vtor.read() };
+        let running = Slot::containing(vector_table);
+        let target = running.map_or(Slot::Second, Slot::other);
+
+        let mut boot_records = BootRecords::new(BOOT_RECORD_OFFSET);
+        let record = boot_records.load(&mut bank1.unlocked()).unwrap_or_else(|_err| {
+            rtt_warn!("Error reading the boot record");
+            None
+        });
+        let pending = running.is_some()
+            && record.is_some_and(|record| Some(record.active) == running && !record.confirmed);
+        if attempts.rolled_back_from.is_some() {
+            rtt_warn!("The bootloader rolled back from an image that failed to confirm itself");
+        }
+        let boot_status = BootStatus {
+            running,
+            pending_confirmation: pending,
+            attempts: if attempts.slot == running { attempts.count } else { 0 },
+            rolled_back_from: attempts.rolled_back_from,
+        };
+
+        Self {
+            bank1,
+            bank2,
+            settings_store,
+            running,
+            updater: Updater::new(target.bank_offset(), SLOT_SIZE, configuration, UPDATE_PUBLIC_KEY),
+            boot_records,
+            confirmation: BootConfirmation::new(pending),
+            rollback_unreported: boot_status.rolled_back_from.is_some(),
+            boot_status,
+            frames: Frames::default(),
+        }
+    }
+
+    pub fn boot_status(&self) -> &BootStatus {
+        &self.boot_status
+    }
+
+    /// Takes the rollback report of the bootloader once the boot status reached the control server.
+    pub fn boot_status_reported(&mut self) {
+        if self.rollback_unreported {
+            clear_rollback_report();
+            self.rollback_unreported = false;
+        }
+    }
+
+    /// Confirms the running image in the boot record once it ran healthy, call periodically.
+    pub fn confirm_when_healthy(&mut self, now: Timestamp, is_connected_to_control_server: bool) {
+        let Some(running) = self.running else {
+            return;
+        };
+        if !self.confirmation.update(now, is_connected_to_control_server) {
+            return;
+        }
+        let record = BootRecord { active: running, confirmed: true };
+        match self.boot_records.save(&mut self.bank1.unlocked(), &record) {
+            Ok(()) => {
+                rtt_debug!("Firmware confirmed, the bootloader keeps booting it");
+                self.boot_status.pending_confirmation = false;
+            }
+            Err(_err) => {
+                // Tried again after another `CONFIRM_AFTER`
+                rtt_warn!("Error writing the boot record");
+                self.confirmation = BootConfirmation::new(true);
+            }
+        }
+    }
+
+    /// Drops any partial frame, e.g. when the connection was closed. An update in progress is
+    /// dropped by the next `Begin`.
+    pub fn reset(&mut self) {
+        self.frames.reset();
+    }
+
+    /// Feeds the bytes received, `respond` is called with the responses to every request they
+    /// complete.
+    pub fn feed(&mut self, bytes: &[u8], mut respond: impl FnMut(&UpdateResponse)) {
+        let Self {
+            bank1,
+            bank2,
+            settings_store,
+            running,
+            updater,
+            boot_records,
+            confirmation,
+            frames,
+            ..
+        } = self;
+
+        frames.feed(bytes, |frame| {
+            let request = match frame {
+                Ok(request) => request,
+                Err(FrameError::TooLong | FrameError::Undecodable) => {
+                    rtt_warn!("Error decoding firmware update request");
+                    respond(&UpdateResponse::Failed(UpdateError::Undecodable));
+                    return;
+                }
+            };
+            // Saved whether or not the running image is in a slot
+            if let UpdateRequest::SaveSettings(settings) = &request {
+                let response = match settings_store.save(&mut bank2.unlocked(), settings) {
+                    Ok(()) => UpdateResponse::SettingsSaved,
+                    Err(_err) => {
+                        rtt_warn!("Error saving the settings");
+                        UpdateResponse::Failed(UpdateError::Settings)
+                    }
+                };
+                respond(&response);
+                return;
+            }
+            let Some(running) = *running else {
+                respond(&UpdateResponse::Failed(UpdateError::NotInSlot));
+                return;
+            };
+
+            let target = running.other();
+            let bank = match target {
+                Slot::First => &mut *bank1,
+                Slot::Second => &mut *bank2,
+            };
+            let response = updater.handle(&mut bank.unlocked(), &request);
+            respond(&response);
+
+            if let UpdateResponse::Verified(_header) = response {
+                let response = match activate(bank1, boot_records, target) {
+                    Ok(()) => {
+                        rtt_debug!("Firmware {} verified, booting it on the next reset", _header.version.as_str());
+                        // Confirming the running image now would undo the activation
+                        *confirmation = BootConfirmation::new(false);
+                        UpdateResponse::Activated
+                    }
+                    Err(()) => {
+                        rtt_warn!("Error writing the boot record");
+                        UpdateResponse::Failed(UpdateError::Activation)
+                    }
+                };
+                respond(&response);
+            }
+        });
+    }
+}
+
+/// Appends a boot record selecting `target`, unconfirmed until its image confirms itself.
+fn activate(
+    bank1: &mut LockedFlashBank,
+    boot_records: &mut BootRecords,
+    target: Slot,
+) -> Result<(), ()> {
+    let record = BootRecord { active: target, confirmed: false };
+    boot_records.save(&mut bank1.unlocked(), &record).map_err(|_| ())
+}
@@ -1,2 +1,3 @@
 pub mod ethernet;
+pub mod firmware_update;
 pub mod net_storage;
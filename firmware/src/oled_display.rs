@@ -6,6 +6,7 @@ use embedded_graphics::prelude::*;
 use embedded_graphics::primitives::{Line, PrimitiveStyle};
 use embedded_graphics::text::{Alignment, Text};
 use embedded_hal::blocking::delay::DelayMs;
+use firmware_logic::data::link::{LinkMode, LinkSpeed};
 use firmware_logic::Timestamp;
 use rtic_monotonics::stm32::fugit::{self, RateExtU32};
 use ssd1351::builder::Builder;
@@ -31,6 +32,9 @@ pub struct OledDisplay {
     step: u64,
     duration: fugit::Duration<u64, 1, 1_000>,
     connected_to_control_server: bool,
+    control_server_qos: u8,
+    link: Option<LinkMode>,
+    mac_address: [u8; 6],
 }
 
 static mut DISPLAY_BUFFER: [u8; 128 * 128 * 2] = [0; 128 * 128 * 2];
@@ -92,6 +96,9 @@ impl OledDisplay {
             graphic_display: display,
             step: 0,
             connected_to_control_server: false,
+            control_server_qos: 0,
+            link: None,
+            mac_address: [0; 6],
             duration: fugit::Duration::<u64, 1, 1000>::from_ticks(0),
         };
 
@@ -117,6 +124,9 @@ impl OledDisplay {
         let steps = self.step;
         let duration = self.duration;
         let network_status = self.connected_to_control_server;
+        let control_server_qos = self.control_server_qos;
+        let link = self.link;
+        let mac_address = self.mac_address;
         self.display(|display| {
             display.clear(false);
 
@@ -124,6 +134,9 @@ impl OledDisplay {
             Self::display_header(display);
             Self::display_counter(steps, display, character_style);
             Self::display_network_status(network_status, display);
+            Self::display_control_server_qos(control_server_qos, display, character_style);
+            Self::display_link(link, display, character_style);
+            Self::display_mac_address(mac_address, display, character_style);
             Self::display_human_readable_time(display, character_style, duration)
         });
     }
@@ -132,6 +145,20 @@ impl OledDisplay {
         self.connected_to_control_server = is_connected;
     }
 
+    /// See `Wingman2IOCardStatus::control_server_qos`.
+    pub fn set_control_server_qos(&mut self, qos: u8) {
+        self.control_server_qos = qos;
+    }
+
+    /// `None` while the Ethernet link is down.
+    pub fn set_link(&mut self, link: Option<LinkMode>) {
+        self.link = link;
+    }
+
+    pub fn set_mac_address(&mut self, mac_address: [u8; 6]) {
+        self.mac_address = mac_address;
+    }
+
     pub fn set_timestamp(&mut self, timestamp: Timestamp) {
         self.duration = timestamp.into();
     }
@@ -188,6 +215,64 @@ impl OledDisplay {
         }
     }
 
+    fn display_control_server_qos(
+        qos: u8,
+        display: &mut GraphicsDisplay,
+        character_style: MonoTextStyle<Rgb565>,
+    ) {
+        let mut buf = [0u8; 16];
+        let qos_text = format_no_std::show(&mut buf, format_args!("QoS {qos}%")).unwrap();
+        Self::display_text(
+            display,
+            qos_text,
+            Point::new(50, 50),
+            Alignment::Left,
+            character_style,
+        );
+    }
+
+    fn display_link(
+        link: Option<LinkMode>,
+        display: &mut GraphicsDisplay,
+        character_style: MonoTextStyle<Rgb565>,
+    ) {
+        let link_text = match link {
+            None => "no link",
+            Some(LinkMode { speed: LinkSpeed::Mbps100, full_duplex: true }) => "100M full",
+            Some(LinkMode { speed: LinkSpeed::Mbps100, full_duplex: false }) => "100M half",
+            Some(LinkMode { speed: LinkSpeed::Mbps10, full_duplex: true }) => "10M full",
+            Some(LinkMode { speed: LinkSpeed::Mbps10, full_duplex: false }) => "10M half",
+        };
+        Self::display_text(
+            display,
+            link_text,
+            Point::new(50, 40),
+            Alignment::Left,
+            character_style,
+        );
+    }
+
+    fn display_mac_address(
+        mac_address: [u8; 6],
+        display: &mut GraphicsDisplay,
+        character_style: MonoTextStyle<Rgb565>,
+    ) {
+        let mut buf = [0u8; 32];
+        let [a, b, c, d, e, f] = mac_address;
+        let mac_text = format_no_std::show(
+            &mut buf,
+            format_args!("{a:02X}:{b:02X}:{c:02X}:{d:02X}:{e:02X}:{f:02X}"),
+        )
+        .unwrap();
+        Self::display_text(
+            display,
+            mac_text,
+            display.bounding_box().center() + Point::new(0, 30),
+            Alignment::Center,
+            character_style,
+        );
+    }
+
     fn display_human_readable_time(
         display: &mut GraphicsDisplay,
         character_style: MonoTextStyle<Rgb565>,
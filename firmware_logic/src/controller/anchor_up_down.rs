@@ -1,13 +1,50 @@
 use crate::data::user_commands::UserCommands;
-use crate::io::digital_output_impl::DigitalOutputImpl;
-use crate::{ControllerLogic, Wingman2HardwareStatus};
+use crate::io::digital_output_impl::{ApplyCommand, DigitalOutputImpl};
+use crate::{
+    BoardMap, Command, ControllerLogic, DigitalOutput, IoLevel, NackReason, SwitchCommand,
+    UserCommand, Wingman2HardwareStatus,
+};
 
+/// Drives the windlass. Up and down are interlocked, the windlass has to be stopped before it
+/// can be reversed.
 #[derive(Default, Clone)]
 pub struct AnchorUpDown {}
 
+impl AnchorUpDown {
+    /// Returns the output to drive and the opposite output it is interlocked with.
+    fn outputs(command: &Command, board_map: &BoardMap) -> Option<(DigitalOutput, DigitalOutput, SwitchCommand)> {
+        match command {
+            Command::AnchorUp(switch_command) => {
+                Some((board_map.anchor_up?, board_map.anchor_down?, *switch_command))
+            }
+            Command::AnchorDown(switch_command) => {
+                Some((board_map.anchor_down?, board_map.anchor_up?, *switch_command))
+            }
+            _ => None,
+        }
+    }
+
+    fn switches_on(
+        output: &DigitalOutput,
+        hardware_status: &Wingman2HardwareStatus,
+        switch_command: SwitchCommand,
+    ) -> bool {
+        match switch_command {
+            SwitchCommand::On => true,
+            SwitchCommand::Off => false,
+            SwitchCommand::Toggle => output.read(hardware_status) == Some(IoLevel::Low),
+        }
+    }
+}
+
 impl ControllerLogic for AnchorUpDown {
-    fn initialize(&mut self, _: &mut Wingman2HardwareStatus) {
-        // NoOp
+    fn initialize(&mut self, hardware_status: &mut Wingman2HardwareStatus) {
+        let Some(board_map) = BoardMap::for_configuration(hardware_status.configuration) else {
+            return;
+        };
+        for output in [board_map.anchor_up, board_map.anchor_down].iter().flatten() {
+            output.apply(hardware_status, SwitchCommand::Off);
+        }
     }
 
     fn apply_user_commands(
@@ -15,9 +52,47 @@ impl ControllerLogic for AnchorUpDown {
         user_commands: &mut UserCommands,
         hardware_status: &mut Wingman2HardwareStatus,
     ) {
+        let Some(board_map) = BoardMap::for_configuration(hardware_status.configuration) else {
+            return;
+        };
+
+        for user_command in user_commands.iter() {
+            let Some((output, opposite, switch_command)) =
+                Self::outputs(&user_command.command, board_map)
+            else {
+                continue;
+            };
+
+            // Never drive both directions at once, even if the command got past the check.
+            if Self::switches_on(&output, hardware_status, switch_command)
+                && opposite.read(hardware_status) == Some(IoLevel::High)
+            {
+                continue;
+            }
+            output.apply(hardware_status, switch_command);
+        }
     }
 
     fn update(&mut self, _: &mut Wingman2HardwareStatus) {
         // NoOp
     }
+
+    fn check_user_command(
+        &self,
+        user_command: &UserCommand,
+        hardware_status: &Wingman2HardwareStatus,
+    ) -> Result<(), NackReason> {
+        let Some(board_map) = BoardMap::for_configuration(hardware_status.configuration) else {
+            return Ok(());
+        };
+        match Self::outputs(&user_command.command, board_map) {
+            Some((output, opposite, switch_command))
+                if Self::switches_on(&output, hardware_status, switch_command)
+                    && opposite.read(hardware_status) == Some(IoLevel::High) =>
+            {
+                Err(NackReason::Interlocked)
+            }
+            _ => Ok(()),
+        }
+    }
 }
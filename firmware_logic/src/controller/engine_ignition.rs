@@ -1,13 +1,52 @@
 use crate::data::user_commands::UserCommands;
-use crate::io::digital_output_impl::DigitalOutputImpl;
-use crate::{ControllerLogic, Wingman2HardwareStatus};
+use crate::io::digital_input_impl::DigitalInputImpl;
+use crate::io::digital_output_impl::{ApplyCommand, DigitalOutputImpl};
+use crate::{
+    BoardMap, ButtonCommand, Command, ControllerLogic, DeviceIdentifier, IoLevel, NackReason,
+    Timestamp, UserCommand, Wingman2HardwareStatus, ENGINE_MAX_CRANK,
+};
 
-#[derive(Default)]
-pub struct EngineIgnition {}
+/// Switches the ignition and cranks the starter while the start button is held.
+///
+/// Cranking stops as soon as the engine runs or after `ENGINE_MAX_CRANK`, the button then has
+/// to be released before the engine can be cranked again.
+#[derive(Default, Clone)]
+pub struct EngineIgnition {
+    starters: [Starter; 2],
+}
+
+#[derive(Default, Clone, Copy)]
+struct Starter {
+    cranking_since: Option<Timestamp>,
+    locked_out: bool,
+}
+
+impl EngineIgnition {
+    fn addresses(identifier: DeviceIdentifier, index: usize) -> bool {
+        identifier == DeviceIdentifier::All || identifier == DeviceIdentifier::from_index(index)
+    }
+
+    fn is_running(index: usize, board_map: &BoardMap, hardware_status: &Wingman2HardwareStatus) -> bool {
+        board_map.engines_running[index]
+            .is_some_and(|running| running.read(hardware_status) == Some(IoLevel::High))
+    }
+
+    fn may_crank(index: usize, board_map: &BoardMap, hardware_status: &Wingman2HardwareStatus) -> bool {
+        let ignition_on = board_map.engine_ignitions[index]
+            .is_some_and(|ignition| ignition.read(hardware_status) == Some(IoLevel::High));
+        ignition_on && !Self::is_running(index, board_map, hardware_status)
+    }
+}
 
 impl ControllerLogic for EngineIgnition {
-    fn initialize(&mut self, _: &mut Wingman2HardwareStatus) {
-        // NoOp
+    fn initialize(&mut self, hardware_status: &mut Wingman2HardwareStatus) {
+        // The ignitions are left alone, a reboot must not stop running engines.
+        let Some(board_map) = BoardMap::for_configuration(hardware_status.configuration) else {
+            return;
+        };
+        for starter in board_map.engine_starters.iter().flatten() {
+            starter.apply(hardware_status, ButtonCommand::Off);
+        }
     }
 
     fn apply_user_commands(
@@ -15,9 +54,93 @@ impl ControllerLogic for EngineIgnition {
         user_commands: &mut UserCommands,
         hardware_status: &mut Wingman2HardwareStatus,
     ) {
+        let Some(board_map) = BoardMap::for_configuration(hardware_status.configuration) else {
+            return;
+        };
+
+        for user_command in user_commands.iter() {
+            if let Command::EngineIgnition(identifier, switch_command) = user_command.command {
+                for (index, ignition) in board_map.engine_ignitions.iter().enumerate() {
+                    if let Some(ignition) = ignition.filter(|_| Self::addresses(identifier, index)) {
+                        ignition.apply(hardware_status, switch_command);
+                    }
+                }
+            }
+        }
+
+        let now = hardware_status.now;
+        for (index, starter) in self.starters.iter_mut().enumerate() {
+            let Some(output) = board_map.engine_starters[index] else {
+                continue;
+            };
+            let button_command = user_commands
+                .latest(now, |user_command| match user_command.command {
+                    Command::EngineStart(identifier, button_command)
+                        if Self::addresses(identifier, index) =>
+                    {
+                        Some(button_command)
+                    }
+                    _ => None,
+                })
+                .unwrap_or(ButtonCommand::Off);
+
+            if button_command == ButtonCommand::Off {
+                starter.locked_out = false;
+            }
+            if button_command == ButtonCommand::On
+                && !starter.locked_out
+                && Self::may_crank(index, board_map, hardware_status)
+            {
+                starter.cranking_since.get_or_insert(now);
+                output.apply(hardware_status, ButtonCommand::On);
+            } else {
+                starter.cranking_since = None;
+                output.apply(hardware_status, ButtonCommand::Off);
+            }
+        }
+    }
+
+    fn update(&mut self, hardware_status: &mut Wingman2HardwareStatus) {
+        let Some(board_map) = BoardMap::for_configuration(hardware_status.configuration) else {
+            return;
+        };
+
+        for (index, starter) in self.starters.iter_mut().enumerate() {
+            let (Some(output), Some(cranking_since)) =
+                (board_map.engine_starters[index], starter.cranking_since)
+            else {
+                continue;
+            };
+            let cranked_too_long = cranking_since + ENGINE_MAX_CRANK <= hardware_status.now;
+            if cranked_too_long || Self::is_running(index, board_map, hardware_status) {
+                starter.cranking_since = None;
+                starter.locked_out = true;
+                output.apply(hardware_status, ButtonCommand::Off);
+            }
+        }
     }
 
-    fn update(&mut self, _: &mut Wingman2HardwareStatus) {
-        // NoOp
+    fn check_user_command(
+        &self,
+        user_command: &UserCommand,
+        hardware_status: &Wingman2HardwareStatus,
+    ) -> Result<(), NackReason> {
+        let Some(board_map) = BoardMap::for_configuration(hardware_status.configuration) else {
+            return Ok(());
+        };
+        match user_command.command {
+            Command::EngineStart(identifier, ButtonCommand::On) => {
+                let ignition_on = board_map.engine_ignitions.iter().enumerate().any(|(index, ignition)| {
+                    Self::addresses(identifier, index)
+                        && ignition.is_some_and(|ignition| ignition.read(hardware_status) == Some(IoLevel::High))
+                });
+                if ignition_on {
+                    Ok(())
+                } else {
+                    Err(NackReason::Interlocked)
+                }
+            }
+            _ => Ok(()),
+        }
     }
 }
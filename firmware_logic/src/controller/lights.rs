@@ -1,6 +1,6 @@
-use crate::data::user_commands::{HardwareCommand, UserCommands};
-use crate::io::digital_output_impl::{ApplyCommand, DigitalOutputImpl};
-use crate::{BoardMap, ControllerLogic, SwitchCommand, UserCommand};
+use crate::data::user_commands::UserCommands;
+use crate::io::digital_output_impl::ApplyCommand;
+use crate::{BoardMap, Command, ControllerLogic, DeviceIdentifier, SwitchCommand, UserCommand};
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 use crate::Wingman2HardwareStatus;
@@ -18,27 +18,40 @@ enum Light {
 #[allow(clippy::single_match)]
 impl Light {
     fn command_extractor(&self, user_command: &UserCommand) -> Option<SwitchCommand> {
-        None
+        match (self, user_command.command) {
+            (Light::Navigation, Command::NavigationLight(switch_command))
+            | (Light::Anchor, Command::AnchorLight(switch_command))
+            | (Light::Courtesy, Command::CourtesyLight(switch_command))
+            | (Light::Underwater, Command::UnderwaterLight(switch_command))
+            | (Light::Ambient, Command::AmbientLight(switch_command)) => Some(switch_command),
+            _ => None,
+        }
     }
 
     const fn digital_output(&self, board_map: &BoardMap) -> Option<DigitalOutput> {
-        None
+        match self {
+            Light::Navigation => board_map.navigation_light,
+            Light::Anchor => board_map.anchor_light,
+            Light::Courtesy => board_map.courtesy_light,
+            Light::Underwater => board_map.underwater_light,
+            Light::Ambient => board_map.ambient_light,
+        }
     }
 
     fn force(&self, hardware_status: &mut Wingman2HardwareStatus, switch_command: &SwitchCommand) {
-    }
-
-    fn on_apply_hook(
-        &self,
-        hardware_status: &mut Wingman2HardwareStatus,
-        _switch_command: &SwitchCommand,
-    ) {
+        let Some(board_map) = BoardMap::for_configuration(hardware_status.configuration) else {
+            return;
+        };
+        if let Some(digital_output) = self.digital_output(board_map) {
+            digital_output.apply(hardware_status, *switch_command);
+        }
     }
 }
 
 #[allow(clippy::single_match)]
 impl ControllerLogic for Light {
     fn initialize(&mut self, hardware_status: &mut Wingman2HardwareStatus) {
+        self.force(hardware_status, &SwitchCommand::Off);
     }
 
     fn apply_user_commands(
@@ -46,9 +59,15 @@ impl ControllerLogic for Light {
         user_commands: &mut UserCommands,
         hardware_status: &mut Wingman2HardwareStatus,
     ) {
+        for user_command in user_commands.iter() {
+            if let Some(switch_command) = self.command_extractor(user_command) {
+                self.force(hardware_status, &switch_command);
+            }
+        }
     }
 
-    fn update(&mut self, hardware_status: &mut Wingman2HardwareStatus) {
+    fn update(&mut self, _hardware_status: &mut Wingman2HardwareStatus) {
+        // NoOp
     }
 }
 
@@ -57,6 +76,16 @@ pub struct Lights {}
 
 impl ControllerLogic for Lights {
     fn initialize(&mut self, hardware_status: &mut Wingman2HardwareStatus) {
+        for mut light in Light::iter() {
+            light.initialize(hardware_status);
+        }
+
+        let Some(board_map) = BoardMap::for_configuration(hardware_status.configuration) else {
+            return;
+        };
+        for engine_room_light in board_map.engine_room_lights.iter().flatten() {
+            engine_room_light.apply(hardware_status, SwitchCommand::Off);
+        }
     }
 
     fn apply_user_commands(
@@ -64,8 +93,32 @@ impl ControllerLogic for Lights {
         user_commands: &mut UserCommands,
         hardware_status: &mut Wingman2HardwareStatus,
     ) {
+        for mut light in Light::iter() {
+            light.apply_user_commands(user_commands, hardware_status);
+        }
+
+        let Some(board_map) = BoardMap::for_configuration(hardware_status.configuration) else {
+            return;
+        };
+        for user_command in user_commands.iter() {
+            if let Command::EngineRoomLight(identifier, switch_command) = user_command.command {
+                for (index, engine_room_light) in board_map.engine_room_lights.iter().enumerate() {
+                    let Some(engine_room_light) = engine_room_light else {
+                        continue;
+                    };
+                    if identifier == DeviceIdentifier::All
+                        || identifier == DeviceIdentifier::from_index(index)
+                    {
+                        engine_room_light.apply(hardware_status, switch_command);
+                    }
+                }
+            }
+        }
     }
 
     fn update(&mut self, hardware_status: &mut Wingman2HardwareStatus) {
+        for mut light in Light::iter() {
+            light.update(hardware_status);
+        }
     }
-}
\ No newline at end of file
+}
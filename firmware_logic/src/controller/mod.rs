@@ -1,5 +1,10 @@
-use self::{anchor_up_down::AnchorUpDown, lights::Lights, pumps::Pumps};
-use crate::{data::user_commands::UserCommands, ControllerLogic, Wingman2HardwareStatus};
+use self::{
+    anchor_up_down::AnchorUpDown, engine_ignition::EngineIgnition, lights::Lights, pumps::Pumps,
+};
+use crate::{
+    data::user_commands::UserCommands, BoardMap, Controller, ControllerLogic, NackReason,
+    UserCommand, Wingman2HardwareStatus,
+};
 
 mod anchor_up_down;
 mod engine_ignition;
@@ -9,12 +14,32 @@ mod pumps;
 #[derive(Default, Clone)]
 pub struct ButtonsAndSwitches {
     anchor_up_down: AnchorUpDown,
+    engine_ignition: EngineIgnition,
     lights: Lights,
     pumps: Pumps,
 }
 
+/// Whether `controller` may run, it does not when one of its devices sits on a bank that failed
+/// the boot self-test.
+fn runs(controller: Controller, hardware_status: &Wingman2HardwareStatus) -> bool {
+    BoardMap::for_configuration(hardware_status.configuration)
+        .is_none_or(|board_map| hardware_status.io_population.runs(controller, board_map))
+}
+
 impl ControllerLogic for ButtonsAndSwitches {
     fn initialize(&mut self, hardware_status: &mut Wingman2HardwareStatus) {
+        if runs(Controller::AnchorUpDown, hardware_status) {
+            self.anchor_up_down.initialize(hardware_status);
+        }
+        if runs(Controller::EngineIgnition, hardware_status) {
+            self.engine_ignition.initialize(hardware_status);
+        }
+        if runs(Controller::Lights, hardware_status) {
+            self.lights.initialize(hardware_status);
+        }
+        if runs(Controller::Pumps, hardware_status) {
+            self.pumps.initialize(hardware_status);
+        }
     }
 
     fn apply_user_commands(
@@ -22,8 +47,38 @@ impl ControllerLogic for ButtonsAndSwitches {
         user_commands: &mut UserCommands, // TOCHECK: Testing, probably should be immutable
         hardware_status: &mut Wingman2HardwareStatus,
     ) {
+        if runs(Controller::AnchorUpDown, hardware_status) {
+            self.anchor_up_down
+                .apply_user_commands(user_commands, hardware_status);
+        }
+        if runs(Controller::EngineIgnition, hardware_status) {
+            self.engine_ignition
+                .apply_user_commands(user_commands, hardware_status);
+        }
+        if runs(Controller::Lights, hardware_status) {
+            self.lights.apply_user_commands(user_commands, hardware_status);
+        }
+        if runs(Controller::Pumps, hardware_status) {
+            self.pumps.apply_user_commands(user_commands, hardware_status);
+        }
+    }
+
+    fn update(&mut self, hardware_status: &mut Wingman2HardwareStatus) {
+        if runs(Controller::EngineIgnition, hardware_status) {
+            self.engine_ignition.update(hardware_status);
+        }
     }
 
-    fn update(&mut self, _hardware_status: &mut Wingman2HardwareStatus) {
+    fn check_user_command(
+        &self,
+        user_command: &UserCommand,
+        hardware_status: &Wingman2HardwareStatus,
+    ) -> Result<(), NackReason> {
+        self.anchor_up_down
+            .check_user_command(user_command, hardware_status)
+            .and_then(|()| {
+                self.engine_ignition
+                    .check_user_command(user_command, hardware_status)
+            })
     }
 }
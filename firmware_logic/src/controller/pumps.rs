@@ -1,7 +1,8 @@
 use crate::{
-    data::user_commands::{HardwareCommand, UserCommands},
-    io::digital_output_impl::DigitalOutputImpl,
-    ControllerLogic, Wingman2HardwareStatus,
+    data::user_commands::UserCommands,
+    io::digital_output_impl::ApplyCommand,
+    BoardMap, ButtonCommand, Command, ControllerLogic, DeviceIdentifier, SwitchCommand,
+    Wingman2HardwareStatus,
 };
 
 #[derive(Default, Clone)]
@@ -10,6 +11,15 @@ pub struct Pumps {}
 impl ControllerLogic for Pumps {
     fn initialize(&mut self, hardware_status: &mut Wingman2HardwareStatus) {
         // Start with all pumps activated
+        let Some(board_map) = BoardMap::for_configuration(hardware_status.configuration) else {
+            return;
+        };
+        for bilge_pump in board_map.bilge_pumps.iter().flatten() {
+            bilge_pump.apply(hardware_status, SwitchCommand::On);
+        }
+        if let Some(black_water_pump) = board_map.black_water_pump {
+            black_water_pump.apply(hardware_status, ButtonCommand::Off);
+        }
     }
 
     fn apply_user_commands(
@@ -17,9 +27,38 @@ impl ControllerLogic for Pumps {
         user_commands: &mut UserCommands,
         hardware_status: &mut Wingman2HardwareStatus,
     ) {
+        let Some(board_map) = BoardMap::for_configuration(hardware_status.configuration) else {
+            return;
+        };
+
+        for user_command in user_commands.iter() {
+            if let Command::BilgePump(identifier, switch_command) = user_command.command {
+                for (index, bilge_pump) in board_map.bilge_pumps.iter().enumerate() {
+                    let Some(bilge_pump) = bilge_pump else {
+                        continue;
+                    };
+                    if identifier == DeviceIdentifier::All
+                        || identifier == DeviceIdentifier::from_index(index)
+                    {
+                        bilge_pump.apply(hardware_status, switch_command);
+                    }
+                }
+            }
+        }
+
+        // The black water pump only runs while the button is held.
+        if let Some(black_water_pump) = board_map.black_water_pump {
+            let button_command = user_commands
+                .latest(hardware_status.now, |user_command| match user_command.command {
+                    Command::BlackWaterPump(button_command) => Some(button_command),
+                    _ => None,
+                })
+                .unwrap_or(ButtonCommand::Off);
+            black_water_pump.apply(hardware_status, button_command);
+        }
     }
 
     fn update(&mut self, _: &mut Wingman2HardwareStatus) {
         // NoOp
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,352 @@
+use crc::{Crc, CRC_32_ISO_HDLC};
+use embedded_storage::nor_flash::NorFlash;
+use serde::{Deserialize, Serialize};
+
+use super::image::{ImageHeader, IMAGE_HEADER_SIZE};
+use crate::Timestamp;
+
+/// Size of each firmware slot, sectors 2 to 7 of a flash bank. See `memory.x`.
+pub const SLOT_SIZE: u32 = 0xC_0000;
+/// Sector 1 of bank 1, between the bootloader in sector 0 and the first slot.
+pub const BOOT_RECORD_ADDRESS: u32 = 0x0802_0000;
+/// Offset of `BOOT_RECORD_ADDRESS` within bank 1.
+pub const BOOT_RECORD_OFFSET: u32 = BOOT_RECORD_ADDRESS - BANK_1_ADDRESS;
+/// Start of backup SRAM, which holds the `BootAttempts`. See `BSRAM` in `memory.x`.
+pub const BOOT_ATTEMPTS_ADDRESS: u32 = 0x3880_0000;
+/// Boots an unconfirmed image gets before the bootloader rolls back to the other slot.
+pub const MAX_BOOT_ATTEMPTS: u8 = 3;
+/// How long a new image runs with the control server connected before it confirms itself.
+pub const CONFIRM_AFTER: Timestamp = Timestamp::new(60_000);
+
+const BANK_1_ADDRESS: u32 = 0x0800_0000;
+const BANK_2_ADDRESS: u32 = 0x0810_0000;
+
+/// Space taken by one entry of the log, a multiple of any flash write size.
+const ENTRY_SIZE: usize = 64;
+const HEADER_SIZE: usize = 10;
+const CRC_SIZE: usize = 4;
+const MAGIC: u32 = u32::from_le_bytes(*b"WMBR");
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+const ATTEMPTS_MAGIC: u32 = u32::from_le_bytes(*b"WMBA");
+
+/// Flash region a firmware image is linked at, see `FLASH_ORIGIN` in `build.rs`.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Slot {
+    /// Bank 1 from 0x08040000.
+    First,
+    /// Bank 2 from 0x08100000.
+    Second,
+}
+
+impl Slot {
+    pub const fn address(self) -> u32 {
+        match self {
+            Slot::First => BANK_1_ADDRESS + 0x4_0000,
+            Slot::Second => BANK_2_ADDRESS,
+        }
+    }
+
+    /// Where the firmware of the slot is linked, after its image header.
+    pub const fn vector_table(self) -> u32 {
+        self.address() + IMAGE_HEADER_SIZE as u32
+    }
+
+    /// Offset of the slot within its flash bank.
+    pub const fn bank_offset(self) -> u32 {
+        match self {
+            Slot::First => self.address() - BANK_1_ADDRESS,
+            Slot::Second => self.address() - BANK_2_ADDRESS,
+        }
+    }
+
+    pub const fn other(self) -> Self {
+        match self {
+            Slot::First => Slot::Second,
+            Slot::Second => Slot::First,
+        }
+    }
+
+    /// The slot holding `address`, e.g. that of the running vector table. `None` outside both slots,
+    /// as for an image flashed at the start of the flash by a debugger.
+    pub fn containing(address: u32) -> Option<Self> {
+        [Slot::First, Slot::Second]
+            .into_iter()
+            .find(|slot| (slot.address()..slot.address() + SLOT_SIZE).contains(&address))
+    }
+}
+
+/// Tells the bootloader which slot to boot.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BootRecord {
+    pub active: Slot,
+    /// The image of `active` ran healthy long enough to be kept, see `BootConfirmation`. Until then
+    /// the bootloader counts its boots and rolls back to the other slot after `MAX_BOOT_ATTEMPTS`.
+    pub confirmed: bool,
+}
+
+/// Boots counted by the bootloader while the active image is unconfirmed, kept in backup SRAM.
+///
+/// Backup SRAM survives resets but not power loss, unless the card has a backup battery: the count
+/// then starts over. Its contents are random at power up, they are checked on load.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct BootAttempts {
+    /// Slot the boots were counted for.
+    pub slot: Option<Slot>,
+    pub count: u8,
+    /// The bootloader rolled back from this slot, until the firmware took the report.
+    pub rolled_back_from: Option<Slot>,
+}
+
+impl BootAttempts {
+    /// Decodes the words at `BOOT_ATTEMPTS_ADDRESS`, the default if they were never written.
+    pub fn from_words(words: [u32; 3]) -> Self {
+        let [magic, state, check] = words;
+        if magic != ATTEMPTS_MAGIC || check != !state {
+            return Self::default();
+        }
+        let [slot, count, rolled_back_from, _] = state.to_le_bytes();
+        Self {
+            slot: slot_from_code(slot),
+            count,
+            rolled_back_from: slot_from_code(rolled_back_from),
+        }
+    }
+
+    pub fn to_words(self) -> [u32; 3] {
+        let state = u32::from_le_bytes([
+            slot_code(self.slot),
+            self.count,
+            slot_code(self.rolled_back_from),
+            0,
+        ]);
+        [ATTEMPTS_MAGIC, state, !state]
+    }
+}
+
+fn slot_code(slot: Option<Slot>) -> u8 {
+    match slot {
+        None => 0,
+        Some(Slot::First) => 1,
+        Some(Slot::Second) => 2,
+    }
+}
+
+fn slot_from_code(code: u8) -> Option<Slot> {
+    match code {
+        1 => Some(Slot::First),
+        2 => Some(Slot::Second),
+        _ => None,
+    }
+}
+
+/// Counts a boot of the active slot of `record`. Once an unconfirmed image was booted
+/// `MAX_BOOT_ATTEMPTS` times without confirming itself, rolls back: returns the record selecting
+/// the other slot, to save before booting it.
+pub fn count_boot(record: &BootRecord, attempts: &mut BootAttempts) -> Option<BootRecord> {
+    if record.confirmed {
+        attempts.slot = None;
+        attempts.count = 0;
+        return None;
+    }
+    if attempts.slot == Some(record.active) {
+        attempts.count = attempts.count.saturating_add(1);
+    } else {
+        attempts.slot = Some(record.active);
+        attempts.count = 1;
+    }
+    if attempts.count <= MAX_BOOT_ATTEMPTS {
+        return None;
+    }
+
+    attempts.slot = None;
+    attempts.count = 0;
+    attempts.rolled_back_from = Some(record.active);
+    // The other slot holds the image that activated this one, it was confirmed then
+    Some(BootRecord {
+        active: record.active.other(),
+        confirmed: true,
+    })
+}
+
+/// Tells when a new image confirms itself: once it ran `CONFIRM_AFTER` with the control server
+/// connected. Disconnecting starts the wait over.
+pub struct BootConfirmation {
+    pending: bool,
+    connected_since: Option<Timestamp>,
+}
+
+impl BootConfirmation {
+    /// `pending` if the running image is active and unconfirmed in the boot record.
+    pub const fn new(pending: bool) -> Self {
+        Self {
+            pending,
+            connected_since: None,
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    /// Returns true once, when the image should be confirmed.
+    pub fn update(&mut self, now: Timestamp, connected: bool) -> bool {
+        if !self.pending || !connected {
+            self.connected_since = None;
+            return false;
+        }
+        let since = *self.connected_since.get_or_insert(now);
+        if now < since + CONFIRM_AFTER {
+            return false;
+        }
+        self.pending = false;
+        true
+    }
+}
+
+/// Picks the slot to boot: the active one of the record, or the other one when its image is
+/// invalid. Without a record, e.g. after the slots were flashed by a debugger, the first valid slot
+/// is booted. `contents` returns the whole of a slot.
+pub fn select_slot<'a>(
+    record: Option<&BootRecord>,
+    contents: impl Fn(Slot) -> &'a [u8],
+) -> Option<Slot> {
+    let preferred = record.map_or(Slot::First, |record| record.active);
+    [preferred, preferred.other()]
+        .into_iter()
+        .find(|&slot| image_is_valid(slot, contents(slot)))
+}
+
+/// Whether the slot holds an image matching its header, whose vector table points into RAM and
+/// into the slot. The signature was checked by the updater.
+pub fn image_is_valid(slot: Slot, contents: &[u8]) -> bool {
+    let Ok(header) = ImageHeader::decode(contents) else {
+        return false;
+    };
+    if header.check_crc(contents).is_err() {
+        return false;
+    }
+
+    let word = |index: usize| {
+        let offset = IMAGE_HEADER_SIZE + index * 4;
+        contents
+            .get(offset..offset + 4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+    };
+    let (Some(stack_pointer), Some(reset_vector)) = (word(0), word(1)) else {
+        return false;
+    };
+    // The initial stack pointer is the top of DTCM or AXI SRAM
+    let stack_in_ram = (0x2000_0004..=0x2002_0000).contains(&stack_pointer)
+        || (0x2400_0004..=0x2408_0000).contains(&stack_pointer);
+    let reset_in_slot = reset_vector & 1 == 1
+        && (slot.address()..slot.address() + SLOT_SIZE).contains(&(reset_vector & !1));
+    stack_in_ram && reset_in_slot
+}
+
+/// Keeps `BootRecord`s as a log of entries appended to a single erase sector.
+///
+/// Appending only programs blank flash, which is quick and leaves the previous entries intact if
+/// power is lost while writing. The sector is erased once full, the record is lost if power is lost
+/// between that erase and the next write.
+///
+/// Entry layout, little endian: magic, payload length `u16`, sequence `u32`, postcard payload, CRC-32
+/// of everything before it.
+pub struct BootRecords {
+    offset: u32,
+    /// Index of the next blank entry and the sequence of the latest valid one.
+    next: Option<(usize, u32)>,
+}
+
+impl BootRecords {
+    /// `offset` is the start of an erase sector of the flash, used for nothing else.
+    pub const fn new(offset: u32) -> Self {
+        Self { offset, next: None }
+    }
+
+    /// Reads the latest valid entry, `None` on a blank sector or if none is valid.
+    pub fn load<F: NorFlash>(&mut self, flash: &mut F) -> Result<Option<BootRecord>, F::Error> {
+        let mut latest = None;
+        let mut blank = None;
+        for index in 0..Self::capacity::<F>() {
+            let mut entry = [0u8; ENTRY_SIZE];
+            flash.read(self.entry_offset(index), &mut entry)?;
+            if entry.iter().all(|&byte| byte == 0xFF) {
+                blank = Some(index);
+                break;
+            }
+            if let Some(decoded) = decode_entry(&entry) {
+                latest = Some(decoded);
+            }
+        }
+
+        let sequence = latest.as_ref().map_or(0, |(sequence, _)| *sequence);
+        self.next = Some((blank.unwrap_or(Self::capacity::<F>()), sequence));
+        Ok(latest.map(|(_, record)| record))
+    }
+
+    /// Appends `record`, erasing the sector first when it is full. Loads first if needed.
+    pub fn save<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        record: &BootRecord,
+    ) -> Result<(), F::Error> {
+        let (mut index, sequence) = match self.next {
+            Some(next) => next,
+            None => {
+                self.load(flash)?;
+                self.next.unwrap_or_default()
+            }
+        };
+        if index >= Self::capacity::<F>() {
+            flash.erase(self.offset, self.offset + F::ERASE_SIZE as u32)?;
+            index = 0;
+        }
+
+        let sequence = sequence.wrapping_add(1);
+        let entry = encode_entry(record, sequence);
+        // Whatever happens next, this entry is no longer blank
+        self.next = Some((index + 1, sequence));
+        flash.write(self.entry_offset(index), &entry)
+    }
+
+    fn capacity<F: NorFlash>() -> usize {
+        F::ERASE_SIZE / ENTRY_SIZE
+    }
+
+    fn entry_offset(&self, index: usize) -> u32 {
+        self.offset + (index * ENTRY_SIZE) as u32
+    }
+}
+
+fn encode_entry(record: &BootRecord, sequence: u32) -> [u8; ENTRY_SIZE] {
+    let mut entry = [0xFFu8; ENTRY_SIZE];
+    let payload_length = postcard::to_slice(record, &mut entry[HEADER_SIZE..ENTRY_SIZE - CRC_SIZE])
+        .expect("a boot record fits an entry")
+        .len();
+
+    entry[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+    entry[4..6].copy_from_slice(&(payload_length as u16).to_le_bytes());
+    entry[6..10].copy_from_slice(&sequence.to_le_bytes());
+    let crc_offset = HEADER_SIZE + payload_length;
+    let crc = CRC.checksum(&entry[..crc_offset]);
+    entry[crc_offset..crc_offset + CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+    entry
+}
+
+fn decode_entry(entry: &[u8; ENTRY_SIZE]) -> Option<(u32, BootRecord)> {
+    let field = |range: core::ops::Range<usize>| &entry[range];
+    let magic = u32::from_le_bytes(field(0..4).try_into().ok()?);
+    let payload_length = u16::from_le_bytes(field(4..6).try_into().ok()?) as usize;
+    let sequence = u32::from_le_bytes(field(6..10).try_into().ok()?);
+    if magic != MAGIC || payload_length > ENTRY_SIZE - HEADER_SIZE - CRC_SIZE {
+        return None;
+    }
+
+    let crc_offset = HEADER_SIZE + payload_length;
+    let crc = u32::from_le_bytes(field(crc_offset..crc_offset + CRC_SIZE).try_into().ok()?);
+    if crc != CRC.checksum(&entry[..crc_offset]) {
+        return None;
+    }
+    let record = postcard::from_bytes(&entry[HEADER_SIZE..crc_offset]).ok()?;
+    Some((sequence, record))
+}
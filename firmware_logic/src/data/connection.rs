@@ -0,0 +1,145 @@
+use heapless::HistoryBuffer;
+use serde::{Deserialize, Serialize};
+
+use crate::data::ping::PingStatistics;
+use crate::Timestamp;
+
+/// Heartbeat intervals the jitter and the recent gaps are computed over.
+pub const CONNECTION_WINDOW: usize = 32;
+
+/// Jitter up to this does not lower the QoS.
+const GOOD_JITTER_MS: u32 = 10;
+/// Jitter this high or higher halves the QoS.
+const BAD_JITTER_MS: u32 = 250;
+/// QoS lost for every gap in the window.
+const GAP_PENALTY: u32 = 10;
+
+/// Traffic received from the control server, see `ConnectionMonitor`.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionStatistics {
+    /// Heartbeats and commands received since boot.
+    pub received_messages: u64,
+    /// Mean deviation of the heartbeat intervals of the window from their mean.
+    pub jitter_ms: u32,
+    /// Heartbeat intervals since boot long enough to count as a gap.
+    pub gaps: u32,
+    /// Gaps among the heartbeat intervals of the window.
+    pub recent_gaps: u8,
+    /// Longest heartbeat interval since boot.
+    pub longest_interval_ms: u64,
+}
+
+/// Follows the connection to the control server from the messages it sends.
+///
+/// The server sends its reporting as a heartbeat, the jitter and the gaps are measured on the
+/// heartbeats only as commands come whenever the user acts. The connection is lost when nothing
+/// was received within `timeout`, a heartbeat interval of more than half of it is a gap.
+pub struct ConnectionMonitor {
+    timeout: Timestamp,
+    received_messages: u64,
+    latest_message: Option<Timestamp>,
+    latest_heartbeat: Option<Timestamp>,
+    intervals: HistoryBuffer<u32, CONNECTION_WINDOW>,
+    gaps: u32,
+    longest_interval_ms: u64,
+}
+
+impl ConnectionMonitor {
+    pub const fn new(timeout: Timestamp) -> Self {
+        Self {
+            timeout,
+            received_messages: 0,
+            latest_message: None,
+            latest_heartbeat: None,
+            intervals: HistoryBuffer::new(),
+            gaps: 0,
+            longest_interval_ms: 0,
+        }
+    }
+
+    /// Records a heartbeat received at `now`.
+    pub fn heartbeat(&mut self, now: Timestamp) {
+        if let Some(latest) = self.latest_heartbeat {
+            let interval = now.as_millis().saturating_sub(latest.as_millis());
+            if self.is_gap(interval) {
+                self.gaps += 1;
+            }
+            self.longest_interval_ms = self.longest_interval_ms.max(interval);
+            self.intervals.write(interval.min(u32::MAX as u64) as u32);
+        }
+        self.latest_heartbeat = Some(now);
+        self.message(now);
+    }
+
+    /// Records a command received at `now`.
+    pub fn command(&mut self, now: Timestamp) {
+        self.message(now);
+    }
+
+    pub fn is_connected(&self, now: Timestamp) -> bool {
+        self.latest_message
+            .is_some_and(|latest| now < latest + self.timeout)
+    }
+
+    pub fn statistics(&self) -> ConnectionStatistics {
+        ConnectionStatistics {
+            received_messages: self.received_messages,
+            jitter_ms: self.jitter_ms(),
+            gaps: self.gaps,
+            recent_gaps: self
+                .intervals
+                .iter()
+                .filter(|&&interval| self.is_gap(interval as u64))
+                .count() as u8,
+            longest_interval_ms: self.longest_interval_ms,
+        }
+    }
+
+    /// Rates the connection from 0, disconnected, to 100: every recent gap costs `GAP_PENALTY`,
+    /// the jitter up to half as it grows from `GOOD_JITTER_MS` to `BAD_JITTER_MS`. Scaled by the
+    /// reachability measured by `pings` once one was answered, servers dropping pings are not
+    /// penalized.
+    pub fn qos(&self, now: Timestamp, pings: &PingStatistics) -> u8 {
+        if !self.is_connected(now) {
+            return 0;
+        }
+        let statistics = self.statistics();
+        let jitter_penalty = 50
+            * (statistics.jitter_ms.clamp(GOOD_JITTER_MS, BAD_JITTER_MS) - GOOD_JITTER_MS)
+            / (BAD_JITTER_MS - GOOD_JITTER_MS);
+        let gap_penalty = GAP_PENALTY * statistics.recent_gaps as u32;
+        let traffic = 100u32.saturating_sub(jitter_penalty + gap_penalty);
+        match pings.round_trip_ms {
+            Some(_) => (traffic * pings.qos() as u32 / 100) as u8,
+            None => traffic as u8,
+        }
+    }
+
+    fn message(&mut self, now: Timestamp) {
+        self.received_messages += 1;
+        self.latest_message = Some(now);
+    }
+
+    fn is_gap(&self, interval_ms: u64) -> bool {
+        interval_ms > self.timeout.as_millis() / 2
+    }
+
+    fn jitter_ms(&self) -> u32 {
+        let count = self.intervals.len() as u64;
+        if count < 2 {
+            return 0;
+        }
+        let mean = self
+            .intervals
+            .iter()
+            .map(|&interval| interval as u64)
+            .sum::<u64>()
+            / count;
+        let deviation = self
+            .intervals
+            .iter()
+            .map(|&interval| (interval as u64).abs_diff(mean))
+            .sum::<u64>();
+        (deviation / count) as u32
+    }
+}
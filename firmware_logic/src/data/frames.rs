@@ -0,0 +1,70 @@
+use core::marker::PhantomData;
+
+use postcard::accumulator::{CobsAccumulator, FeedResult};
+use serde::de::DeserializeOwned;
+
+use crate::{ServerReporting, UserCommand};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    /// The frame did not fit the reassembly buffer and was dropped.
+    TooLong,
+    /// The frame was complete but does not decode to the expected message.
+    Undecodable,
+}
+
+/// Splits a TCP stream into COBS framed messages of type `T`.
+///
+/// A single read may hold several frames or only part of one, `N` bounds the length of a frame.
+pub struct Frames<T, const N: usize> {
+    accumulator: CobsAccumulator<N>,
+    message: PhantomData<T>,
+}
+
+/// Commands received from the control server.
+pub type CommandFrames<const N: usize> = Frames<UserCommand, N>;
+
+impl<T, const N: usize> Default for Frames<T, N> {
+    fn default() -> Self {
+        Self {
+            accumulator: CobsAccumulator::new(),
+            message: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned, const N: usize> Frames<T, N> {
+    /// Drops any partial frame, e.g. when the connection was closed.
+    pub fn reset(&mut self) {
+        self.accumulator = CobsAccumulator::new();
+    }
+
+    /// Feeds the bytes received, `on_frame` is called once for every frame they complete.
+    pub fn feed(&mut self, bytes: &[u8], mut on_frame: impl FnMut(Result<T, FrameError>)) {
+        let mut window = bytes;
+        while !window.is_empty() {
+            window = match self.accumulator.feed::<T>(window) {
+                FeedResult::Consumed => break,
+                FeedResult::OverFull(remaining) => {
+                    on_frame(Err(FrameError::TooLong));
+                    remaining
+                }
+                FeedResult::DeserError(remaining) => {
+                    on_frame(Err(FrameError::Undecodable));
+                    remaining
+                }
+                FeedResult::Success { data, remaining } => {
+                    on_frame(Ok(data));
+                    remaining
+                }
+            };
+        }
+    }
+}
+
+impl ServerReporting {
+    /// Decodes a datagram received from the control server, `None` if it is malformed.
+    pub fn from_datagram(datagram: &[u8]) -> Option<Self> {
+        postcard::from_bytes(datagram).ok()
+    }
+}
@@ -0,0 +1,213 @@
+use crc::{Crc, CRC_32_ISO_HDLC};
+use ed25519_compact::{PublicKey, Signature};
+use heapless::{String, Vec};
+use serde::{Deserialize, Serialize};
+
+use crate::Configuration;
+
+/// Space reserved for the header at the start of a slot. The vector table follows it, aligned as
+/// `VTOR` requires.
+pub const IMAGE_HEADER_SIZE: usize = 0x400;
+/// Bumped whenever `ImageHeader` changes in a way older bootloaders cannot decode.
+pub const IMAGE_FORMAT_VERSION: u16 = 1;
+/// Length of the Ed25519 public key baked into the firmware.
+pub const PUBLIC_KEY_SIZE: usize = 32;
+
+const MAGIC: u32 = u32::from_le_bytes(*b"WMFW");
+const SIGNATURE_OFFSET: usize = 4;
+const SIGNATURE_SIZE: usize = 64;
+/// The signature covers the header from there, then the image.
+const SIGNED_OFFSET: usize = SIGNATURE_OFFSET + SIGNATURE_SIZE;
+const FIELDS_OFFSET: usize = SIGNED_OFFSET + 4;
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+const BUILD_INFO_MARKER: [u8; 8] = *b"WMBUILD\0";
+
+/// Describes the image following it in a slot, added by `image_tool` after the build.
+///
+/// Header layout, little endian: magic, Ed25519 signature, format version `u16`, fields length
+/// `u16`, postcard fields, erased up to `IMAGE_HEADER_SIZE`. The signature covers everything from
+/// the format version to the end of the fields, then the image.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ImageHeader {
+    /// Package version of the firmware, e.g. "0.2.0".
+    pub version: String<16>,
+    pub git_hash: Option<[u8; 20]>,
+    /// The working tree had uncommitted changes when the firmware was built.
+    pub git_dirty: bool,
+    /// The updater refuses the image on a card configured otherwise.
+    pub configurations: Vec<Configuration, 8>,
+    /// Length of the image, header excluded.
+    pub length: u32,
+    /// CRC-32 of the image, header excluded.
+    pub crc: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImageError {
+    /// No header, or one of a format this firmware cannot decode.
+    Header,
+    /// The image does not match the length or the CRC of its header.
+    Crc,
+    Signature,
+    /// The slot could not be read.
+    Read,
+}
+
+impl ImageHeader {
+    pub fn is_compatible(&self, configuration: Configuration) -> bool {
+        self.configurations.contains(&configuration)
+    }
+
+    /// Writes the header, unsigned, and returns the part of it covered by the signature.
+    pub fn encode<'a>(&self, header: &'a mut [u8; IMAGE_HEADER_SIZE]) -> Option<&'a [u8]> {
+        header.fill(0xFF);
+        let fields_length = postcard::to_slice(self, &mut header[FIELDS_OFFSET..])
+            .ok()?
+            .len();
+        header[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        header[SIGNED_OFFSET..SIGNED_OFFSET + 2]
+            .copy_from_slice(&IMAGE_FORMAT_VERSION.to_le_bytes());
+        header[SIGNED_OFFSET + 2..FIELDS_OFFSET]
+            .copy_from_slice(&(fields_length as u16).to_le_bytes());
+        Some(&header[SIGNED_OFFSET..FIELDS_OFFSET + fields_length])
+    }
+
+    /// Completes a header written by `encode` with the signature of its signed part and the image.
+    pub fn set_signature(header: &mut [u8; IMAGE_HEADER_SIZE], signature: &[u8; SIGNATURE_SIZE]) {
+        header[SIGNATURE_OFFSET..SIGNED_OFFSET].copy_from_slice(signature);
+    }
+
+    /// Decodes the header at the start of `slot`.
+    pub fn decode(slot: &[u8]) -> Result<Self, ImageError> {
+        let (_, signed) = split_header(slot)?;
+        postcard::from_bytes(&signed[FIELDS_OFFSET - SIGNED_OFFSET..])
+            .map_err(|_| ImageError::Header)
+    }
+
+    /// Checks the image following the header against its length and CRC.
+    pub fn check_crc(&self, slot: &[u8]) -> Result<(), ImageError> {
+        let image = slot
+            .get(IMAGE_HEADER_SIZE..IMAGE_HEADER_SIZE + self.length as usize)
+            .ok_or(ImageError::Crc)?;
+        match CRC.checksum(image) == self.crc {
+            true => Ok(()),
+            false => Err(ImageError::Crc),
+        }
+    }
+}
+
+/// Verifies the signature of `header` and of the image following it against `public_key`, and the
+/// image against the CRC of the header. `read` fills the buffer with the bytes of the slot from an
+/// offset, the image need not be in memory.
+pub fn verify_signature<E>(
+    public_key: &[u8; PUBLIC_KEY_SIZE],
+    header: &[u8; IMAGE_HEADER_SIZE],
+    mut read: impl FnMut(u32, &mut [u8]) -> Result<(), E>,
+) -> Result<(), ImageError> {
+    let (signature, signed) = split_header(header)?;
+    let fields: ImageHeader = postcard::from_bytes(&signed[FIELDS_OFFSET - SIGNED_OFFSET..])
+        .map_err(|_| ImageError::Header)?;
+
+    let mut state = PublicKey::new(*public_key)
+        .verify_incremental(&Signature::new(signature))
+        .map_err(|_| ImageError::Signature)?;
+    state.absorb(signed);
+    let mut digest = CRC.digest();
+    let mut buf = [0u8; 256];
+    let mut offset = 0;
+    while offset < fields.length {
+        let length = buf.len().min((fields.length - offset) as usize);
+        read(IMAGE_HEADER_SIZE as u32 + offset, &mut buf[..length])
+            .map_err(|_| ImageError::Read)?;
+        state.absorb(&buf[..length]);
+        digest.update(&buf[..length]);
+        offset += length as u32;
+    }
+    state.verify().map_err(|_| ImageError::Signature)?;
+    match digest.finalize() == fields.crc {
+        true => Ok(()),
+        false => Err(ImageError::Crc),
+    }
+}
+
+/// Returns the signature and the signed part of the header.
+fn split_header(slot: &[u8]) -> Result<([u8; SIGNATURE_SIZE], &[u8]), ImageError> {
+    let header = slot.get(..IMAGE_HEADER_SIZE).ok_or(ImageError::Header)?;
+    let field = |range: core::ops::Range<usize>| &header[range];
+    let magic = u32::from_le_bytes(field(0..4).try_into().unwrap());
+    let format_version =
+        u16::from_le_bytes(field(SIGNED_OFFSET..SIGNED_OFFSET + 2).try_into().unwrap());
+    let fields_length =
+        u16::from_le_bytes(field(SIGNED_OFFSET + 2..FIELDS_OFFSET).try_into().unwrap()) as usize;
+    if magic != MAGIC
+        || format_version != IMAGE_FORMAT_VERSION
+        || fields_length > IMAGE_HEADER_SIZE - FIELDS_OFFSET
+    {
+        return Err(ImageError::Header);
+    }
+    let signature = field(SIGNATURE_OFFSET..SIGNED_OFFSET).try_into().unwrap();
+    Ok((
+        signature,
+        field(SIGNED_OFFSET..FIELDS_OFFSET + fields_length),
+    ))
+}
+
+/// Build metadata the firmware keeps in its binary, `image_tool` finds it by its marker and copies
+/// it into the header.
+#[repr(C)]
+pub struct BuildInfo {
+    marker: [u8; 8],
+    /// Zero padded.
+    version: [u8; 16],
+    /// Hexadecimal, zero when unknown.
+    git_hash: [u8; 40],
+    git_dirty: u8,
+}
+
+impl BuildInfo {
+    /// Takes the fields of `built_info`.
+    pub const fn new(version: &str, git_hash: Option<&str>, git_dirty: Option<bool>) -> Self {
+        Self {
+            marker: BUILD_INFO_MARKER,
+            version: padded(version.as_bytes()),
+            git_hash: match git_hash {
+                Some(git_hash) => padded(git_hash.as_bytes()),
+                None => [0; 40],
+            },
+            git_dirty: matches!(git_dirty, Some(true)) as u8,
+        }
+    }
+
+    /// Reads the build info from a firmware binary, `None` if it holds none.
+    pub fn find(binary: &[u8]) -> Option<(String<16>, Option<[u8; 20]>, bool)> {
+        let start = binary
+            .windows(BUILD_INFO_MARKER.len())
+            .position(|window| window == BUILD_INFO_MARKER)?;
+        let info = binary.get(start..start + core::mem::size_of::<BuildInfo>())?;
+        let version = &info[8..24];
+        let version = &version[..version.iter().position(|&byte| byte == 0).unwrap_or(16)];
+        let version = String::try_from(core::str::from_utf8(version).ok()?).ok()?;
+
+        let git_hash = match info[24] {
+            0 => None,
+            _ => {
+                let mut git_hash = [0u8; 20];
+                for (byte, hex) in git_hash.iter_mut().zip(info[24..64].chunks(2)) {
+                    *byte = u8::from_str_radix(core::str::from_utf8(hex).ok()?, 16).ok()?;
+                }
+                Some(git_hash)
+            }
+        };
+        Some((version, git_hash, info[64] != 0))
+    }
+}
+
+const fn padded<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    let mut padded = [0; N];
+    let mut index = 0;
+    while index < N && index < bytes.len() {
+        padded[index] = bytes[index];
+        index += 1;
+    }
+    padded
+}
@@ -0,0 +1,95 @@
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+use crate::{BoardMap, Configuration, Controller, IoSupply, IoTemp, IoType, WATCHED_LINES};
+
+/// Result of probing one DI or DO controller at boot, see `Dio::probe`.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControllerProbe {
+    /// The self-test did not run, e.g. in the simulator.
+    #[default]
+    NotProbed,
+    /// Answered as expected, with the supply and the temperature it reported.
+    Fitted { supply: IoSupply, temp: IoTemp },
+    /// Nothing answered on the bus, the bank is not fitted.
+    Missing,
+    /// Answered with a corrupted frame or lost its configuration.
+    Faulty,
+}
+
+impl ControllerProbe {
+    /// Whether the channels of the controller can be relied on. A bank that was not probed gets
+    /// the benefit of the doubt.
+    pub fn is_usable(&self) -> bool {
+        matches!(
+            self,
+            ControllerProbe::NotProbed | ControllerProbe::Fitted { .. }
+        )
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BankPopulation {
+    pub inputs: ControllerProbe,
+    pub outputs: ControllerProbe,
+}
+
+/// Controllers found on the six IO banks, indexed by `IoBank`. Output n and input n, up to 47,
+/// are on bank n / 8, the watched inputs after them on the DO controllers, see
+/// `DigitalInput::watched`.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IoPopulation {
+    pub banks: [BankPopulation; 6],
+}
+
+impl IoPopulation {
+    /// The controller `address`, an input or an output numbered from 0, sits on.
+    pub fn controller(&self, direction: IoType, address: usize) -> Option<ControllerProbe> {
+        match direction {
+            IoType::DigitalIn if address >= 48 => {
+                Some(self.banks.get((address - 48) / WATCHED_LINES)?.outputs)
+            }
+            IoType::DigitalIn => Some(self.banks.get(address / 8)?.inputs),
+            IoType::DigitalOut => Some(self.banks.get(address / 8)?.outputs),
+        }
+    }
+
+    /// Whether every device of `controller` mapped in `board_map` sits on a usable bank.
+    pub fn runs(&self, controller: Controller, board_map: &BoardMap) -> bool {
+        let usable = |direction, address| {
+            self.controller(direction, address)
+                .is_some_and(|probe| probe.is_usable())
+        };
+        board_map
+            .digital_outputs_of(controller)
+            .all(|output| usable(IoType::DigitalOut, output.address))
+            && board_map
+                .digital_inputs_of(controller)
+                .all(|input| usable(IoType::DigitalIn, input.address))
+    }
+}
+
+/// The boot self-test of the IO banks as reported to the control server.
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IoSelfTest {
+    pub population: IoPopulation,
+    /// Controllers of the configuration that do not run, one of their devices sits on a bank
+    /// that is missing or faulty.
+    pub refused: Vec<Controller, 4>,
+}
+
+impl IoSelfTest {
+    pub fn new(population: IoPopulation, configuration: Configuration) -> Self {
+        let refused = match BoardMap::for_configuration(configuration) {
+            Some(board_map) => Controller::iter()
+                .filter(|&controller| !population.runs(controller, board_map))
+                .collect(),
+            None => Vec::new(),
+        };
+        Self {
+            population,
+            refused,
+        }
+    }
+}
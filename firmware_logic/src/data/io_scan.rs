@@ -0,0 +1,70 @@
+use heapless::HistoryBuffer;
+use serde::{Deserialize, Serialize};
+
+use crate::Timestamp;
+
+/// The IO banks are scanned at this interval, the same as the logic is applied at.
+pub const IO_SCAN_INTERVAL: Timestamp = Timestamp::new(5);
+
+/// Scans the mean duration is computed over.
+pub const IO_SCAN_WINDOW: usize = 64;
+
+/// Timing of the IO scan, see `IoScanTiming`.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IoScanStatus {
+    /// Scans completed since boot.
+    pub scans: u64,
+    /// Duration of the latest scan, the banks read and the outputs written.
+    pub latest_us: u32,
+    /// Mean duration of the scans of the window.
+    pub mean_us: u32,
+    /// Longest scan since boot.
+    pub longest_us: u32,
+    /// Scans since boot that took longer than `IO_SCAN_INTERVAL`, the next one started late.
+    pub overruns: u32,
+    /// Reads since boot that failed, the channel is reported unknown until read again.
+    pub failed_reads: u32,
+    /// Output writes since boot that failed.
+    pub failed_writes: u32,
+    /// Scans since boot started ahead of the interval by an input change interrupt.
+    pub change_wakeups: u32,
+}
+
+/// Accumulates the duration and the failures of the IO scans.
+#[derive(Default)]
+pub struct IoScanTiming {
+    status: IoScanStatus,
+    durations: HistoryBuffer<u32, IO_SCAN_WINDOW>,
+}
+
+impl IoScanTiming {
+    /// Records a scan that took `duration_us` with `failed_reads` and `failed_writes`.
+    pub fn record(&mut self, duration_us: u32, failed_reads: u32, failed_writes: u32) {
+        let status = &mut self.status;
+        status.scans += 1;
+        status.latest_us = duration_us;
+        status.longest_us = status.longest_us.max(duration_us);
+        if duration_us as u64 > IO_SCAN_INTERVAL.as_millis() * 1_000 {
+            status.overruns += 1;
+        }
+        status.failed_reads += failed_reads;
+        status.failed_writes += failed_writes;
+
+        self.durations.write(duration_us);
+        let total = self
+            .durations
+            .iter()
+            .map(|&duration| duration as u64)
+            .sum::<u64>();
+        self.status.mean_us = (total / self.durations.len() as u64) as u32;
+    }
+
+    /// Records that the next scan was started by an input change rather than the interval.
+    pub fn record_change_wakeup(&mut self) {
+        self.status.change_wakeups += 1;
+    }
+
+    pub fn status(&self) -> IoScanStatus {
+        self.status
+    }
+}
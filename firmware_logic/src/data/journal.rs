@@ -0,0 +1,200 @@
+use heapless::Vec;
+use ringbuffer::{ConstGenericRingBuffer, RingBuffer};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Command, Configuration, HardwareDigital, IoState, IoSupply, IoTemp, OutputFault, Timestamp,
+    Wingman2HardwareStatus, DIGITAL_INPUTS,
+};
+
+const JOURNAL_CAPACITY: usize = 128;
+/// Entries sent per datagram, keeps a page well below the UDP payload limit.
+pub const JOURNAL_PAGE_SIZE: usize = 24;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct JournalEntry {
+    /// Increments with every entry, starts over at 0 on reboot.
+    pub sequence: u32,
+    pub timestamp: Timestamp,
+    pub device: Device,
+    pub event: JournalEvent,
+    pub reason: Reason,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Device {
+    /// The card itself.
+    Card,
+    DigitalInput(u8),
+    DigitalOutput(u8),
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum JournalEvent {
+    Reboot,
+    ConfigurationChanged(Configuration),
+    CommandApplied(Command),
+    InterlockTripped(Command),
+    FaultRaised,
+    FaultCleared,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Reason {
+    Startup,
+    Settings,
+    ControlServer,
+    Interlock,
+    OpenOrFault,
+    Overtemperature,
+    SupplyLost,
+    /// The output did not follow the level commanded, see `OutputFault::Stuck`.
+    OutputStuck,
+}
+
+/// Entries sent to the control server, starting at the sequence it asked for.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct JournalPage {
+    /// Changes when the card reboots, the sequences start over then.
+    pub boot_id: u32,
+    /// Oldest sequence still in the journal, anything before it was overwritten.
+    pub first_sequence: u32,
+    /// Sequence the next entry will get.
+    pub next_sequence: u32,
+    pub entries: Vec<JournalEntry, JOURNAL_PAGE_SIZE>,
+}
+
+/// Fixed capacity record of what happened on the card, oldest entries are overwritten.
+#[derive(Default, Clone)]
+pub struct Journal {
+    boot_id: u32,
+    entries: ConstGenericRingBuffer<JournalEntry, JOURNAL_CAPACITY>,
+    next_sequence: u32,
+}
+
+impl Journal {
+    /// `boot_id` tells this boot from the previous ones, e.g. a random number.
+    pub fn new(boot_id: u32) -> Self {
+        Self {
+            boot_id,
+            ..Self::default()
+        }
+    }
+
+    pub fn record(&mut self, timestamp: Timestamp, device: Device, event: JournalEvent, reason: Reason) {
+        self.entries.push(JournalEntry {
+            sequence: self.next_sequence,
+            timestamp,
+            device,
+            event,
+            reason,
+        });
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+    }
+
+    pub fn first_sequence(&self) -> u32 {
+        self.entries
+            .front()
+            .map_or(self.next_sequence, |entry| entry.sequence)
+    }
+
+    pub fn next_sequence(&self) -> u32 {
+        self.next_sequence
+    }
+
+    pub fn boot_id(&self) -> u32 {
+        self.boot_id
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &JournalEntry> {
+        self.entries.iter()
+    }
+
+    /// Returns up to `JOURNAL_PAGE_SIZE` entries starting at `sequence`.
+    pub fn page_from(&self, sequence: u32) -> JournalPage {
+        let mut page = JournalPage {
+            boot_id: self.boot_id,
+            first_sequence: self.first_sequence(),
+            next_sequence: self.next_sequence,
+            entries: Vec::new(),
+        };
+        for entry in self.entries.iter().filter(|entry| entry.sequence >= sequence) {
+            if page.entries.push(*entry).is_err() {
+                break;
+            }
+        }
+        page
+    }
+}
+
+/// Journals faults as they are raised and cleared on the digital channels.
+#[derive(Clone)]
+pub struct FaultMonitor {
+    inputs: [u8; DIGITAL_INPUTS],
+    outputs: [u8; 48],
+}
+
+impl Default for FaultMonitor {
+    fn default() -> Self {
+        Self {
+            inputs: [0; DIGITAL_INPUTS],
+            outputs: [0; 48],
+        }
+    }
+}
+
+impl FaultMonitor {
+    const FAULTS: [(u8, Reason); 4] = [
+        (1 << 0, Reason::OpenOrFault),
+        (1 << 1, Reason::Overtemperature),
+        (1 << 2, Reason::SupplyLost),
+        (1 << 3, Reason::OutputStuck),
+    ];
+
+    pub fn update(&mut self, hardware_status: &Wingman2HardwareStatus, journal: &mut Journal) {
+        let now = hardware_status.now;
+        let inputs = self.inputs.iter_mut().zip(&hardware_status.digital_inputs);
+        for (address, (faults, digital)) in inputs.enumerate() {
+            Self::update_channel(faults, digital, now, Device::DigitalInput(address as u8), journal);
+        }
+        let outputs = self.outputs.iter_mut().zip(&hardware_status.digital_outputs);
+        for (address, (faults, digital)) in outputs.enumerate() {
+            Self::update_channel(faults, digital, now, Device::DigitalOutput(address as u8), journal);
+        }
+    }
+
+    fn update_channel(
+        faults: &mut u8,
+        digital: &HardwareDigital,
+        now: Timestamp,
+        device: Device,
+        journal: &mut Journal,
+    ) {
+        let current = Self::faults(digital);
+        for (fault, reason) in Self::FAULTS {
+            match (*faults & fault != 0, current & fault != 0) {
+                (false, true) => journal.record(now, device, JournalEvent::FaultRaised, reason),
+                (true, false) => journal.record(now, device, JournalEvent::FaultCleared, reason),
+                _ => {}
+            }
+        }
+        *faults = current;
+    }
+
+    fn faults(digital: &HardwareDigital) -> u8 {
+        let mut faults = 0;
+        if digital.state == Some(IoState::OpenOrFault) {
+            faults |= Self::FAULTS[0].0;
+        }
+        if digital.temp == Some(IoTemp::Hot) {
+            faults |= Self::FAULTS[1].0;
+        }
+        if digital.supply == Some(IoSupply::Unpowered) {
+            faults |= Self::FAULTS[2].0;
+        }
+        if digital.fault == Some(OutputFault::Stuck) {
+            faults |= Self::FAULTS[3].0;
+        }
+        faults
+    }
+}
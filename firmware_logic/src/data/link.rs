@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Timestamp;
+
+/// The PHY is read at this interval, reading it takes a few MDIO transactions.
+pub const LINK_POLL_INTERVAL: Timestamp = Timestamp::new(250);
+/// Autonegotiation is restarted when the link stayed down this long, then again at this interval.
+pub const RENEGOTIATE_AFTER: Timestamp = Timestamp::new(3_000);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LinkSpeed {
+    Mbps10,
+    Mbps100,
+}
+
+/// Mode negotiated with the link partner, the MAC has to be configured to match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkMode {
+    pub speed: LinkSpeed,
+    pub full_duplex: bool,
+}
+
+/// Ethernet link of the card, see `LinkMonitor`.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkStatus {
+    /// `None` while the link is down.
+    pub mode: Option<LinkMode>,
+    /// Times the link went down since boot, e.g. the cable was pulled.
+    pub drops: u32,
+    /// Autonegotiations restarted since boot while the link was down.
+    pub renegotiations: u32,
+}
+
+/// The PHY as seen by `LinkMonitor`.
+pub trait LinkPhy {
+    /// The negotiated mode once the link is up and autonegotiation completed, `None` otherwise.
+    fn link_mode(&mut self) -> Option<LinkMode>;
+
+    fn restart_autonegotiation(&mut self);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkEvent {
+    /// The link came up, or was renegotiated, in this mode.
+    Up(LinkMode),
+    Down,
+}
+
+/// Follows the Ethernet link by polling the PHY, restarting autonegotiation while it is down.
+#[derive(Default)]
+pub struct LinkMonitor {
+    status: LinkStatus,
+    latest_poll: Option<Timestamp>,
+    /// Down since, or autonegotiation last restarted at.
+    down_since: Option<Timestamp>,
+}
+
+impl LinkMonitor {
+    /// Polls `phy` when due, call periodically. Returns how the link changed, the MAC has to be
+    /// configured for the mode of an `Up`.
+    pub fn poll(&mut self, now: Timestamp, phy: &mut impl LinkPhy) -> Option<LinkEvent> {
+        if self
+            .latest_poll
+            .is_some_and(|latest| now < latest + LINK_POLL_INTERVAL)
+        {
+            return None;
+        }
+        self.latest_poll = Some(now);
+
+        match (self.status.mode, phy.link_mode()) {
+            (current, Some(mode)) => {
+                if current == Some(mode) {
+                    return None;
+                }
+                self.status.mode = Some(mode);
+                self.down_since = None;
+                Some(LinkEvent::Up(mode))
+            }
+            (Some(_), None) => {
+                self.status.mode = None;
+                self.status.drops += 1;
+                self.down_since = Some(now);
+                Some(LinkEvent::Down)
+            }
+            (None, None) => {
+                let down_since = *self.down_since.get_or_insert(now);
+                if now >= down_since + RENEGOTIATE_AFTER {
+                    phy.restart_autonegotiation();
+                    self.status.renegotiations += 1;
+                    self.down_since = Some(now);
+                }
+                None
+            }
+        }
+    }
+
+    pub fn status(&self) -> &LinkStatus {
+        &self.status
+    }
+}
@@ -1 +1,14 @@
+pub mod boot;
+pub mod connection;
+pub mod frames;
+pub mod image;
+pub mod io_population;
+pub mod io_scan;
+pub mod journal;
+pub mod link;
+pub mod ping;
+pub mod recording;
+pub mod settings;
+pub mod status_report;
+pub mod update;
 pub mod user_commands;
@@ -0,0 +1,148 @@
+use heapless::{Deque, HistoryBuffer};
+use serde::{Deserialize, Serialize};
+
+use crate::Timestamp;
+
+/// Echo requests the statistics are computed over.
+pub const PING_WINDOW: usize = 16;
+
+/// Round trips up to this long do not lower the QoS.
+const GOOD_ROUND_TRIP_MS: u32 = 20;
+/// Round trips this long or longer bring the QoS down to 0.
+const BAD_ROUND_TRIP_MS: u32 = 500;
+
+/// Reachability of the control server measured by pinging it, reported to the server.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PingStatistics {
+    /// Mean round trip of the requests answered, `None` if none was.
+    pub round_trip_ms: Option<u32>,
+    /// Share of the requests of the window that were lost.
+    pub loss_percent: u8,
+    /// Requests accounted for, up to `PING_WINDOW`.
+    pub samples: u8,
+}
+
+impl PingStatistics {
+    /// Rates the reachability from 0, unreachable or not measured yet, to 100: the share of
+    /// requests answered, lowered as the round trip grows from `GOOD_ROUND_TRIP_MS` to
+    /// `BAD_ROUND_TRIP_MS`.
+    pub fn qos(&self) -> u8 {
+        let Some(round_trip) = self.round_trip_ms else {
+            return 0;
+        };
+        let answered = 100 - self.loss_percent as u32;
+        let latency_penalty = round_trip
+            .saturating_sub(GOOD_ROUND_TRIP_MS)
+            .min(BAD_ROUND_TRIP_MS - GOOD_ROUND_TRIP_MS);
+        let latency_factor = BAD_ROUND_TRIP_MS - GOOD_ROUND_TRIP_MS - latency_penalty;
+        (answered * latency_factor / (BAD_ROUND_TRIP_MS - GOOD_ROUND_TRIP_MS)) as u8
+    }
+}
+
+/// Pings the control server at an interval and rates its reachability from the replies.
+///
+/// A request not answered within `timeout` is counted as lost, a late reply is ignored.
+pub struct ServerProbe {
+    interval: Timestamp,
+    timeout: Timestamp,
+    next_sequence: u16,
+    latest_request: Option<Timestamp>,
+    /// Sequence and send time of the requests awaiting a reply.
+    pending: Deque<(u16, Timestamp), PING_WINDOW>,
+    /// Round trip of the latest requests, `None` for the lost ones.
+    outcomes: HistoryBuffer<Option<u32>, PING_WINDOW>,
+}
+
+impl ServerProbe {
+    pub const fn new(interval: Timestamp, timeout: Timestamp) -> Self {
+        Self {
+            interval,
+            timeout,
+            next_sequence: 0,
+            latest_request: None,
+            pending: Deque::new(),
+            outcomes: HistoryBuffer::new(),
+        }
+    }
+
+    /// Returns the sequence number of the echo request to send at `now`, if one is due. Requests
+    /// that timed out are counted as lost first.
+    pub fn poll(&mut self, now: Timestamp) -> Option<u16> {
+        while let Some(&(_, sent_at)) = self.pending.front() {
+            if now < sent_at + self.timeout {
+                break;
+            }
+            self.pending.pop_front();
+            self.outcomes.write(None);
+        }
+
+        if self
+            .latest_request
+            .is_some_and(|latest| now < latest + self.interval)
+        {
+            return None;
+        }
+        self.latest_request = Some(now);
+        let sequence = self.next_sequence;
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        if self.pending.is_full() {
+            self.pending.pop_front();
+            self.outcomes.write(None);
+        }
+        // Cannot fail, room was made above
+        let _ = self.pending.push_back((sequence, now));
+        Some(sequence)
+    }
+
+    /// Records the reply to the request `sequence`, received at `now`.
+    pub fn reply(&mut self, sequence: u16, now: Timestamp) {
+        let Some((index, sent_at)) = self
+            .pending
+            .iter()
+            .enumerate()
+            .find(|(_, &(pending, _))| pending == sequence)
+            .map(|(index, &(_, sent_at))| (index, sent_at))
+        else {
+            return;
+        };
+        // Replies come back in order, the requests sent before this one were lost
+        for _ in 0..index {
+            self.pending.pop_front();
+            self.outcomes.write(None);
+        }
+        self.pending.pop_front();
+        let round_trip = now.as_millis().saturating_sub(sent_at.as_millis());
+        self.outcomes.write(Some(round_trip as u32));
+    }
+
+    /// Forgets every request, e.g. when the card moved to another address.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.outcomes.clear();
+        self.latest_request = None;
+    }
+
+    pub fn statistics(&self) -> PingStatistics {
+        let samples = self.outcomes.len();
+        let (answered, total_round_trip) = self
+            .outcomes
+            .iter()
+            .flatten()
+            .fold((0u32, 0u32), |(count, total), round_trip| {
+                (count + 1, total.saturating_add(*round_trip))
+            });
+        PingStatistics {
+            round_trip_ms: (answered > 0).then(|| total_round_trip / answered),
+            loss_percent: match samples {
+                0 => 0,
+                _ => (100 * (samples as u32 - answered) / samples as u32) as u8,
+            },
+            samples: samples as u8,
+        }
+    }
+
+    /// See `PingStatistics::qos`.
+    pub fn qos(&self) -> u8 {
+        self.statistics().qos()
+    }
+}
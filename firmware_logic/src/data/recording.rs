@@ -0,0 +1,148 @@
+use heapless::Vec;
+use ringbuffer::{ConstGenericRingBuffer, RingBuffer};
+use serde::{Deserialize, Serialize};
+use serde_big_array::BigArray;
+use uom::si::electric_potential::volt;
+use uom::si::f32::ElectricPotential;
+
+use crate::{
+    data::status_report::PackedDigital, Configuration, HardwareDigital, IoLevel, Timestamp,
+    UserCommand, Wingman2HardwareStatus, DIGITAL_INPUTS,
+};
+
+/// Commands recorded per cycle, any more in a single cycle are left out of the recording.
+pub const RECORDED_COMMANDS: usize = 8;
+/// Cycles kept until they are sent, older ones are dropped.
+const PENDING_CYCLES: usize = 8;
+
+/// One `apply_logic` cycle: what the logic was given and which outputs it drove.
+///
+/// A recording is a sequence of cycles, on the wire and in files each one is a COBS framed
+/// postcard message.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordedCycle {
+    pub step: u64,
+    pub now: Timestamp,
+    pub configuration: Configuration,
+    /// Commands queued since the previous cycle, with the timestamp they were received at.
+    pub commands: Vec<UserCommand, RECORDED_COMMANDS>,
+    #[serde(with = "BigArray")]
+    pub digital_inputs: [PackedDigital; DIGITAL_INPUTS],
+    /// State, supply and temperature read back from the outputs, without the level.
+    #[serde(with = "BigArray")]
+    pub output_diagnostics: [PackedDigital; 48],
+    /// Volts.
+    #[serde(with = "BigArray")]
+    pub analog_inputs: [f32; 48],
+    /// Outputs as driven by the logic at the end of the cycle.
+    #[serde(with = "BigArray")]
+    pub digital_outputs: [PackedDigital; 48],
+}
+
+impl RecordedCycle {
+    fn begin(hardware_status: &Wingman2HardwareStatus, commands: Vec<UserCommand, RECORDED_COMMANDS>) -> Self {
+        Self {
+            step: hardware_status.step,
+            now: hardware_status.now,
+            configuration: hardware_status.configuration,
+            commands,
+            digital_inputs: core::array::from_fn(|index| {
+                (&hardware_status.digital_inputs[index]).into()
+            }),
+            output_diagnostics: core::array::from_fn(|index| {
+                PackedDigital::from(&hardware_status.digital_outputs[index]).without_level()
+            }),
+            analog_inputs: core::array::from_fn(|index| {
+                hardware_status.analog_inputs[index].voltage.get::<volt>()
+            }),
+            digital_outputs: [PackedDigital::default(); 48],
+        }
+    }
+
+    /// Restores the inputs of the cycle, leaving the output levels to the logic being replayed.
+    pub fn restore_inputs(&self, hardware_status: &mut Wingman2HardwareStatus) {
+        hardware_status.step = self.step;
+        hardware_status.now = self.now;
+        hardware_status.configuration = self.configuration;
+        for (digital, packed) in hardware_status.digital_inputs.iter_mut().zip(self.digital_inputs) {
+            *digital = packed.into();
+        }
+        for (digital, packed) in hardware_status.digital_outputs.iter_mut().zip(self.output_diagnostics) {
+            *digital = HardwareDigital {
+                level: digital.level,
+                ..packed.into()
+            };
+        }
+        for (analog, volts) in hardware_status.analog_inputs.iter_mut().zip(self.analog_inputs) {
+            analog.voltage = ElectricPotential::new::<volt>(volts);
+        }
+    }
+
+    /// Level of the output at `address` at the end of the cycle.
+    pub fn output_level(&self, address: usize) -> Option<IoLevel> {
+        HardwareDigital::from(*self.digital_outputs.get(address)?).level
+    }
+}
+
+/// Records the `apply_logic` cycles while enabled by the control server.
+#[derive(Default)]
+pub struct Recorder {
+    enabled: bool,
+    pending_commands: Vec<UserCommand, RECORDED_COMMANDS>,
+    current: Option<RecordedCycle>,
+    cycles: ConstGenericRingBuffer<RecordedCycle, PENDING_CYCLES>,
+    dropped_cycles: u32,
+}
+
+impl Recorder {
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.pending_commands.clear();
+            self.current = None;
+        }
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Cycles overwritten before they were sent.
+    pub fn dropped_cycles(&self) -> u32 {
+        self.dropped_cycles
+    }
+
+    /// Records a command that was queued for the logic.
+    pub fn record_command(&mut self, user_command: UserCommand) {
+        if self.enabled {
+            let _ = self.pending_commands.push(user_command);
+        }
+    }
+
+    /// Called once the hardware status was read, before the logic runs.
+    pub fn begin_cycle(&mut self, hardware_status: &Wingman2HardwareStatus) {
+        if self.enabled {
+            let commands = core::mem::take(&mut self.pending_commands);
+            self.current = Some(RecordedCycle::begin(hardware_status, commands));
+        }
+    }
+
+    /// Called once the logic ran, before the outputs are written to the hardware.
+    pub fn end_cycle(&mut self, hardware_status: &Wingman2HardwareStatus) {
+        let Some(mut cycle) = self.current.take() else {
+            return;
+        };
+        for (packed, digital) in cycle.digital_outputs.iter_mut().zip(&hardware_status.digital_outputs) {
+            *packed = digital.into();
+        }
+        if self.cycles.is_full() {
+            self.dropped_cycles = self.dropped_cycles.wrapping_add(1);
+        }
+        self.cycles.push(cycle);
+    }
+
+    /// Oldest recorded cycle not taken yet.
+    pub fn pop(&mut self) -> Option<RecordedCycle> {
+        self.cycles.dequeue()
+    }
+}
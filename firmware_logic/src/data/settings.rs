@@ -0,0 +1,214 @@
+use crc::{Crc, CRC_32_ISO_HDLC, CRC_64_XZ};
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+use serde::{Deserialize, Serialize};
+
+use crate::Configuration;
+
+/// Bumped whenever `Settings` changes in a way older firmware cannot decode.
+pub const SETTINGS_VERSION: u16 = 1;
+
+/// Space taken by one copy, header and CRC included. A multiple of any flash write size.
+const RECORD_SIZE: usize = 256;
+const HEADER_SIZE: usize = 12;
+const CRC_SIZE: usize = 4;
+const MAGIC: u32 = u32::from_le_bytes(*b"WMST");
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Everything the card keeps across reboots.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct Settings {
+    pub configuration: Configuration,
+    /// Assigned when the card is fitted to a vessel, 0 until then.
+    pub card_id: u32,
+    pub network: NetworkSettings,
+    pub tunables: Tunables,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NetworkSettings {
+    /// Overrides the MAC address derived from the unique ID of the microcontroller.
+    pub mac_address: Option<[u8; 6]>,
+    /// Asks a DHCP server for an address first. The card has no address until a lease arrives, or
+    /// until it falls back to the static one below after ten seconds without one.
+    pub dhcp: bool,
+    /// Static address, used when DHCP is off or no lease was obtained in time.
+    pub ip_address: [u8; 4],
+    pub prefix_length: u8,
+    pub gateway: Option<[u8; 4]>,
+    pub control_server: [u8; 4],
+    /// UDP port of the control server, status and reporting are sent to it.
+    pub control_server_port: u16,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            mac_address: None,
+            dhcp: true,
+            ip_address: [10, 0, 90, 194],
+            prefix_length: 24,
+            gateway: None,
+            control_server: [10, 0, 90, 149],
+            control_server_port: 6972,
+        }
+    }
+}
+
+impl NetworkSettings {
+    /// The MAC address of the card, `unique_id` is the 96-bit unique ID of the microcontroller.
+    pub fn mac_address(&self, unique_id: &[u8; 12]) -> [u8; 6] {
+        self.mac_address
+            .unwrap_or_else(|| derive_mac_address(unique_id))
+    }
+}
+
+/// Locally administered unicast address hashed from the unique ID, the same on every boot.
+pub fn derive_mac_address(unique_id: &[u8; 12]) -> [u8; 6] {
+    let hash = Crc::<u64>::new(&CRC_64_XZ)
+        .checksum(unique_id)
+        .to_le_bytes();
+    let mut mac_address = [0u8; 6];
+    mac_address.copy_from_slice(&hash[..6]);
+    mac_address[0] = (mac_address[0] & 0b1111_1100) | 0b10;
+    mac_address
+}
+
+/// Timings that may be adjusted per vessel. Safety limits such as `ENGINE_MAX_CRANK` are not.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Tunables {
+    /// The control server is deemed disconnected after this long without a datagram from it.
+    pub control_server_timeout_ms: u32,
+    /// Interval between two `FirmwareReporting` datagrams.
+    pub reporting_period_ms: u32,
+}
+
+impl Default for Tunables {
+    fn default() -> Self {
+        Self {
+            control_server_timeout_ms: 5_000,
+            reporting_period_ms: 25,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SettingsError<E> {
+    /// The settings do not fit a record.
+    TooLarge,
+    Flash(E),
+}
+
+/// Keeps `Settings` in two copies, each in its own erase sector.
+///
+/// A save always goes to the copy that was not loaded, and carries a higher sequence, so losing
+/// power while saving leaves the previous copy intact. Each copy is checked with a CRC on load.
+///
+/// Record layout, little endian: magic, version `u16`, payload length `u16`, sequence `u32`,
+/// postcard payload, CRC-32 of everything before it.
+pub struct SettingsStore {
+    offsets: [u32; 2],
+    /// Copy holding the settings last loaded or saved, and its sequence.
+    current: Option<(usize, u32)>,
+}
+
+impl SettingsStore {
+    /// `first` and `second` are the offsets of two distinct erase sectors of the flash.
+    pub const fn new(first: u32, second: u32) -> Self {
+        Self {
+            offsets: [first, second],
+            current: None,
+        }
+    }
+
+    /// Reads the most recent valid copy, `None` if neither copy is valid, e.g. on a blank card.
+    pub fn load<F: ReadNorFlash>(&mut self, flash: &mut F) -> Result<Option<Settings>, F::Error> {
+        self.current = None;
+        let mut latest = None;
+        for (copy, offset) in self.offsets.into_iter().enumerate() {
+            let mut record = [0u8; RECORD_SIZE];
+            flash.read(offset, &mut record)?;
+            let Some((sequence, settings)) = decode_record(&record) else {
+                continue;
+            };
+            if self.current.is_none_or(|(_, current)| sequence > current) {
+                self.current = Some((copy, sequence));
+                latest = Some(settings);
+            }
+        }
+        Ok(latest)
+    }
+
+    /// Writes `settings` over the copy that is not current, call `load` first.
+    pub fn save<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        settings: &Settings,
+    ) -> Result<(), SettingsError<F::Error>> {
+        let (copy, sequence) = match self.current {
+            Some((copy, sequence)) => (1 - copy, sequence.wrapping_add(1)),
+            None => (0, 0),
+        };
+
+        let mut record = [0xFFu8; RECORD_SIZE];
+        let length = encode_record(settings, sequence, &mut record)?;
+        let length = length.next_multiple_of(F::WRITE_SIZE);
+        if length > RECORD_SIZE {
+            return Err(SettingsError::TooLarge);
+        }
+
+        let offset = self.offsets[copy];
+        flash
+            .erase(offset, offset + F::ERASE_SIZE as u32)
+            .map_err(SettingsError::Flash)?;
+        flash
+            .write(offset, &record[..length])
+            .map_err(SettingsError::Flash)?;
+        self.current = Some((copy, sequence));
+        Ok(())
+    }
+}
+
+/// Returns the length of the record, header and CRC included.
+fn encode_record<E>(
+    settings: &Settings,
+    sequence: u32,
+    record: &mut [u8; RECORD_SIZE],
+) -> Result<usize, SettingsError<E>> {
+    let payload = postcard::to_slice(settings, &mut record[HEADER_SIZE..RECORD_SIZE - CRC_SIZE])
+        .map_err(|_| SettingsError::TooLarge)?;
+    let payload_length = payload.len();
+
+    record[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+    record[4..6].copy_from_slice(&SETTINGS_VERSION.to_le_bytes());
+    record[6..8].copy_from_slice(&(payload_length as u16).to_le_bytes());
+    record[8..12].copy_from_slice(&sequence.to_le_bytes());
+    let crc_offset = HEADER_SIZE + payload_length;
+    let crc = CRC.checksum(&record[..crc_offset]);
+    record[crc_offset..crc_offset + CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+    Ok(crc_offset + CRC_SIZE)
+}
+
+fn decode_record(record: &[u8; RECORD_SIZE]) -> Option<(u32, Settings)> {
+    let field = |range: core::ops::Range<usize>| &record[range];
+    let magic = u32::from_le_bytes(field(0..4).try_into().ok()?);
+    let version = u16::from_le_bytes(field(4..6).try_into().ok()?);
+    let payload_length = u16::from_le_bytes(field(6..8).try_into().ok()?) as usize;
+    let sequence = u32::from_le_bytes(field(8..12).try_into().ok()?);
+    if magic != MAGIC || payload_length > RECORD_SIZE - HEADER_SIZE - CRC_SIZE {
+        return None;
+    }
+
+    let crc_offset = HEADER_SIZE + payload_length;
+    let crc = u32::from_le_bytes(field(crc_offset..crc_offset + CRC_SIZE).try_into().ok()?);
+    if crc != CRC.checksum(&record[..crc_offset]) {
+        return None;
+    }
+
+    let payload = &record[HEADER_SIZE..crc_offset];
+    let settings = match version {
+        SETTINGS_VERSION => postcard::from_bytes(payload).ok()?,
+        // Older versions are converted here once there are any
+        _ => return None,
+    };
+    Some((sequence, settings))
+}
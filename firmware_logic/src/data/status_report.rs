@@ -0,0 +1,299 @@
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+use uom::si::electric_potential::volt;
+use uom::si::f32::ElectricPotential;
+
+use crate::{
+    HardwareAnalog, HardwareDigital, IoLevel, IoState, IoSupply, IoTemp, OutputFault, Timestamp,
+    Wingman2HardwareStatus, DIGITAL_INPUTS,
+};
+
+const DIGITAL_OUTPUTS: usize = 48;
+const ANALOG_CHANNELS: usize = 48;
+
+/// Status sent to the control server over UDP.
+///
+/// A snapshot is sent periodically, deltas in between only carry the channels that changed
+/// since that snapshot. A lost delta is therefore superseded by the next one.
+// Reports are serialized right away, boxing the snapshot would need an allocator
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum StatusReport {
+    Snapshot(Wingman2HardwareStatus),
+    Delta(StatusDelta),
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StatusDelta {
+    pub step: u64,
+    pub now: Timestamp,
+    /// Step of the snapshot the changes are relative to.
+    pub snapshot_step: u64,
+    pub digital_inputs: DigitalChanges,
+    pub digital_outputs: DigitalChanges,
+    pub analog_inputs: AnalogChanges,
+    pub analog_outputs: AnalogChanges,
+}
+
+impl StatusDelta {
+    /// Applies the delta on top of the snapshot it was computed from.
+    /// Returns false and leaves `status` untouched if it is based on another snapshot.
+    pub fn apply_to(&self, snapshot: &Wingman2HardwareStatus, status: &mut Wingman2HardwareStatus) -> bool {
+        if snapshot.step != self.snapshot_step {
+            return false;
+        }
+
+        status.clone_from(snapshot);
+        status.step = self.step;
+        status.now = self.now;
+        self.digital_inputs.apply_to(&mut status.digital_inputs);
+        self.digital_outputs.apply_to(&mut status.digital_outputs);
+        self.analog_inputs.apply_to(&mut status.analog_inputs);
+        self.analog_outputs.apply_to(&mut status.analog_outputs);
+        true
+    }
+}
+
+/// Changed digital channels, bit `n` of `changed` is set if channel `n` is part of `channels`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DigitalChanges {
+    pub changed: u128,
+    pub channels: Vec<PackedDigital, DIGITAL_INPUTS>,
+}
+
+impl DigitalChanges {
+    fn between(snapshot: &[HardwareDigital], current: &[HardwareDigital]) -> Self {
+        let mut changes = Self::default();
+        for (index, (before, after)) in snapshot.iter().zip(current).enumerate() {
+            let after = PackedDigital::from(after);
+            if PackedDigital::from(before) != after {
+                changes.changed |= 1 << index;
+                // Cannot overflow, there are no more channels than capacity.
+                let _ = changes.channels.push(after);
+            }
+        }
+        changes
+    }
+
+    fn apply_to(&self, digitals: &mut [HardwareDigital]) {
+        let changed = (0..digitals.len()).filter(|index| self.changed & (1 << index) != 0);
+        for (index, packed) in changed.zip(&self.channels) {
+            digitals[index] = (*packed).into();
+        }
+    }
+}
+
+/// Changed analog channels, bit `n` of `changed` is set if channel `n` is part of `voltages`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AnalogChanges {
+    pub changed: u64,
+    pub voltages: Vec<f32, ANALOG_CHANNELS>,
+}
+
+impl AnalogChanges {
+    /// Changes smaller than this are considered noise and not reported until the next snapshot.
+    const DEADBAND_VOLTS: f32 = 0.05;
+
+    fn between(snapshot: &[HardwareAnalog], current: &[HardwareAnalog]) -> Self {
+        let mut changes = Self::default();
+        for (index, (before, after)) in snapshot.iter().zip(current).enumerate() {
+            let after = after.voltage.get::<volt>();
+            let difference = before.voltage.get::<volt>() - after;
+            if !(-Self::DEADBAND_VOLTS..=Self::DEADBAND_VOLTS).contains(&difference) {
+                changes.changed |= 1 << index;
+                let _ = changes.voltages.push(after);
+            }
+        }
+        changes
+    }
+
+    fn apply_to(&self, analogs: &mut [HardwareAnalog]) {
+        let changed = (0..analogs.len()).filter(|index| self.changed & (1 << index) != 0);
+        for (index, voltage) in changed.zip(&self.voltages) {
+            analogs[index].voltage = ElectricPotential::new::<volt>(*voltage);
+        }
+    }
+}
+
+/// `HardwareDigital` packed into 11 bits:
+/// level in bits 0-1, state in bits 2-3, supply in bits 4-6, temp in bits 7-8 and fault in
+/// bits 9-10.
+/// Zero means unknown for every field.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackedDigital(pub u16);
+
+impl From<&HardwareDigital> for PackedDigital {
+    fn from(digital: &HardwareDigital) -> Self {
+        let level = match digital.level {
+            None => 0,
+            Some(IoLevel::High) => 1,
+            Some(IoLevel::Low) => 2,
+            Some(IoLevel::HiZ) => 3,
+        };
+        let state = match digital.state {
+            None => 0,
+            Some(IoState::Normal) => 1,
+            Some(IoState::OpenOrFault) => 2,
+        };
+        let supply = match digital.supply {
+            None => 0,
+            Some(IoSupply::Volts24) => 1,
+            Some(IoSupply::Volts12) => 2,
+            Some(IoSupply::Powered) => 3,
+            Some(IoSupply::Unpowered) => 4,
+        };
+        let temp = match digital.temp {
+            None => 0,
+            Some(IoTemp::Normal) => 1,
+            Some(IoTemp::Warm) => 2,
+            Some(IoTemp::Hot) => 3,
+        };
+        let fault = match digital.fault {
+            None => 0,
+            Some(OutputFault::Stuck) => 1,
+            Some(OutputFault::OpenLoad) => 2,
+            Some(OutputFault::Overload) => 3,
+        };
+        Self(level | state << 2 | supply << 4 | temp << 7 | fault << 9)
+    }
+}
+
+impl From<PackedDigital> for HardwareDigital {
+    fn from(packed: PackedDigital) -> Self {
+        let PackedDigital(bits) = packed;
+        Self {
+            level: match bits & 0b11 {
+                1 => Some(IoLevel::High),
+                2 => Some(IoLevel::Low),
+                3 => Some(IoLevel::HiZ),
+                _ => None,
+            },
+            state: match (bits >> 2) & 0b11 {
+                1 => Some(IoState::Normal),
+                2 => Some(IoState::OpenOrFault),
+                _ => None,
+            },
+            supply: match (bits >> 4) & 0b111 {
+                1 => Some(IoSupply::Volts24),
+                2 => Some(IoSupply::Volts12),
+                3 => Some(IoSupply::Powered),
+                4 => Some(IoSupply::Unpowered),
+                _ => None,
+            },
+            temp: match (bits >> 7) & 0b11 {
+                1 => Some(IoTemp::Normal),
+                2 => Some(IoTemp::Warm),
+                3 => Some(IoTemp::Hot),
+                _ => None,
+            },
+            fault: match (bits >> 9) & 0b11 {
+                1 => Some(OutputFault::Stuck),
+                2 => Some(OutputFault::OpenLoad),
+                3 => Some(OutputFault::Overload),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl PackedDigital {
+    const LEVEL_MASK: u16 = 0b11;
+
+    /// Everything but the level, i.e. whatever indicates a fault on the channel.
+    fn fault_bits(self) -> u16 {
+        self.0 & !Self::LEVEL_MASK
+    }
+
+    pub(crate) fn without_level(self) -> Self {
+        Self(self.fault_bits())
+    }
+}
+
+/// Decides when to send a snapshot or a delta of the hardware status.
+pub struct StatusReporter {
+    snapshot: Option<Wingman2HardwareStatus>,
+    latest_report: Timestamp,
+    reported_outputs: [PackedDigital; DIGITAL_OUTPUTS],
+    reported_input_faults: [u16; DIGITAL_INPUTS],
+}
+
+impl Default for StatusReporter {
+    fn default() -> Self {
+        Self {
+            snapshot: None,
+            latest_report: Timestamp::default(),
+            reported_outputs: [PackedDigital::default(); DIGITAL_OUTPUTS],
+            reported_input_faults: [0; DIGITAL_INPUTS],
+        }
+    }
+}
+
+impl StatusReporter {
+    const SNAPSHOT_PERIOD: Timestamp = Timestamp::new(1_000);
+    const DELTA_PERIOD: Timestamp = Timestamp::new(25);
+
+    /// Returns the report to send for `status`, if any.
+    ///
+    /// Deltas are sent every `DELTA_PERIOD`, or right away when an output or a fault changed.
+    pub fn report(&mut self, status: &Wingman2HardwareStatus) -> Option<StatusReport> {
+        let snapshot = match &self.snapshot {
+            Some(snapshot) if status.now < snapshot.now + Self::SNAPSHOT_PERIOD => snapshot,
+            _ => {
+                self.mark_reported(status);
+                self.snapshot = Some(status.clone());
+                return Some(StatusReport::Snapshot(status.clone()));
+            }
+        };
+
+        if !self.has_urgent_changes(status) && status.now < self.latest_report + Self::DELTA_PERIOD {
+            return None;
+        }
+
+        let delta = StatusDelta {
+            step: status.step,
+            now: status.now,
+            snapshot_step: snapshot.step,
+            digital_inputs: DigitalChanges::between(&snapshot.digital_inputs, &status.digital_inputs),
+            digital_outputs: DigitalChanges::between(
+                &snapshot.digital_outputs,
+                &status.digital_outputs,
+            ),
+            analog_inputs: AnalogChanges::between(&snapshot.analog_inputs, &status.analog_inputs),
+            analog_outputs: AnalogChanges::between(
+                &snapshot.analog_outputs,
+                &status.analog_outputs,
+            ),
+        };
+        self.mark_reported(status);
+        Some(StatusReport::Delta(delta))
+    }
+
+    /// Forces a snapshot on the next report, e.g. after the control server reconnected.
+    pub fn request_snapshot(&mut self) {
+        self.snapshot = None;
+    }
+
+    fn has_urgent_changes(&self, status: &Wingman2HardwareStatus) -> bool {
+        let outputs_changed = status
+            .digital_outputs
+            .iter()
+            .zip(&self.reported_outputs)
+            .any(|(output, reported)| PackedDigital::from(output) != *reported);
+        let input_faults_changed = status
+            .digital_inputs
+            .iter()
+            .zip(&self.reported_input_faults)
+            .any(|(input, reported)| PackedDigital::from(input).fault_bits() != *reported);
+        outputs_changed || input_faults_changed
+    }
+
+    fn mark_reported(&mut self, status: &Wingman2HardwareStatus) {
+        self.latest_report = status.now;
+        for (reported, output) in self.reported_outputs.iter_mut().zip(&status.digital_outputs) {
+            *reported = output.into();
+        }
+        for (reported, input) in self.reported_input_faults.iter_mut().zip(&status.digital_inputs) {
+            *reported = PackedDigital::from(input).fault_bits();
+        }
+    }
+}
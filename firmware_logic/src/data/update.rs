@@ -0,0 +1,274 @@
+use crc::{Crc, CRC_32_ISO_HDLC};
+use embedded_storage::nor_flash::NorFlash;
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+use super::image::{verify_signature, ImageError, ImageHeader, IMAGE_HEADER_SIZE, PUBLIC_KEY_SIZE};
+use super::settings::Settings;
+use crate::Configuration;
+
+/// Image bytes carried by every `UpdateRequest::Chunk` but the last, a multiple of any flash
+/// write size.
+pub const UPDATE_CHUNK_SIZE: usize = 1_024;
+/// Room for a COBS framed `UpdateRequest` carrying a full chunk.
+pub const UPDATE_FRAME_SIZE: usize = UPDATE_CHUNK_SIZE + 32;
+
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// CRC-32 of an upload or of a chunk of it.
+pub fn image_crc(bytes: &[u8]) -> u32 {
+    CRC.checksum(bytes)
+}
+
+/// Sent by the uploader to the flash TCP socket of the card, each request gets one response.
+// Requests are decoded one at a time, boxing the chunk would need an allocator
+#[allow(clippy::large_enum_variant)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum UpdateRequest {
+    /// Starts an update of `length` bytes, the image and its header, any update in progress is
+    /// dropped.
+    Begin {
+        length: u32,
+        crc: u32,
+    },
+    /// `data` goes at `offset` in the image. Chunks are sent in order, and hold
+    /// `UPDATE_CHUNK_SIZE` bytes but the last.
+    Chunk {
+        offset: u32,
+        crc: u32,
+        data: Vec<u8, UPDATE_CHUNK_SIZE>,
+    },
+    /// Checks the whole upload against the CRC it was begun with, and the image against its header
+    /// and signature, then boots it on the next reset.
+    Finish,
+    Abort,
+    /// Saves the settings of the card, which apply from its next reset. Handled by the owner of the
+    /// `SettingsStore` rather than the `Updater`, an update in progress goes on.
+    SaveSettings(Settings),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum UpdateResponse {
+    /// The update was begun, chunks are expected from offset 0.
+    Ready,
+    /// The chunk was written, the next one is expected at `next_offset`.
+    ChunkWritten {
+        next_offset: u32,
+    },
+    /// The image read back from flash matches its CRC and is signed, it is activated next.
+    Verified(ImageHeader),
+    /// The next reset boots the new image.
+    Activated,
+    Aborted,
+    SettingsSaved,
+    Failed(UpdateError),
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UpdateError {
+    /// The request could not be decoded, or did not fit `UPDATE_FRAME_SIZE`.
+    Undecodable,
+    /// No update is in progress, it was never begun or has failed.
+    NotStarted,
+    /// The image does not fit the slot.
+    TooLarge,
+    /// The update resumes at `expected`, the chunk was not written.
+    OutOfOrder { expected: u32 },
+    /// Only the last chunk may hold less than `UPDATE_CHUNK_SIZE` bytes.
+    ShortChunk,
+    /// The chunk does not match its CRC and was not written, it may be sent again.
+    ChunkCrc,
+    /// `Finish` came before the image was complete, the update resumes at `expected`.
+    Incomplete { expected: u32 },
+    /// The upload read back from flash does not match the CRC it was begun with.
+    ImageCrc,
+    /// The header is missing, does not match the image or its signature is invalid.
+    Image(ImageError),
+    /// The image was not built for the configuration of the card.
+    Incompatible,
+    /// Erasing, writing or reading the slot failed.
+    Flash,
+    /// The running firmware is not in a slot, e.g. it was flashed by a debugger.
+    NotInSlot,
+    /// The boot record could not be written, the previous image is booted on the next reset.
+    Activation,
+    /// The settings could not be saved, the card keeps the previous ones.
+    Settings,
+}
+
+struct Progress {
+    length: u32,
+    crc: u32,
+    next_offset: u32,
+    /// The slot is erased up to there, sectors are erased as chunks reach them.
+    erased_to: u32,
+}
+
+/// Writes an image received in chunks to the inactive slot.
+///
+/// The slot is `size` bytes from `offset` in the flash handed to `handle`. Only images signed with
+/// the key matching `public_key`, and built for `configuration`, are verified. Failures other than
+/// a bad or misplaced chunk drop the update, it must be begun again.
+pub struct Updater {
+    offset: u32,
+    size: u32,
+    configuration: Configuration,
+    public_key: [u8; PUBLIC_KEY_SIZE],
+    progress: Option<Progress>,
+}
+
+impl Updater {
+    pub const fn new(
+        offset: u32,
+        size: u32,
+        configuration: Configuration,
+        public_key: [u8; PUBLIC_KEY_SIZE],
+    ) -> Self {
+        Self {
+            offset,
+            size,
+            configuration,
+            public_key,
+            progress: None,
+        }
+    }
+
+    pub fn is_updating(&self) -> bool {
+        self.progress.is_some()
+    }
+
+    pub fn handle<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        request: &UpdateRequest,
+    ) -> UpdateResponse {
+        let response = match request {
+            UpdateRequest::Begin { length, crc } => self.begin(*length, *crc),
+            UpdateRequest::Chunk { offset, crc, data } => {
+                self.write_chunk(flash, *offset, *crc, data)
+            }
+            UpdateRequest::Finish => self.verify(flash),
+            UpdateRequest::Abort => {
+                self.progress = None;
+                Ok(UpdateResponse::Aborted)
+            }
+            UpdateRequest::SaveSettings(_) => Err(UpdateError::Settings),
+        };
+        response.unwrap_or_else(|error| {
+            if !matches!(
+                error,
+                UpdateError::OutOfOrder { .. }
+                    | UpdateError::ChunkCrc
+                    | UpdateError::Incomplete { .. }
+                    | UpdateError::Settings
+            ) {
+                self.progress = None;
+            }
+            UpdateResponse::Failed(error)
+        })
+    }
+
+    fn begin(&mut self, length: u32, crc: u32) -> Result<UpdateResponse, UpdateError> {
+        self.progress = None;
+        if length > self.size {
+            return Err(UpdateError::TooLarge);
+        }
+        self.progress = Some(Progress {
+            length,
+            crc,
+            next_offset: 0,
+            erased_to: 0,
+        });
+        Ok(UpdateResponse::Ready)
+    }
+
+    fn write_chunk<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        offset: u32,
+        crc: u32,
+        data: &[u8],
+    ) -> Result<UpdateResponse, UpdateError> {
+        let progress = self.progress.as_mut().ok_or(UpdateError::NotStarted)?;
+        if offset != progress.next_offset {
+            return Err(UpdateError::OutOfOrder {
+                expected: progress.next_offset,
+            });
+        }
+        let end = offset + data.len() as u32;
+        if end > progress.length {
+            return Err(UpdateError::TooLarge);
+        }
+        if data.len() != UPDATE_CHUNK_SIZE && end != progress.length {
+            return Err(UpdateError::ShortChunk);
+        }
+        if image_crc(data) != crc {
+            return Err(UpdateError::ChunkCrc);
+        }
+
+        while progress.erased_to < end {
+            let from = self.offset + progress.erased_to;
+            flash
+                .erase(from, from + F::ERASE_SIZE as u32)
+                .map_err(|_| UpdateError::Flash)?;
+            progress.erased_to += F::ERASE_SIZE as u32;
+        }
+
+        // The last chunk is padded with erased bytes to a whole number of writes
+        let mut padded = [0xFFu8; UPDATE_CHUNK_SIZE];
+        padded[..data.len()].copy_from_slice(data);
+        let length = data.len().next_multiple_of(F::WRITE_SIZE);
+        flash
+            .write(self.offset + offset, &padded[..length])
+            .map_err(|_| UpdateError::Flash)?;
+
+        progress.next_offset = end;
+        Ok(UpdateResponse::ChunkWritten { next_offset: end })
+    }
+
+    fn verify<F: NorFlash>(&mut self, flash: &mut F) -> Result<UpdateResponse, UpdateError> {
+        let progress = self.progress.as_ref().ok_or(UpdateError::NotStarted)?;
+        if progress.next_offset != progress.length {
+            return Err(UpdateError::Incomplete {
+                expected: progress.next_offset,
+            });
+        }
+
+        let mut digest = CRC.digest();
+        let mut buf = [0u8; 256];
+        let mut offset = 0;
+        while offset < progress.length {
+            let length = buf.len().min((progress.length - offset) as usize);
+            flash
+                .read(self.offset + offset, &mut buf[..length])
+                .map_err(|_| UpdateError::Flash)?;
+            digest.update(&buf[..length]);
+            offset += length as u32;
+        }
+        if digest.finalize() != progress.crc {
+            return Err(UpdateError::ImageCrc);
+        }
+
+        if (progress.length as usize) < IMAGE_HEADER_SIZE {
+            return Err(UpdateError::Image(ImageError::Header));
+        }
+        let mut header = [0u8; IMAGE_HEADER_SIZE];
+        flash
+            .read(self.offset, &mut header)
+            .map_err(|_| UpdateError::Flash)?;
+        let decoded = ImageHeader::decode(&header).map_err(UpdateError::Image)?;
+        if decoded.length as usize + IMAGE_HEADER_SIZE != progress.length as usize {
+            return Err(UpdateError::Image(ImageError::Crc));
+        }
+        if !decoded.is_compatible(self.configuration) {
+            return Err(UpdateError::Incompatible);
+        }
+        verify_signature(&self.public_key, &header, |offset, buf| {
+            flash.read(self.offset + offset, buf)
+        })
+        .map_err(UpdateError::Image)?;
+
+        self.progress = None;
+        Ok(UpdateResponse::Verified(decoded))
+    }
+}
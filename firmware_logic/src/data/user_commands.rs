@@ -1,7 +1,6 @@
+use ringbuffer::{ConstGenericRingBuffer, RingBuffer};
 
-use ringbuffer::ConstGenericRingBuffer;
-
-use crate::UserCommand;
+use crate::{NackReason, Timestamp, UserCommand};
 
 // 34 results into a core lockup state. 33 is the maximum value that works without issues.
 // Note that the size does not have to be a power of two, but that not using a power of two might be significantly (up to 3 times) slower.
@@ -10,4 +9,76 @@ const COMMANDS_BUFFER_SIZE: usize = 32;
 #[derive(Default)]
 pub struct UserCommands {
     commands: ConstGenericRingBuffer<UserCommand, COMMANDS_BUFFER_SIZE>,
-}
\ No newline at end of file
+}
+
+impl UserCommands {
+    /// Queues a command received from the control server.
+    ///
+    /// A command that is already queued is refreshed instead of queued twice, so that a held
+    /// button does not fill up the queue.
+    pub fn push(&mut self, user_command: UserCommand) -> Result<(), NackReason> {
+        if let Some(queued) = self
+            .commands
+            .iter_mut()
+            .find(|queued| queued.command == user_command.command)
+        {
+            if user_command > *queued {
+                *queued = user_command;
+            }
+            return Ok(());
+        }
+
+        if self.commands.is_full() {
+            return Err(NackReason::QueueFull);
+        }
+        self.commands.push(user_command);
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &UserCommand> {
+        self.commands.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Returns the most recent valid command picked by `extractor`.
+    pub fn latest<T>(
+        &self,
+        now: Timestamp,
+        extractor: impl Fn(&UserCommand) -> Option<T>,
+    ) -> Option<T> {
+        self.commands
+            .iter()
+            .filter(|user_command| user_command.is_valid(now))
+            .filter_map(|user_command| {
+                extractor(user_command).map(|value| (user_command.timestamp, value))
+            })
+            .max_by_key(|(timestamp, _)| *timestamp)
+            .map(|(_, value)| value)
+    }
+
+    pub fn remove_expired(&mut self, now: Timestamp) {
+        self.retain(|user_command| user_command.is_valid(now));
+    }
+
+    /// Switch commands only have to be applied once, buttons stay until they expire.
+    pub fn remove_applied_switches(&mut self) {
+        self.retain(|user_command| user_command.command.is_button());
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&UserCommand) -> bool) {
+        for _ in 0..self.commands.len() {
+            if let Some(user_command) = self.commands.dequeue() {
+                if keep(&user_command) {
+                    self.commands.push(user_command);
+                }
+            }
+        }
+    }
+}
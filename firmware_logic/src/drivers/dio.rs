@@ -0,0 +1,752 @@
+//! Digital IO banks: a MAX7301 port expander drives the outputs of each bank, an SN65HVS881
+//! serializer reads its inputs. The controllers share one SPI bus, selected through the SEL0..3
+//! decoder.
+
+use core::fmt::Debug;
+
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::blocking::spi::Transfer;
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::spi::{self, Mode};
+use strum::IntoEnumIterator;
+
+use crate::data::io_population::{ControllerProbe, IoPopulation};
+use crate::{
+    DigitalInput, HardwareDigital, IoBank, IoError, IoLevel, IoState, IoSupply, IoTemp, IoType,
+    OutputFault, WATCHED_LINES,
+};
+
+pub type Result<T, SPI, HAL> = core::result::Result<T, IoError<SPI, HAL>>;
+
+#[allow(dead_code)]
+mod dio_consts {
+    pub const MAX7301_NOP: u8 = 0x00;
+    pub const MAX7301_CONFIGURATION: u8 = 0x04;
+    pub const MAX7301_TRANSITION_MASK: u8 = 0x06;
+    /// Port configuration registers, two bits per port, four ports per register.
+    pub const MAX7301_PORT_CONFIG: u8 = 0x08;
+    /// Single port registers, the port number is added.
+    pub const MAX7301_SINGLE_PORT: u8 = 0x20;
+    /// Multi-port registers, the first of eight ports is added.
+    pub const MAX7301_MULTI_PORT: u8 = 0x40;
+    /// Set in the command byte to read the register back on the next transfer.
+    pub const MAX7301_READ: u8 = 0x80;
+
+    pub const MAX7301_CONFIGURATION_NORMAL: u8 = 0x01;
+    pub const MAX7301_CONFIGURATION_TRANSITION_DETECT: u8 = 0x80;
+
+    pub const MAX7301_PORT_OUTPUT: u8 = 0x01;
+    pub const MAX7301_PORT_INPUT: u8 = 0x02;
+    pub const MAX7301_PORT_INPUT_PULLUP: u8 = 0x03;
+
+    /// First MAX7301 port of the EN outputs, the STAT inputs and the status inputs of a DO bank.
+    pub const DO_EN_PORT: u8 = 4;
+    pub const DO_STAT_PORT: u8 = 12;
+    pub const DO_STATUS_PORT: u8 = 20;
+    /// Ports 24 to 30 can be watched for transitions, port 31 then outputs the interrupt.
+    pub const DO_WATCHED_PORT: u8 = 24;
+    pub const DO_INTERRUPT_PORT: u8 = 31;
+
+    /// Top bits of every SN65HVS881 frame, bit 10 is the parity.
+    pub const SN65HVS881_HEADER: u16 = 0x5000;
+    pub const SN65HVS881_HEADER_MASK: u16 = 0xF800;
+    pub const SN65HVS881_PARITY: u16 = 1 << 10;
+}
+pub use self::dio_consts::*;
+
+/// The SPI bus of the banks, the MAX7301 and the SN65HVS881 need different modes.
+pub trait SpiMode {
+    fn set_spi_mode(&mut self, mode: Mode);
+}
+
+/// A DI bank, read from a single SN65HVS881 frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DiBank {
+    /// Inputs 1 to 8.
+    pub inputs: [IoLevel; 8],
+    pub temp: IoTemp,
+    pub supply: IoSupply,
+}
+
+/// A DO bank, read from the MAX7301 multi-port registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DoBank {
+    /// STAT pins of outputs 1 to 8, `None` while the bank is unpowered.
+    pub states: Option<[IoState; 8]>,
+    /// Levels of ports 24 to 30, see `DigitalInput::watched`.
+    pub watched: [IoLevel; WATCHED_LINES],
+    pub temp: IoTemp,
+    pub supply: IoSupply,
+}
+
+/// Hands the watched inputs of a DO bank `read` to their channels of `digital_inputs`, unknown
+/// when the read failed. Their supply and temperature are those of the DO bank.
+pub fn update_watched_inputs(
+    digital_inputs: &mut [HardwareDigital],
+    bank: IoBank,
+    read: Option<&DoBank>,
+) {
+    for line in 0..WATCHED_LINES {
+        let channel = &mut digital_inputs[DigitalInput::watched(bank, line).address];
+        channel.level = read.map(|read| read.watched[line]);
+        channel.supply = read.map(|read| read.supply);
+        channel.temp = read.map(|read| read.temp);
+    }
+}
+
+/// Decoder output selecting the controller of `bank`.
+pub fn chip_select(direction: IoType, bank: IoBank) -> u8 {
+    let chip = match bank {
+        IoBank::Bank1 => 5,
+        IoBank::Bank2 => 4,
+        IoBank::Bank3 => 3,
+        IoBank::Bank4 => 2,
+        IoBank::Bank5 => 1,
+        IoBank::Bank6 => 0,
+    };
+    match direction {
+        IoType::DigitalIn => chip,
+        IoType::DigitalOut => chip + 8,
+    }
+}
+
+pub struct Dio<SPI, NCS, SEL0, SEL1, SEL2, SEL3, LD, DELAY> {
+    sel0: SEL0,
+    sel1: SEL1,
+    sel2: SEL2,
+    sel3: SEL3,
+    spi: SPI,
+    ncs: NCS,
+    ld: LD,
+    delay: DELAY,
+    /// Outputs are read back after each write, see `set_write_verify`.
+    write_verify: bool,
+}
+
+impl<SPI, NCS, SEL0, SEL1, SEL2, SEL3, LD, DELAY, SPIE, CSE>
+    Dio<SPI, NCS, SEL0, SEL1, SEL2, SEL3, LD, DELAY>
+where
+    SPI: Transfer<u8, Error = SPIE> + SpiMode,
+    NCS: OutputPin<Error = CSE>,
+    SEL0: OutputPin<Error = CSE>,
+    SEL1: OutputPin<Error = CSE>,
+    SEL2: OutputPin<Error = CSE>,
+    SEL3: OutputPin<Error = CSE>,
+    LD: OutputPin<Error = CSE>,
+    DELAY: DelayUs<u32>,
+    SPIE: Debug,
+    CSE: Debug,
+{
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        sel0: SEL0,
+        sel1: SEL1,
+        sel2: SEL2,
+        sel3: SEL3,
+        spi: SPI,
+        ncs: NCS,
+        ld: LD,
+        delay: DELAY,
+    ) -> Self {
+        Self {
+            sel0,
+            sel1,
+            sel2,
+            sel3,
+            spi,
+            ncs,
+            ld,
+            delay,
+            write_verify: false,
+        }
+    }
+
+    /// Reads the outputs back after each write: `set_do_by_bank` fails with `IoError::Stuck` when
+    /// the port differs from the level written, `write_do_bank` checks every output of the bank.
+    pub fn set_write_verify(&mut self, enabled: bool) {
+        self.write_verify = enabled;
+    }
+
+    /// Initializes every bank, a missing one does not keep the others from being initialized.
+    /// Returns the first error.
+    pub fn init(&mut self) -> Result<(), SPIE, CSE> {
+        // Initialize Digital Output Controllers for Banks 1-6
+        let mut result = Ok(());
+        for bank_n in IoBank::iter() {
+            let initialized = self.init_do_bank(bank_n);
+            if result.is_ok() {
+                result = initialized;
+            }
+        }
+
+        // Initialize Digital Input Controllers for Bank 1-6
+
+        result
+    }
+
+    /// Boot self-test, after `init`: probes the DI and the DO controller of every bank, reading
+    /// their supply and temperature.
+    ///
+    /// A DI controller is faulty when its frame fails the parity check, a DO controller when it
+    /// lost the configuration written by `init`.
+    pub fn probe(&mut self) -> IoPopulation {
+        let mut population = IoPopulation::default();
+        for (bank, probed) in IoBank::iter().zip(population.banks.iter_mut()) {
+            probed.inputs =
+                probe_result(self.read_di_bank(bank).map(|read| (read.supply, read.temp)));
+            probed.outputs = self.probe_do_bank(bank);
+        }
+        population
+    }
+
+    fn probe_do_bank(&mut self, bank: IoBank) -> ControllerProbe {
+        let configured = self
+            .select_io_bank(IoType::DigitalOut, bank)
+            .and_then(|()| {
+                let configuration = self.max7301_read(MAX7301_CONFIGURATION)?;
+                // The EN ports are outputs
+                let en_config = self.max7301_read(MAX7301_PORT_CONFIG + DO_EN_PORT / 4)?;
+                Ok(configuration & MAX7301_CONFIGURATION_NORMAL != 0 && en_config == 0x55)
+            });
+        match configured {
+            Ok(true) => probe_result(self.read_do_bank(bank).map(|read| (read.supply, read.temp))),
+            Ok(false) => ControllerProbe::Faulty,
+            Err(error) => probe_result(Err(error)),
+        }
+    }
+
+    fn init_do_bank(&mut self, bank_n: IoBank) -> Result<(), SPIE, CSE> {
+        self.select_io_bank(IoType::DigitalOut, bank_n)?;
+
+        for port_n in 0..=7 {
+            // Configure port expander outputs for EN pins
+            self.max7301_configure_output(DO_EN_PORT + port_n, IoLevel::HiZ)?;
+
+            // configure Stat inputs
+            self.max7301_configure_input(DO_STAT_PORT + port_n, true)?;
+        }
+
+        // configure voltage detect inputs
+        self.max7301_configure_input(20, false)?;
+        self.max7301_configure_input(21, false)?;
+
+        // configure nHot input
+        self.max7301_configure_input(22, true)?;
+        // configure nWarm input
+        self.max7301_configure_input(23, true)?;
+
+        self.max7301_wakeup(false)
+    }
+
+    pub fn set_do_by_num(&mut self, output: u8, level: IoLevel) -> Result<(), SPIE, CSE> {
+        match output {
+            1..=8 => self.set_do_by_bank(IoBank::Bank1, output, level),
+            9..=16 => self.set_do_by_bank(IoBank::Bank2, output - 8, level),
+            17..=24 => self.set_do_by_bank(IoBank::Bank3, output - 16, level),
+            25..=32 => self.set_do_by_bank(IoBank::Bank4, output - 24, level),
+            33..=40 => self.set_do_by_bank(IoBank::Bank5, output - 32, level),
+            41..=48 => self.set_do_by_bank(IoBank::Bank6, output - 40, level),
+            _ => Err(IoError::IllegalOutput),
+        }
+    }
+
+    pub fn set_do_by_bank(
+        &mut self,
+        bank: IoBank,
+        output: u8,
+        level: IoLevel,
+    ) -> Result<(), SPIE, CSE> {
+        self.select_io_bank(IoType::DigitalOut, bank)?;
+        if output > 8 {
+            return Err(IoError::IllegalOutput);
+        }
+
+        self.max7301_write_single_port(output + 3, level)?;
+        if self.write_verify && self.max7301_read_single_port(output + 3)? != Self::en_level(level)
+        {
+            return Err(IoError::Stuck);
+        }
+        Ok(())
+    }
+
+    pub fn get_do_temp(&mut self, bank: IoBank) -> Result<IoTemp, SPIE, CSE> {
+        self.select_io_bank(IoType::DigitalOut, bank)?;
+        if self.max7301_read_single_port(22)? == IoLevel::Low {
+            return Ok(IoTemp::Hot);
+        }
+        if self.max7301_read_single_port(23)? == IoLevel::Low {
+            return Ok(IoTemp::Warm);
+        }
+        Ok(IoTemp::Normal)
+    }
+
+    pub fn get_do_supply(&mut self, bank: IoBank) -> Result<IoSupply, SPIE, CSE> {
+        self.select_io_bank(IoType::DigitalOut, bank)?;
+        if self.max7301_read_single_port(20)? == IoLevel::High {
+            return Ok(IoSupply::Volts24);
+        }
+        if self.max7301_read_single_port(21)? == IoLevel::High {
+            return Ok(IoSupply::Volts12);
+        }
+        Ok(IoSupply::Unpowered)
+    }
+
+    pub fn get_do_state_by_num(&mut self, output: u8) -> Result<IoState, SPIE, CSE> {
+        match output {
+            1..=8 => self.get_do_state_by_bank(IoBank::Bank1, output),
+            9..=16 => self.get_do_state_by_bank(IoBank::Bank2, output - 8),
+            17..=24 => self.get_do_state_by_bank(IoBank::Bank3, output - 16),
+            25..=32 => self.get_do_state_by_bank(IoBank::Bank4, output - 24),
+            33..=40 => self.get_do_state_by_bank(IoBank::Bank5, output - 32),
+            41..=48 => self.get_do_state_by_bank(IoBank::Bank6, output - 40),
+            _ => Err(IoError::IllegalOutput),
+        }
+    }
+
+    pub fn get_do_state_by_bank(&mut self, bank: IoBank, output: u8) -> Result<IoState, SPIE, CSE> {
+        static STATE_PIN_OFFSET: u8 = 11;
+
+        // We have no information if the bank is unpowered
+        if self.get_do_supply(bank)? == IoSupply::Unpowered {
+            return Err(IoError::IllegalOutput);
+        }
+
+        self.select_io_bank(IoType::DigitalOut, bank)?;
+        if output > 8 {
+            return Err(IoError::IllegalOutput);
+        }
+
+        if self.max7301_read_single_port(output + STATE_PIN_OFFSET)? == IoLevel::High {
+            Ok(IoState::Normal)
+        } else {
+            Ok(IoState::OpenOrFault)
+        }
+    }
+
+    pub fn get_di_by_bank(&mut self, bank: IoBank, input: u8) -> Result<IoLevel, SPIE, CSE> {
+        static INPUT_PIN_OFFSET: u8 = 1;
+        self.select_io_bank(IoType::DigitalIn, bank)?;
+        if !(1..=8).contains(&input) {
+            return Err(IoError::IllegalInput);
+        }
+
+        match self.sn65hvs881_read_single_bit(input - INPUT_PIN_OFFSET) {
+            Ok(level) => {
+                if level {
+                    Ok(IoLevel::High)
+                } else {
+                    Ok(IoLevel::Low)
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get_di_by_num(&mut self, input: u8) -> Result<IoLevel, SPIE, CSE> {
+        match input {
+            1..=8 => self.get_di_by_bank(IoBank::Bank1, input),
+            9..=16 => self.get_di_by_bank(IoBank::Bank2, input - 8),
+            17..=24 => self.get_di_by_bank(IoBank::Bank3, input - 16),
+            25..=32 => self.get_di_by_bank(IoBank::Bank4, input - 24),
+            33..=40 => self.get_di_by_bank(IoBank::Bank5, input - 32),
+            41..=48 => self.get_di_by_bank(IoBank::Bank6, input - 40),
+            _ => Err(IoError::IllegalInput),
+        }
+    }
+
+    pub fn get_di_temp(&mut self, bank: IoBank) -> Result<IoTemp, SPIE, CSE> {
+        self.select_io_bank(IoType::DigitalIn, bank)?;
+
+        match self.sn65hvs881_read_single_bit(8) {
+            Ok(level) => {
+                if level {
+                    Ok(IoTemp::Normal)
+                } else {
+                    Ok(IoTemp::Hot)
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get_di_supply(&mut self, bank: IoBank) -> Result<IoSupply, SPIE, CSE> {
+        self.select_io_bank(IoType::DigitalIn, bank)?;
+
+        match self.sn65hvs881_read_single_bit(9) {
+            Ok(level) => {
+                if level {
+                    Ok(IoSupply::Powered)
+                } else {
+                    Ok(IoSupply::Unpowered)
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Watches the ports of `bank` set in `mask`, bit 0 is port 24, for transitions. The MAX7301
+    /// raises its interrupt output on the first one, until rearmed.
+    pub fn enable_change_detection(&mut self, bank: IoBank, mask: u8) -> Result<(), SPIE, CSE> {
+        self.select_io_bank(IoType::DigitalOut, bank)?;
+        self.max7301_configure_output(DO_INTERRUPT_PORT, IoLevel::Low)?;
+        for line in (0..7).filter(|line| mask & (1 << line) != 0) {
+            self.max7301_configure_input(DO_WATCHED_PORT + line, true)?;
+        }
+        self.max7301_transfer(&mut [MAX7301_TRANSITION_MASK, mask & 0x7F])?;
+        self.max7301_wakeup(true)
+    }
+
+    /// Clears the interrupt of `bank` and compares the watched ports against their levels from
+    /// now on. Detection stops after a transition, rearm once the inputs have been read.
+    pub fn rearm_change_detection(&mut self, bank: IoBank) -> Result<(), SPIE, CSE> {
+        self.select_io_bank(IoType::DigitalOut, bank)?;
+        self.max7301_wakeup(true)
+    }
+
+    /// Reads the inputs, the temperature and the supply of `bank` from a single frame.
+    pub fn read_di_bank(&mut self, bank: IoBank) -> Result<DiBank, SPIE, CSE> {
+        self.select_io_bank(IoType::DigitalIn, bank)?;
+        let frame = self.sn65hvs881_read_frame()?;
+        let bit = |n: u8| frame & (1 << n) != 0;
+
+        Ok(DiBank {
+            inputs: core::array::from_fn(|input| {
+                if bit(input as u8) {
+                    IoLevel::High
+                } else {
+                    IoLevel::Low
+                }
+            }),
+            temp: if bit(8) { IoTemp::Normal } else { IoTemp::Hot },
+            supply: if bit(9) {
+                IoSupply::Powered
+            } else {
+                IoSupply::Unpowered
+            },
+        })
+    }
+
+    /// Reads the STAT pins, the temperature, the supply and the watched inputs of `bank` with
+    /// three multi-port reads.
+    pub fn read_do_bank(&mut self, bank: IoBank) -> Result<DoBank, SPIE, CSE> {
+        self.select_io_bank(IoType::DigitalOut, bank)?;
+        let stat = self.max7301_read_ports(DO_STAT_PORT)?;
+        // Ports 20 and 21 detect 24 V and 12 V, ports 22 and 23 are nHot and nWarm
+        let status = self.max7301_read_ports(DO_STATUS_PORT)?;
+        let watched = self.max7301_read_ports(DO_WATCHED_PORT)?;
+
+        let supply = if status & 0x01 != 0 {
+            IoSupply::Volts24
+        } else if status & 0x02 != 0 {
+            IoSupply::Volts12
+        } else {
+            IoSupply::Unpowered
+        };
+        let temp = if status & 0x04 == 0 {
+            IoTemp::Hot
+        } else if status & 0x08 == 0 {
+            IoTemp::Warm
+        } else {
+            IoTemp::Normal
+        };
+        // We have no information if the bank is unpowered
+        let states = (supply != IoSupply::Unpowered).then(|| {
+            core::array::from_fn(|output| {
+                if stat & (1 << output) != 0 {
+                    IoState::Normal
+                } else {
+                    IoState::OpenOrFault
+                }
+            })
+        });
+
+        Ok(DoBank {
+            states,
+            watched: core::array::from_fn(|line| {
+                if watched & (1 << line) != 0 {
+                    IoLevel::High
+                } else {
+                    IoLevel::Low
+                }
+            }),
+            temp,
+            supply,
+        })
+    }
+
+    /// Writes outputs 1 to 8 of `bank` with a single multi-port write.
+    ///
+    /// In write-verify mode the EN ports and the STAT pins are read back to tell the fault of
+    /// each output: a port that did not take the level is stuck, a STAT fault is an open load
+    /// while the output is off and an overload while it is on. Without, no fault is returned.
+    pub fn write_do_bank(
+        &mut self,
+        bank: IoBank,
+        levels: [IoLevel; 8],
+    ) -> Result<[Option<OutputFault>; 8], SPIE, CSE> {
+        self.select_io_bank(IoType::DigitalOut, bank)?;
+        let mut ports = 0u8;
+        for (output, level) in levels.iter().enumerate() {
+            if Self::en_level(*level) == IoLevel::High {
+                ports |= 1 << output;
+            }
+        }
+        self.max7301_write_ports(DO_EN_PORT, ports)?;
+        if !self.write_verify {
+            return Ok([None; 8]);
+        }
+
+        let en = self.max7301_read_ports(DO_EN_PORT)?;
+        let stat = self.max7301_read_ports(DO_STAT_PORT)?;
+        // The STAT pins tell nothing while the bank is unpowered
+        let powered = self.max7301_read_ports(DO_STATUS_PORT)? & 0x03 != 0;
+
+        Ok(core::array::from_fn(|output| {
+            let on = ports & (1 << output) != 0;
+            if (en & (1 << output) != 0) != on {
+                Some(OutputFault::Stuck)
+            } else if powered && stat & (1 << output) == 0 {
+                Some(if on {
+                    OutputFault::Overload
+                } else {
+                    OutputFault::OpenLoad
+                })
+            } else {
+                None
+            }
+        }))
+    }
+
+    /// Level of the EN port for `level`: the EN pins switch high side drivers, low and floating
+    /// both turn the output off.
+    fn en_level(level: IoLevel) -> IoLevel {
+        match level {
+            IoLevel::High => IoLevel::High,
+            IoLevel::Low | IoLevel::HiZ => IoLevel::Low,
+        }
+    }
+
+    fn max7301_write_single_port(&mut self, port_no: u8, level: IoLevel) -> Result<(), SPIE, CSE> {
+        let mut buf = [0u8; 2];
+
+        if !(4..=31).contains(&port_no) {
+            return Err(IoError::IllegalOutput);
+        }
+
+        // set desired output state (high / low)
+        buf[0] = MAX7301_SINGLE_PORT + port_no; // address for single port register
+        match Self::en_level(level) {
+            IoLevel::High => buf[1] = 1u8,
+            _ => buf[1] = 0u8,
+        }
+        self.max7301_transfer(&mut buf)?;
+        Ok(())
+    }
+
+    fn max7301_read_single_port(&mut self, port_no: u8) -> Result<IoLevel, SPIE, CSE> {
+        if !(4..=31).contains(&port_no) {
+            return Err(IoError::IllegalOutput);
+        }
+
+        if self.max7301_read(MAX7301_SINGLE_PORT + port_no)? != 0 {
+            Ok(IoLevel::High)
+        } else {
+            Ok(IoLevel::Low)
+        }
+    }
+
+    /// Reads ports `first_port` to `first_port + 7`, bit 0 is `first_port`.
+    fn max7301_read_ports(&mut self, first_port: u8) -> Result<u8, SPIE, CSE> {
+        if !(4..=31).contains(&first_port) {
+            return Err(IoError::IllegalOutput);
+        }
+
+        self.max7301_read(MAX7301_MULTI_PORT + first_port)
+    }
+
+    /// Writes ports `first_port` to `first_port + 7`, ports configured as inputs ignore it.
+    fn max7301_write_ports(&mut self, first_port: u8, ports: u8) -> Result<(), SPIE, CSE> {
+        if !(4..=31).contains(&first_port) {
+            return Err(IoError::IllegalOutput);
+        }
+
+        // address for multi-port register
+        self.max7301_transfer(&mut [MAX7301_MULTI_PORT + first_port, ports])
+    }
+
+    /// Reads `register`. The MAX7301 shifts out the read command on the next transfer with the
+    /// register in place of the data: anything else on DOUT means no MAX7301 answers.
+    fn max7301_read(&mut self, register: u8) -> Result<u8, SPIE, CSE> {
+        // send read request with address of the register
+        let command = MAX7301_READ | register;
+        self.max7301_transfer(&mut [command, 0])?;
+
+        let mut buf = [MAX7301_NOP, 0]; // send NOP command and get read result
+        self.max7301_transfer(&mut buf)?;
+
+        if buf[0] != command {
+            return Err(IoError::NotPresent);
+        }
+        Ok(buf[1])
+    }
+
+    fn max7301_configure_output(&mut self, port_no: u8, level: IoLevel) -> Result<(), SPIE, CSE> {
+        if !(4..=31).contains(&port_no) {
+            return Err(IoError::IllegalOutput);
+        }
+
+        self.max7301_write_single_port(port_no, level)?;
+
+        self.max7301_write_port_config_bits(port_no, MAX7301_PORT_OUTPUT)?;
+        Ok(())
+    }
+
+    fn max7301_configure_input(&mut self, port_no: u8, en_pullup: bool) -> Result<(), SPIE, CSE> {
+        if !(4..=31).contains(&port_no) {
+            return Err(IoError::IllegalOutput);
+        }
+
+        let cfg_bits = if en_pullup {
+            MAX7301_PORT_INPUT_PULLUP
+        } else {
+            MAX7301_PORT_INPUT
+        };
+
+        self.max7301_write_port_config_bits(port_no, cfg_bits)?;
+        Ok(())
+    }
+
+    fn max7301_write_port_config_bits(
+        &mut self,
+        port_no: u8,
+        mut cfg_bits: u8,
+    ) -> Result<(), SPIE, CSE> {
+        cfg_bits &= 0x03;
+
+        // read corresponding port config register
+        let register = MAX7301_PORT_CONFIG + (port_no / 4);
+        let mut config_reg = self.max7301_read(register)?;
+
+        // modify config
+        let shift = (port_no & 0x03) * 2; // position of config bits
+        config_reg &= !(0x03 << shift); // clear both config bits for this output
+        config_reg |= cfg_bits << shift; // set requested bits
+
+        // write config
+        self.max7301_transfer(&mut [register, config_reg])?;
+        Ok(())
+    }
+
+    fn max7301_wakeup(&mut self, en_irq: bool) -> Result<(), SPIE, CSE> {
+        let mut buf = [MAX7301_CONFIGURATION, MAX7301_CONFIGURATION_NORMAL];
+
+        if en_irq {
+            buf[1] |= MAX7301_CONFIGURATION_TRANSITION_DETECT;
+        }
+
+        self.max7301_transfer(&mut buf)?;
+        Ok(())
+    }
+
+    fn max7301_transfer(&mut self, buf: &mut [u8]) -> Result<(), SPIE, CSE> {
+        self.set_spi_mode(spi::MODE_0);
+        self.ncs.set_low().map_err(IoError::Hal)?;
+        self.delay.delay_us(2u32);
+        self.spi.transfer(buf).map_err(IoError::Spi)?;
+        self.delay.delay_us(2u32);
+        self.ncs.set_high().map_err(IoError::Hal)?;
+        self.delay.delay_us(2u32);
+        Ok(())
+    }
+
+    fn sn65hvs881_read_single_bit(&mut self, port_no: u8) -> Result<bool, SPIE, CSE> {
+        let combined = self.sn65hvs881_read_frame()?;
+
+        if combined & (1 << port_no) != 0 {
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Latches and reads a frame: inputs in bits 0 to 7, then nHot, the supply and the parity,
+    /// the 0x5000 header in the top bits.
+    fn sn65hvs881_read_frame(&mut self) -> Result<u16, SPIE, CSE> {
+        let mut buf = [0x50, 0x00];
+
+        self.ld.set_low().map_err(IoError::Hal)?;
+        self.delay.delay_us(2u32);
+        self.ld.set_high().map_err(IoError::Hal)?;
+
+        self.sn65hvs881_transfer(&mut buf)?;
+
+        let combined = ((buf[1].reverse_bits() as u16) << 8) | buf[0] as u16;
+
+        // Nothing drives MISO, it floats to all ones or all zeros
+        if combined == 0x0000 || combined == 0xFFFF {
+            return Err(IoError::NotPresent);
+        }
+
+        let parity = u32::from(combined & SN65HVS881_PARITY != 0);
+
+        if (combined & SN65HVS881_HEADER_MASK) != SN65HVS881_HEADER {
+            return Err(IoError::DiParity);
+        }
+
+        if (combined & 0x03FF).count_ones() % 2 == parity {
+            return Err(IoError::DiParity);
+        }
+
+        Ok(combined)
+    }
+
+    fn sn65hvs881_transfer(&mut self, buf: &mut [u8]) -> Result<(), SPIE, CSE> {
+        self.set_spi_mode(spi::MODE_2);
+
+        self.ncs.set_low().map_err(IoError::Hal)?;
+        self.delay.delay_us(2u32);
+        self.spi.transfer(buf).map_err(IoError::Spi)?;
+        self.delay.delay_us(2u32);
+        self.ncs.set_high().map_err(IoError::Hal)?;
+        self.delay.delay_us(2u32);
+        Ok(())
+    }
+
+    fn select_io_bank(&mut self, direction: IoType, bank: IoBank) -> Result<(), SPIE, CSE> {
+        self.select_chip(chip_select(direction, bank))?;
+        self.delay.delay_us(2u32);
+        Ok(())
+    }
+
+    fn select_chip(&mut self, chip: u8) -> Result<(), SPIE, CSE> {
+        let pins: [&mut dyn OutputPin<Error = CSE>; 4] = [
+            &mut self.sel0,
+            &mut self.sel1,
+            &mut self.sel2,
+            &mut self.sel3,
+        ];
+
+        for (n, pin) in pins.into_iter().enumerate() {
+            if chip & (1 << n) != 0 {
+                pin.set_high().map_err(IoError::Hal)?;
+            } else {
+                pin.set_low().map_err(IoError::Hal)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn set_spi_mode(&mut self, mode: Mode) {
+        self.spi.set_spi_mode(mode);
+    }
+}
+
+fn probe_result<SPIE: Debug, CSE: Debug>(
+    result: Result<(IoSupply, IoTemp), SPIE, CSE>,
+) -> ControllerProbe {
+    match result {
+        Ok((supply, temp)) => ControllerProbe::Fitted { supply, temp },
+        Err(IoError::NotPresent) => ControllerProbe::Missing,
+        Err(_) => ControllerProbe::Faulty,
+    }
+}
@@ -0,0 +1,343 @@
+//! SMSC LAN8720A Ethernet PHY
+
+use core::ops::BitOr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::data::link::{LinkMode, LinkPhy, LinkSpeed};
+
+#[allow(dead_code)]
+mod phy_consts {
+    pub const PHY_REG_BCR: u8 = 0x00;
+    pub const PHY_REG_BSR: u8 = 0x01;
+    pub const PHY_REG_ID1: u8 = 0x02;
+    pub const PHY_REG_ID2: u8 = 0x03;
+    pub const PHY_REG_ANTX: u8 = 0x04;
+    pub const PHY_REG_ANRX: u8 = 0x05;
+    pub const PHY_REG_ANEXP: u8 = 0x06;
+    pub const PHY_REG_MCSR: u8 = 0x11; // Mode Control/Status Register
+    pub const PHY_REG_SMR: u8 = 0x12; // Special Modes Register
+    pub const PHY_REG_SECR: u8 = 0x1A; // Symbol Error Counter Register
+    pub const PHY_REG_CSIR: u8 = 0x1B; // Control/Status Indication Register
+    pub const PHY_REG_ISR: u8 = 0x1D; // Interrupt Source Register
+    pub const PHY_REG_IMR: u8 = 0x1E; // Interrupt Mask Register
+    pub const PHY_REG_SSR: u8 = 0x1F; // Special Status Register
+
+    pub const PHY_REG_BCR_COLTEST: u16 = 1 << 7;
+    pub const PHY_REG_BCR_FD: u16 = 1 << 8;
+    pub const PHY_REG_BCR_ANRST: u16 = 1 << 9;
+    pub const PHY_REG_BCR_ISOLATE: u16 = 1 << 10;
+    pub const PHY_REG_BCR_POWERDN: u16 = 1 << 11;
+    pub const PHY_REG_BCR_AN: u16 = 1 << 12;
+    pub const PHY_REG_BCR_100M: u16 = 1 << 13;
+    pub const PHY_REG_BCR_LOOPBACK: u16 = 1 << 14;
+    pub const PHY_REG_BCR_RESET: u16 = 1 << 15;
+
+    pub const PHY_REG_BSR_JABBER: u16 = 1 << 1;
+    pub const PHY_REG_BSR_UP: u16 = 1 << 2;
+    pub const PHY_REG_BSR_ANABLE: u16 = 1 << 3;
+    pub const PHY_REG_BSR_FAULT: u16 = 1 << 4;
+    pub const PHY_REG_BSR_ANDONE: u16 = 1 << 5;
+    pub const PHY_REG_BSR_EXTST: u16 = 1 << 8;
+    pub const PHY_REG_BSR_100BASE_T2_HD: u16 = 1 << 9;
+    pub const PHY_REG_BSR_100BASE_T2_FD: u16 = 1 << 10;
+    pub const PHY_REG_BSR_10BASE_T_HD: u16 = 1 << 11;
+    pub const PHY_REG_BSR_10BASE_T_FD: u16 = 1 << 12;
+    pub const PHY_REG_BSR_100BASE_TX_HD: u16 = 1 << 13;
+    pub const PHY_REG_BSR_100BASE_TX_FD: u16 = 1 << 14;
+    pub const PHY_REG_BSR_100BASE_T4: u16 = 1 << 15;
+
+    pub const PHY_REG_ANTX_SELECTOR_802_3: u16 = 0x0001;
+    pub const PHY_REG_ANTX_10BASE_T_HD: u16 = 1 << 5;
+    pub const PHY_REG_ANTX_10BASE_T_FD: u16 = 1 << 6;
+    pub const PHY_REG_ANTX_100BASE_TX_HD: u16 = 1 << 7;
+    pub const PHY_REG_ANTX_100BASE_TX_FD: u16 = 1 << 8;
+
+    pub const PHY_REG_SSR_SPEED: u16 = 0b111 << 2;
+    pub const PHY_REG_SSR_SPEED_10BASE_T_HD: u16 = 0b001 << 2;
+    pub const PHY_REG_SSR_SPEED_100BASE_TX_HD: u16 = 0b010 << 2;
+    pub const PHY_REG_SSR_SPEED_10BASE_T_FD: u16 = 0b101 << 2;
+    pub const PHY_REG_SSR_SPEED_100BASE_TX_FD: u16 = 0b110 << 2;
+    pub const PHY_REG_SSR_ANDONE: u16 = 1 << 12;
+
+    /// OUI and model of the LAN8720A as encoded in the identifier registers.
+    pub const LAN8720A_ID1: u16 = 0x0007;
+    pub const LAN8720A_ID2_MODEL: u16 = 0xC0F0;
+    pub const PHY_REG_ID2_REVISION: u16 = 0x000F;
+}
+pub use self::phy_consts::*;
+
+/// Access to the PHY registers over MDIO, as `stm32h7xx_hal::ethernet::StationManagement`.
+pub trait StationManagement {
+    /// Read a register over SMI.
+    fn smi_read(&mut self, reg: u8) -> u16;
+    /// Write a register over SMI.
+    fn smi_write(&mut self, reg: u8, val: u16);
+}
+
+/// Identifier registers of the PHY.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhyId {
+    pub id1: u16,
+    pub id2: u16,
+}
+
+impl PhyId {
+    pub fn is_lan8720a(&self) -> bool {
+        self.id1 == LAN8720A_ID1 && self.id2 & !PHY_REG_ID2_REVISION == LAN8720A_ID2_MODEL
+    }
+
+    pub fn revision(&self) -> u8 {
+        (self.id2 & PHY_REG_ID2_REVISION) as u8
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhyError {
+    /// Another chip answers at the PHY address, or none: an unpowered bus reads all ones.
+    UnknownId(PhyId),
+    /// The frame sent in loopback never came back.
+    LoopbackLost,
+    /// The frame came back altered.
+    LoopbackCorrupted,
+}
+
+/// Sources of the interrupt source register, latched until read. The driver does not use nINT,
+/// the sources are polled by `LAN8720A::update_diagnostics`.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct PhyInterrupts(pub u16);
+
+impl PhyInterrupts {
+    pub const AUTONEGOTIATION_PAGE_RECEIVED: Self = Self(1 << 1);
+    pub const PARALLEL_DETECTION_FAULT: Self = Self(1 << 2);
+    pub const AUTONEGOTIATION_LP_ACKNOWLEDGE: Self = Self(1 << 3);
+    pub const LINK_DOWN: Self = Self(1 << 4);
+    pub const REMOTE_FAULT: Self = Self(1 << 5);
+    pub const AUTONEGOTIATION_COMPLETE: Self = Self(1 << 6);
+    pub const ENERGY_ON: Self = Self(1 << 7);
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for PhyInterrupts {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+/// Health of the PHY since boot, reported to the control server with the network status.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhyDiagnostics {
+    /// `None` until read at boot.
+    pub id: Option<PhyId>,
+    /// Passed the loopback self-test at boot, see `LAN8720A::loopback_self_test`.
+    pub loopback_passed: bool,
+    /// 100BASE-TX symbols received in error.
+    pub symbol_errors: u32,
+    pub link_downs: u32,
+    pub remote_faults: u32,
+    pub parallel_detection_faults: u32,
+}
+
+/// Frames of this size are sent in the loopback self-test.
+pub const LOOPBACK_FRAME_SIZE: usize = 64;
+/// Local experimental EtherType, the frame never leaves the PHY.
+const LOOPBACK_ETHER_TYPE: [u8; 2] = [0x88, 0xB5];
+
+/// SMSC LAN8720A Ethernet PHY
+pub struct LAN8720A<MAC: StationManagement> {
+    mac: MAC,
+    /// Last value of the symbol error counter, which wraps.
+    latest_symbol_errors: u16,
+}
+
+/// Public functions for the LAN8720A
+impl<MAC: StationManagement> LAN8720A<MAC> {
+    /// Create LAN8720A instance from ETHMAC peripheral
+    pub fn new(mac: MAC) -> Self {
+        LAN8720A {
+            mac,
+            latest_symbol_errors: 0,
+        }
+    }
+    /// Returns a reference to the inner ETHMAC peripheral
+    pub fn inner(&self) -> &MAC {
+        &self.mac
+    }
+    /// Returns a mutable reference to the inner ETHMAC peripheral
+    pub fn inner_mut(&mut self) -> &mut MAC {
+        &mut self.mac
+    }
+    /// Releases the ETHMAC peripheral
+    pub fn free(self) -> MAC {
+        self.mac
+    }
+
+    /// Reset PHY and wait for it to come out of reset.
+    pub fn phy_reset(&mut self) {
+        self.mac.smi_write(PHY_REG_BCR, PHY_REG_BCR_RESET);
+        while self.mac.smi_read(PHY_REG_BCR) & PHY_REG_BCR_RESET == PHY_REG_BCR_RESET {}
+        self.latest_symbol_errors = self.mac.smi_read(PHY_REG_SECR);
+    }
+
+    /// PHY initialisation.
+    pub fn phy_init(&mut self) {
+        // Advertise every mode, the MAC follows the one negotiated, see `link_mode`
+        self.mac.smi_write(
+            PHY_REG_ANTX,
+            PHY_REG_ANTX_SELECTOR_802_3
+                | PHY_REG_ANTX_10BASE_T_HD
+                | PHY_REG_ANTX_10BASE_T_FD
+                | PHY_REG_ANTX_100BASE_TX_HD
+                | PHY_REG_ANTX_100BASE_TX_FD,
+        );
+        // Enable auto-negotiation
+        self.mac.smi_write(
+            PHY_REG_BCR,
+            PHY_REG_BCR_AN | PHY_REG_BCR_ANRST | PHY_REG_BCR_100M,
+        );
+    }
+
+    /// Reads the identifier registers, failing unless they are those of a LAN8720A.
+    pub fn identify(&mut self) -> Result<PhyId, PhyError> {
+        let id = PhyId {
+            id1: self.mac.smi_read(PHY_REG_ID1),
+            id2: self.mac.smi_read(PHY_REG_ID2),
+        };
+        match id.is_lan8720a() {
+            true => Ok(id),
+            false => Err(PhyError::UnknownId(id)),
+        }
+    }
+
+    /// Poll PHY to determine link status, in any mode.
+    pub fn poll_link(&mut self) -> bool {
+        // The link status latches low until read, the second read tells whether it is up now
+        self.mac.smi_read(PHY_REG_BSR);
+        let bsr = self.mac.smi_read(PHY_REG_BSR);
+
+        // No link if link is down
+        if bsr & PHY_REG_BSR_UP == 0 {
+            return false;
+        }
+        // No link if autonegotiate incomplete
+        if bsr & PHY_REG_BSR_ANDONE == 0 {
+            return false;
+        }
+
+        // Got link
+        true
+    }
+
+    pub fn link_established(&mut self) -> bool {
+        self.poll_link()
+    }
+
+    pub fn block_until_link(&mut self) {
+        while !self.link_established() {}
+    }
+
+    /// Restarts autonegotiation, e.g. after the link partner changed.
+    pub fn restart_autonegotiation(&mut self) {
+        let bcr = self.mac.smi_read(PHY_REG_BCR);
+        self.mac.smi_write(
+            PHY_REG_BCR,
+            (bcr & !PHY_REG_BCR_LOOPBACK) | PHY_REG_BCR_AN | PHY_REG_BCR_ANRST,
+        );
+    }
+
+    /// Selects the sources driving the nINT pin, they are latched in the source register either
+    /// way, see `take_interrupts`. All of them are masked after reset, which the card leaves as is.
+    pub fn set_interrupt_mask(&mut self, mask: PhyInterrupts) {
+        self.mac.smi_write(PHY_REG_IMR, mask.0);
+    }
+
+    /// Returns the interrupt sources raised since the previous call, reading clears them.
+    pub fn take_interrupts(&mut self) -> PhyInterrupts {
+        PhyInterrupts(self.mac.smi_read(PHY_REG_ISR))
+    }
+
+    /// Raw 100BASE-TX symbol error counter, it wraps.
+    pub fn symbol_error_counter(&mut self) -> u16 {
+        self.mac.smi_read(PHY_REG_SECR)
+    }
+
+    /// Adds the symbol errors and the interrupt sources raised since the previous call to
+    /// `diagnostics`, call periodically. This polling is how link downs and faults are found.
+    pub fn update_diagnostics(&mut self, diagnostics: &mut PhyDiagnostics) {
+        let symbol_errors = self.symbol_error_counter();
+        diagnostics.symbol_errors += symbol_errors.wrapping_sub(self.latest_symbol_errors) as u32;
+        self.latest_symbol_errors = symbol_errors;
+
+        let interrupts = self.take_interrupts();
+        let count = |source: PhyInterrupts| interrupts.contains(source) as u32;
+        diagnostics.link_downs += count(PhyInterrupts::LINK_DOWN);
+        diagnostics.remote_faults += count(PhyInterrupts::REMOTE_FAULT);
+        diagnostics.parallel_detection_faults += count(PhyInterrupts::PARALLEL_DETECTION_FAULT);
+    }
+
+    /// Loops the PHY back on itself at 100 Mbit/s full duplex and checks that a frame sent from
+    /// `mac_address` comes back unaltered. `exchange` sends the frame through the MAC and returns
+    /// the length of the one received, `None` if none came back in time.
+    ///
+    /// Run before `phy_init`, autonegotiation has to be restarted afterwards.
+    pub fn loopback_self_test(
+        &mut self,
+        mac_address: [u8; 6],
+        exchange: impl FnOnce(&[u8], &mut [u8]) -> Option<usize>,
+    ) -> Result<(), PhyError> {
+        self.mac.smi_write(
+            PHY_REG_BCR,
+            PHY_REG_BCR_LOOPBACK | PHY_REG_BCR_100M | PHY_REG_BCR_FD,
+        );
+
+        let mut frame = [0u8; LOOPBACK_FRAME_SIZE];
+        frame[..6].copy_from_slice(&mac_address);
+        frame[6..12].copy_from_slice(&mac_address);
+        frame[12..14].copy_from_slice(&LOOPBACK_ETHER_TYPE);
+        for (i, byte) in frame[14..].iter_mut().enumerate() {
+            *byte = (i as u8) ^ 0x5A;
+        }
+        // Room for the frame check sequence, should the MAC pass it on
+        let mut received = [0u8; LOOPBACK_FRAME_SIZE + 4];
+        let result = match exchange(&frame, &mut received) {
+            None => Err(PhyError::LoopbackLost),
+            Some(length) if length < frame.len() || received[..frame.len()] != frame => {
+                Err(PhyError::LoopbackCorrupted)
+            }
+            Some(_) => Ok(()),
+        };
+
+        self.restart_autonegotiation();
+        result
+    }
+}
+
+impl<MAC: StationManagement> LinkPhy for LAN8720A<MAC> {
+    /// Mode resolved by autonegotiation, from the special status register.
+    fn link_mode(&mut self) -> Option<LinkMode> {
+        if !self.poll_link() {
+            return None;
+        }
+        let ssr = self.mac.smi_read(PHY_REG_SSR);
+        if ssr & PHY_REG_SSR_ANDONE == 0 {
+            return None;
+        }
+        let (speed, full_duplex) = match ssr & PHY_REG_SSR_SPEED {
+            PHY_REG_SSR_SPEED_10BASE_T_HD => (LinkSpeed::Mbps10, false),
+            PHY_REG_SSR_SPEED_10BASE_T_FD => (LinkSpeed::Mbps10, true),
+            PHY_REG_SSR_SPEED_100BASE_TX_HD => (LinkSpeed::Mbps100, false),
+            PHY_REG_SSR_SPEED_100BASE_TX_FD => (LinkSpeed::Mbps100, true),
+            _ => return None,
+        };
+        Some(LinkMode { speed, full_duplex })
+    }
+
+    fn restart_autonegotiation(&mut self) {
+        LAN8720A::restart_autonegotiation(self);
+    }
+}
@@ -0,0 +1,2 @@
+pub mod dio;
+pub mod lan8720a;
@@ -1,8 +1,22 @@
 #![no_std]
 
+use controller::ButtonsAndSwitches;
+use data::boot::Slot;
+use data::connection::ConnectionMonitor;
+use data::ping::PingStatistics;
+use data::journal::{Device, FaultMonitor, Journal, JournalEvent, JournalPage, Reason};
+use data::io_population::{IoPopulation, IoSelfTest};
+use data::io_scan::IoScanStatus;
+use data::link::LinkStatus;
+use drivers::lan8720a::PhyDiagnostics;
+use data::recording::{RecordedCycle, Recorder};
+use data::status_report::StatusReport;
 use data::user_commands::UserCommands;
 
+mod controller;
 pub mod data;
+pub mod drivers;
+mod io;
 use serde_big_array::BigArray;
 use uom::si::f32::ElectricPotential;
 use uom::si::electric_potential::volt;
@@ -22,14 +36,145 @@ pub trait ControllerLogic {
     fn update_reporting(&self, firmware_reporting: &mut FirmwareReporting) {
         // NoOp
     }
+
+    /// Decides whether a command received from the control server is queued or rejected.
+    fn check_user_command(
+        &self,
+        _user_command: &UserCommand,
+        _hardware_status: &Wingman2HardwareStatus,
+    ) -> core::result::Result<(), NackReason> {
+        Ok(())
+    }
 }
 
 #[derive(Default, Clone)]
 pub struct FirmwareLogic {
+    buttons_and_switches: ButtonsAndSwitches,
+    journal: Journal,
+    fault_monitor: FaultMonitor,
+    configuration: Option<Configuration>,
+}
+
+impl FirmwareLogic {
+    /// `boot_id` is sent with every journal page, see `Journal::new`.
+    pub fn with_boot_id(boot_id: u32) -> Self {
+        Self {
+            journal: Journal::new(boot_id),
+            ..Self::default()
+        }
+    }
+
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    /// Queues a command received from the control server, or journals why it was rejected. The
+    /// network task of the card and the simulator both receive commands through here.
+    pub fn receive_command(
+        &mut self,
+        user_command: UserCommand,
+        user_commands: &mut UserCommands,
+        recorder: &mut Recorder,
+        hardware_status: &Wingman2HardwareStatus,
+    ) -> core::result::Result<(), NackReason> {
+        let result = self
+            .check_user_command(&user_command, hardware_status)
+            .and_then(|()| user_commands.push(user_command));
+        match result {
+            Ok(()) => recorder.record_command(user_command),
+            Err(reason) => self.journal_rejected_command(&user_command, reason, hardware_status),
+        }
+        result
+    }
+
+    /// One cycle of the `apply_logic` task, on the inputs already read: the commands queued are
+    /// applied, then the logic runs. The cycle is recorded when the control server asked for it.
+    pub fn run_cycle(
+        &mut self,
+        user_commands: &mut UserCommands,
+        recorder: &mut Recorder,
+        hardware_status: &mut Wingman2HardwareStatus,
+    ) {
+        recorder.begin_cycle(hardware_status);
+        self.apply_user_commands(user_commands, hardware_status);
+        self.update(hardware_status);
+        recorder.end_cycle(hardware_status);
+    }
+
+    /// Journals commands rejected before they reached the commands queue.
+    fn journal_rejected_command(
+        &mut self,
+        user_command: &UserCommand,
+        reason: NackReason,
+        hardware_status: &Wingman2HardwareStatus,
+    ) {
+        if reason == NackReason::Interlocked {
+            self.journal_interlock_trip(user_command, hardware_status);
+        }
+    }
+
+    fn journal_interlock_trip(
+        &mut self,
+        user_command: &UserCommand,
+        hardware_status: &Wingman2HardwareStatus,
+    ) {
+        let Some(board_map) = BoardMap::for_configuration(hardware_status.configuration) else {
+            return;
+        };
+        for output in board_map.digital_outputs_for(&user_command.command) {
+            self.journal.record(
+                hardware_status.now,
+                Device::DigitalOutput(output.address as u8),
+                JournalEvent::InterlockTripped(user_command.command),
+                Reason::Interlock,
+            );
+        }
+    }
+
+    /// Journals the commands that changed an output, or were held back by an interlock.
+    fn journal_applied_commands(
+        &mut self,
+        user_commands: &UserCommands,
+        hardware_status: &Wingman2HardwareStatus,
+        levels_before: &[Option<IoLevel>; 48],
+    ) {
+        let Some(board_map) = BoardMap::for_configuration(hardware_status.configuration) else {
+            return;
+        };
+
+        for user_command in user_commands.iter() {
+            let mut applied = false;
+            for output in board_map.digital_outputs_for(&user_command.command) {
+                if hardware_status.get_digital_output(&output) != levels_before[output.address] {
+                    applied = true;
+                    self.journal.record(
+                        hardware_status.now,
+                        Device::DigitalOutput(output.address as u8),
+                        JournalEvent::CommandApplied(user_command.command),
+                        Reason::ControlServer,
+                    );
+                }
+            }
+
+            if !applied
+                && self.check_user_command(user_command, hardware_status)
+                    == Err(NackReason::Interlocked)
+            {
+                self.journal_interlock_trip(user_command, hardware_status);
+            }
+        }
+    }
 }
 
 impl ControllerLogic for FirmwareLogic {
     fn initialize(&mut self, hardware_status: &mut Wingman2HardwareStatus) {
+        self.journal.record(
+            hardware_status.now,
+            Device::Card,
+            JournalEvent::Reboot,
+            Reason::Startup,
+        );
+        self.buttons_and_switches.initialize(hardware_status);
     }
 
     fn apply_user_commands(
@@ -37,25 +182,78 @@ impl ControllerLogic for FirmwareLogic {
         user_commands: &mut UserCommands,
         hardware_status: &mut Wingman2HardwareStatus,
     ) {
+        user_commands.remove_expired(hardware_status.now);
+        let levels_before = core::array::from_fn(|address| hardware_status.digital_outputs[address].level);
+        self.buttons_and_switches
+            .apply_user_commands(user_commands, hardware_status);
+        self.journal_applied_commands(user_commands, hardware_status, &levels_before);
+        user_commands.remove_applied_switches();
     }
 
-    fn update(&mut self, _hardware_status: &mut Wingman2HardwareStatus) {
+    fn update(&mut self, hardware_status: &mut Wingman2HardwareStatus) {
+        if self.configuration != Some(hardware_status.configuration) {
+            let reason = if self.configuration.is_none() {
+                Reason::Startup
+            } else {
+                Reason::Settings
+            };
+            self.configuration = Some(hardware_status.configuration);
+            self.journal.record(
+                hardware_status.now,
+                Device::Card,
+                JournalEvent::ConfigurationChanged(hardware_status.configuration),
+                reason,
+            );
+        }
+        self.fault_monitor.update(hardware_status, &mut self.journal);
+
+        self.buttons_and_switches.update(hardware_status);
+    }
+
+    fn check_user_command(
+        &self,
+        user_command: &UserCommand,
+        hardware_status: &Wingman2HardwareStatus,
+    ) -> core::result::Result<(), NackReason> {
+        let board_map = BoardMap::for_configuration(hardware_status.configuration)
+            .ok_or(NackReason::Unsupported)?;
+        if !board_map.supports(&user_command.command) {
+            return Err(NackReason::Unsupported);
+        }
+        if Controller::for_command(&user_command.command)
+            .is_some_and(|controller| !hardware_status.io_population.runs(controller, board_map))
+        {
+            return Err(NackReason::BankMissing);
+        }
+
+        self.buttons_and_switches
+            .check_user_command(user_command, hardware_status)
     }
 }
 
+/// The eight inputs of each DI bank, then the seven watched ports of each DO bank, see
+/// `DigitalInput::watched`.
+pub const DIGITAL_INPUTS: usize = 48 + 6 * WATCHED_LINES;
+/// Ports 24 to 30 of the MAX7301 of a DO bank, the only ones it watches for transitions.
+pub const WATCHED_LINES: usize = 7;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Wingman2HardwareStatus {
     pub step: u64,
     pub now: Timestamp,
     pub configuration: Configuration,
     #[serde(with = "BigArray")]
-    pub digital_inputs: [HardwareDigital; 48],
+    pub digital_inputs: [HardwareDigital; DIGITAL_INPUTS],
     #[serde(with = "BigArray")]
     pub digital_outputs: [HardwareDigital; 48],
     #[serde(with = "BigArray")]
     pub analog_inputs: [HardwareAnalog; 48],
     #[serde(with = "BigArray")]
     pub analog_outputs: [HardwareAnalog; 36],
+    /// Set by the network task, see `LinkMonitor`.
+    pub ethernet_link: LinkStatus,
+    /// Set at boot by the IO bank self-test, see `IoPopulation::runs`.
+    pub io_population: IoPopulation,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -100,6 +298,8 @@ impl Default for Wingman2HardwareStatus {
             digital_outputs: core::array::from_fn(|_| Default::default()),
             analog_inputs: core::array::from_fn(|_| Default::default()),
             analog_outputs: core::array::from_fn(|_| Default::default()),
+            ethernet_link: LinkStatus::default(),
+            io_population: IoPopulation::default(),
         }
     }
 }
@@ -123,6 +323,24 @@ impl Wingman2HardwareStatus {
             analog_outputs: core::array::from_fn(|_| HardwareAnalog {
                 voltage: ElectricPotential::new::<volt>(0.0),
             }),
+            ethernet_link: LinkStatus::default(),
+            io_population: IoPopulation::default(),
+        }
+    }
+}
+
+impl Wingman2HardwareStatus {
+    pub fn get_digital_input(&self, input: &DigitalInput) -> Option<IoLevel> {
+        self.digital_inputs.get(input.address)?.level
+    }
+
+    pub fn get_digital_output(&self, output: &DigitalOutput) -> Option<IoLevel> {
+        self.digital_outputs.get(output.address)?.level
+    }
+
+    pub fn set_digital_output(&mut self, output: &DigitalOutput, level: IoLevel) {
+        if let Some(digital_output) = self.digital_outputs.get_mut(output.address) {
+            digital_output.level = Some(level);
         }
     }
 }
@@ -145,6 +363,8 @@ pub enum IoError<SPI: Debug, HAL: Debug> {
     IllegalOutput,
     /// Unsupported Output Level
     UnsupportedLevel,
+    /// MAX7301 Port Read Back Differs From The Level Written
+    Stuck,
     /// SPI error.
     Spi(SPI),
     /// Error from HAL crate.
@@ -193,6 +413,18 @@ pub enum IoState {
     OpenOrFault,
 }
 
+/// Fault of a digital output found by writing it with verification, see `Dio::set_write_verify`.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputFault {
+    /// The EN port read back differs from the level commanded, the output did not follow.
+    Stuck,
+    /// STAT reports a fault while the output is off: no load is connected.
+    OpenLoad,
+    /// STAT reports a fault while the output is on: overload, short or overheated switch.
+    Overload,
+}
+
 impl IoLevel {
     pub fn toggle(self) -> Self {
         match self {
@@ -217,8 +449,11 @@ pub struct HardwareDigital {
     pub level: Option<IoLevel>,
     pub supply: Option<IoSupply>,
     pub temp: Option<IoTemp>,
+    /// Outputs only, `None` while fine or not verified.
+    pub fault: Option<OutputFault>,
 }
 
+/// Sent by the control server over TCP, one COBS framed postcard message per command.
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
 pub struct UserCommand {
     pub command: Command,
@@ -277,6 +512,43 @@ impl PartialOrd for UserCommand {
     }
 }
 
+/// Sent back to the control server over TCP for every frame received, COBS framed like the commands.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum CommandResponse {
+    /// The command was queued and will be applied by the logic.
+    Ack(Command),
+    /// The command was dropped. Carries no command if the frame could not be decoded.
+    Nack(Option<Command>, NackReason),
+}
+
+impl CommandResponse {
+    /// Room for any response once COBS framed. The longest, a `Nack` of a command carrying a
+    /// `DevicePosition` of 10 varint bytes, is 16 bytes before framing.
+    pub const MAX_FRAME_SIZE: usize = 20;
+
+    /// Answers `command` with the outcome of `FirmwareLogic::receive_command`.
+    pub fn for_command(command: Command, received: core::result::Result<(), NackReason>) -> Self {
+        match received {
+            Ok(()) => CommandResponse::Ack(command),
+            Err(reason) => CommandResponse::Nack(Some(command), reason),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NackReason {
+    /// The commands queue is full, the command can be sent again later.
+    QueueFull,
+    /// The device is not fitted in the current configuration.
+    Unsupported,
+    /// The command conflicts with a device that is currently active.
+    Interlocked,
+    /// The frame could not be decoded into a command.
+    DecodeError,
+    /// The controller of the device does not run, see `IoSelfTest::refused`.
+    BankMissing,
+}
+
 /// Used to number devices. Starts at 1. Front to back, left to right, as in aeronautics.
 pub type DevicePosition = usize;
 
@@ -329,6 +601,34 @@ pub enum Command {
     UnderwaterLight(SwitchCommand),
 }
 
+impl Command {
+    /// Button commands have to be repeated by the control server, all others are applied once.
+    pub fn is_button(&self) -> bool {
+        matches!(
+            self,
+            Command::BlackWaterPump(_)
+                | Command::EngineStart(..)
+                | Command::FoilDeploy(..)
+                | Command::FoilDown(..)
+                | Command::FoilIn(..)
+                | Command::FoilOut(..)
+                | Command::FoilRetract(..)
+                | Command::FoilUp(..)
+                | Command::HydraulicDcPumpOn(..)
+                | Command::RudderDeploy(..)
+                | Command::RudderDown(..)
+                | Command::RudderPark(..)
+                | Command::RudderRetract(..)
+                | Command::RudderTiltIn(..)
+                | Command::RudderTiltOut(..)
+                | Command::RudderUp(..)
+        )
+    }
+}
+
+/// Longest the starter motor of an engine is cranked in one go.
+pub const ENGINE_MAX_CRANK: Timestamp = Timestamp::new(5_000);
+
 #[derive(Default, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Timestamp {
     ms: u64,
@@ -338,6 +638,10 @@ impl Timestamp {
     pub const fn new(millis: u64) -> Self {
         Self { ms: millis }
     }
+
+    pub const fn as_millis(&self) -> u64 {
+        self.ms
+    }
 }
 
 impl Add<Timestamp> for Timestamp {
@@ -394,15 +698,224 @@ pub struct BoardMap {
     pub bilge_pumps: [Option<DigitalOutput>; 3],
     pub black_water_pump: Option<DigitalOutput>,
     pub engine_room_lights: [Option<DigitalOutput>; 2],
+    pub engine_ignitions: [Option<DigitalOutput>; 2],
+    pub engine_starters: [Option<DigitalOutput>; 2],
     // Digital Inputs
     pub engine_battery_port: Option<DigitalInput>,
     pub engine_battery_stbd: Option<DigitalInput>,
     pub bilge_pumps_running: [Option<DigitalInput>; 3],
+    pub engines_running: [Option<DigitalInput>; 2],
     // Analog Inputs
     pub fresh_water_level: Option<AnalogInput>,
     pub black_water_level: Option<AnalogInput>,
 }
 
+impl BoardMap {
+    /// Board map used by `Configuration::UnitTest`, one device per output starting at the first one.
+    pub const UNIT_TEST: BoardMap = BoardMap {
+        digital_input_count: 48,
+        digital_output_count: 48,
+        analog_input_count: 48,
+        analog_output_count: 36,
+        pulse_wave_modulation_count: 0,
+        anchor_up: Some(DigitalOutput::new(0)),
+        anchor_down: Some(DigitalOutput::new(1)),
+        anchor_light: Some(DigitalOutput::new(2)),
+        navigation_light: Some(DigitalOutput::new(3)),
+        courtesy_light: Some(DigitalOutput::new(4)),
+        ambient_light: Some(DigitalOutput::new(5)),
+        underwater_light: Some(DigitalOutput::new(6)),
+        bilge_pumps: [
+            Some(DigitalOutput::new(7)),
+            Some(DigitalOutput::new(8)),
+            Some(DigitalOutput::new(9)),
+        ],
+        black_water_pump: Some(DigitalOutput::new(10)),
+        engine_room_lights: [Some(DigitalOutput::new(11)), Some(DigitalOutput::new(12))],
+        engine_ignitions: [Some(DigitalOutput::new(13)), Some(DigitalOutput::new(14))],
+        engine_starters: [Some(DigitalOutput::new(15)), Some(DigitalOutput::new(16))],
+        engine_battery_port: Some(DigitalInput { address: 0 }),
+        engine_battery_stbd: Some(DigitalInput { address: 1 }),
+        bilge_pumps_running: [
+            Some(DigitalInput { address: 2 }),
+            Some(DigitalInput { address: 3 }),
+            Some(DigitalInput { address: 4 }),
+        ],
+        engines_running: [Some(DigitalInput { address: 5 }), Some(DigitalInput { address: 6 })],
+        fresh_water_level: Some(AnalogInput { address: 0 }),
+        black_water_level: Some(AnalogInput { address: 1 }),
+    };
+
+    /// Wiring of the standard harness, shared by the vessels until one of them differs: the
+    /// windlass and the lights on bank 1, the pumps and the engine room lights on bank 2 and the
+    /// engines on bank 3, their feedback on the DI bank of the same number.
+    pub const HARNESS: BoardMap = BoardMap {
+        digital_input_count: 48,
+        digital_output_count: 48,
+        analog_input_count: 48,
+        analog_output_count: 36,
+        pulse_wave_modulation_count: 0,
+        anchor_up: Some(DigitalOutput::new(0)),
+        anchor_down: Some(DigitalOutput::new(1)),
+        anchor_light: Some(DigitalOutput::new(2)),
+        navigation_light: Some(DigitalOutput::new(3)),
+        courtesy_light: Some(DigitalOutput::new(4)),
+        ambient_light: Some(DigitalOutput::new(5)),
+        underwater_light: Some(DigitalOutput::new(6)),
+        bilge_pumps: [
+            Some(DigitalOutput::new(8)),
+            Some(DigitalOutput::new(9)),
+            Some(DigitalOutput::new(10)),
+        ],
+        black_water_pump: Some(DigitalOutput::new(11)),
+        engine_room_lights: [Some(DigitalOutput::new(12)), Some(DigitalOutput::new(13))],
+        engine_ignitions: [Some(DigitalOutput::new(16)), Some(DigitalOutput::new(17))],
+        engine_starters: [Some(DigitalOutput::new(18)), Some(DigitalOutput::new(19))],
+        engine_battery_port: Some(DigitalInput { address: 18 }),
+        engine_battery_stbd: Some(DigitalInput { address: 19 }),
+        bilge_pumps_running: [
+            Some(DigitalInput { address: 8 }),
+            Some(DigitalInput { address: 9 }),
+            Some(DigitalInput { address: 10 }),
+        ],
+        engines_running: [Some(DigitalInput { address: 16 }), Some(DigitalInput { address: 17 })],
+        fresh_water_level: Some(AnalogInput { address: 0 }),
+        black_water_level: Some(AnalogInput { address: 1 }),
+    };
+
+    /// Returns `None` for a card not configured for a vessel yet, it drives nothing.
+    pub const fn for_configuration(configuration: Configuration) -> Option<&'static BoardMap> {
+        match configuration {
+            Configuration::Unconfigured => None,
+            Configuration::Prototype0
+            | Configuration::Prototype2
+            | Configuration::Spirit101
+            | Configuration::Spirit102
+            | Configuration::Spirit103
+            | Configuration::Spirit104 => Some(&Self::HARNESS),
+            Configuration::UnitTest => Some(&Self::UNIT_TEST),
+        }
+    }
+
+    /// The fitted outputs driven by `controller`.
+    pub fn digital_outputs_of(
+        &self,
+        controller: Controller,
+    ) -> impl Iterator<Item = DigitalOutput> {
+        let [engine_room_light_1, engine_room_light_2] = self.engine_room_lights;
+        let [ignition_1, ignition_2] = self.engine_ignitions;
+        let [starter_1, starter_2] = self.engine_starters;
+        let [bilge_pump_1, bilge_pump_2, bilge_pump_3] = self.bilge_pumps;
+
+        let outputs = match controller {
+            Controller::AnchorUpDown => {
+                [self.anchor_up, self.anchor_down, None, None, None, None, None]
+            }
+            Controller::EngineIgnition => {
+                [ignition_1, ignition_2, starter_1, starter_2, None, None, None]
+            }
+            Controller::Lights => [
+                self.anchor_light,
+                self.navigation_light,
+                self.courtesy_light,
+                self.ambient_light,
+                self.underwater_light,
+                engine_room_light_1,
+                engine_room_light_2,
+            ],
+            Controller::Pumps => [
+                bilge_pump_1,
+                bilge_pump_2,
+                bilge_pump_3,
+                self.black_water_pump,
+                None,
+                None,
+                None,
+            ],
+        };
+        outputs.into_iter().flatten()
+    }
+
+    /// The fitted inputs read by `controller`.
+    pub fn digital_inputs_of(&self, controller: Controller) -> impl Iterator<Item = DigitalInput> {
+        let inputs = match controller {
+            Controller::EngineIgnition => self.engines_running,
+            _ => [None; 2],
+        };
+        inputs.into_iter().flatten()
+    }
+
+    /// Whether the devices addressed by `command` are fitted on this board.
+    pub fn supports(&self, command: &Command) -> bool {
+        self.digital_outputs_for(command).next().is_some()
+    }
+
+    /// The fitted outputs driven by `command`.
+    pub fn digital_outputs_for(&self, command: &Command) -> impl Iterator<Item = DigitalOutput> {
+        fn select<const N: usize>(
+            devices: &[Option<DigitalOutput>; N],
+            identifier: &DeviceIdentifier,
+        ) -> [Option<DigitalOutput>; 3] {
+            core::array::from_fn(|index| match identifier {
+                DeviceIdentifier::All => devices.get(index).copied().flatten(),
+                DeviceIdentifier::Device(position) if *position == index + 1 => {
+                    devices.get(index).copied().flatten()
+                }
+                DeviceIdentifier::Device(_) => None,
+            })
+        }
+
+        let outputs = match command {
+            Command::AmbientLight(_) => [self.ambient_light, None, None],
+            Command::AnchorDown(_) => [self.anchor_down, None, None],
+            Command::AnchorLight(_) => [self.anchor_light, None, None],
+            Command::AnchorUp(_) => [self.anchor_up, None, None],
+            Command::BilgePump(identifier, _) => select(&self.bilge_pumps, identifier),
+            Command::BlackWaterPump(_) => [self.black_water_pump, None, None],
+            Command::CourtesyLight(_) => [self.courtesy_light, None, None],
+            Command::EngineIgnition(identifier, _) => select(&self.engine_ignitions, identifier),
+            Command::EngineRoomLight(identifier, _) => {
+                select(&self.engine_room_lights, identifier)
+            }
+            Command::EngineStart(identifier, _) => select(&self.engine_starters, identifier),
+            Command::NavigationLight(_) => [self.navigation_light, None, None],
+            Command::UnderwaterLight(_) => [self.underwater_light, None, None],
+            _ => [None; 3],
+        };
+        outputs.into_iter().flatten()
+    }
+}
+
+/// The controllers of the logic, each drives the devices of one kind.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, Serialize, Deserialize)]
+pub enum Controller {
+    AnchorUpDown,
+    EngineIgnition,
+    Lights,
+    Pumps,
+}
+
+impl Controller {
+    /// The controller `command` is applied by, `None` for commands no controller handles yet.
+    pub fn for_command(command: &Command) -> Option<Controller> {
+        match command {
+            Command::AnchorUp(_) | Command::AnchorDown(_) => Some(Controller::AnchorUpDown),
+            Command::EngineIgnition(..) | Command::EngineStart(..) => {
+                Some(Controller::EngineIgnition)
+            }
+            Command::AmbientLight(_)
+            | Command::AnchorLight(_)
+            | Command::CourtesyLight(_)
+            | Command::EngineRoomLight(..)
+            | Command::NavigationLight(_)
+            | Command::UnderwaterLight(_) => Some(Controller::Lights),
+            Command::BilgePump(..) | Command::BlackWaterPump(_) => Some(Controller::Pumps),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct BoardDeviceData<T> {
     pub topic_name: &'static str,
@@ -422,10 +935,73 @@ impl<T> BoardDeviceData<T> {
 
 
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
-pub struct FirmwareReporting {}
+pub struct FirmwareReporting {
+    /// Filled in by the network task as the reporting is sent.
+    pub network: NetworkStatus,
+    /// Filled in by the network task from the pings to the control server.
+    pub control_server_pings: PingStatistics,
+    pub boot: BootStatus,
+    /// Filled in by the network task, see `Wingman2IOCardStatus::update`.
+    pub card: Wingman2IOCardStatus,
+    /// Filled in by the IO scan task.
+    pub io_scan: IoScanStatus,
+    /// Filled in at boot.
+    pub io_self_test: IoSelfTest,
+}
+
+/// Image the card runs and whether the bootloader had to roll back, see `data::boot`.
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BootStatus {
+    /// `None` when the image was flashed by a debugger rather than booted from a slot.
+    pub running: Option<Slot>,
+    /// The running image still has to confirm itself, the bootloader rolls back if it fails to.
+    pub pending_confirmation: bool,
+    /// Boots of the running image while unconfirmed, this one included.
+    pub attempts: u8,
+    /// The bootloader rolled back from this slot, its image failed `MAX_BOOT_ATTEMPTS` boots.
+    pub rolled_back_from: Option<Slot>,
+}
+
+/// Address the card is currently reachable at.
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkStatus {
+    pub mac_address: [u8; 6],
+    pub ip_address: [u8; 4],
+    pub prefix_length: u8,
+    pub gateway: Option<[u8; 4]>,
+    pub source: AddressSource,
+    pub phy: PhyDiagnostics,
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AddressSource {
+    /// Waiting for a DHCP lease, the card has no address yet.
+    #[default]
+    Unconfigured,
+    /// The static address from the settings.
+    Static,
+    /// Leased from the DHCP server at `server`, at `leased_at`.
+    Dhcp { server: [u8; 4], leased_at: Timestamp },
+}
+
+/// Everything the card sends to the control server over UDP, one postcard message per datagram.
+// Datagrams are serialized right away, boxing the journal page would need an allocator
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum FirmwareDatagram {
+    Status(StatusReport),
+    Reporting(FirmwareReporting),
+    Journal(JournalPage),
+    Recording(RecordedCycle),
+}
 
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
-pub struct ServerReporting {}
+pub struct ServerReporting {
+    /// Asks for the journal entries starting at this sequence, answered with a `JournalPage`.
+    pub journal_request: Option<u32>,
+    /// Streams every `apply_logic` cycle as a `RecordedCycle` while set.
+    pub record: bool,
+}
 
 #[derive(
     Eq,
@@ -453,11 +1029,18 @@ pub enum Configuration {
     UnitTest = 9999,
 }
 
+/// Connection of the card to the control server, published in the reporting and shown on the
+/// display.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Wingman2IOCardStatus {
     pub is_connected_to_control_server: bool,
+    /// From 0, disconnected, to 100, see `ConnectionMonitor::qos`.
     pub control_server_qos: u8,
     pub control_server_received_messages: u64,
+    pub control_server_jitter_ms: u32,
+    pub control_server_gaps: u32,
+    pub control_server_longest_gap_ms: u64,
+    /// Updates since boot.
     tick: u64,
     total_uptime_ms: i64,
     pub configuration: Configuration,
@@ -466,15 +1049,54 @@ pub struct Wingman2IOCardStatus {
 #[allow(clippy::should_implement_trait)]
 impl Wingman2IOCardStatus {
     pub const fn default() -> Self {
+        Self::new(Configuration::Unconfigured)
+    }
+
+    pub const fn new(configuration: Configuration) -> Self {
         Self {
             tick: 0,
             is_connected_to_control_server: false,
             control_server_qos: 0,
             control_server_received_messages: 0,
+            control_server_jitter_ms: 0,
+            control_server_gaps: 0,
+            control_server_longest_gap_ms: 0,
             total_uptime_ms: 0,
-            configuration: Configuration::Unconfigured,
+            configuration,
         }
     }
+
+    /// Refreshes the status from the traffic of the control server and its pings at `now`.
+    pub fn update(
+        &mut self,
+        now: Timestamp,
+        connection: &ConnectionMonitor,
+        pings: &PingStatistics,
+    ) {
+        let statistics = connection.statistics();
+        self.tick += 1;
+        self.total_uptime_ms = now.as_millis() as i64;
+        self.is_connected_to_control_server = connection.is_connected(now);
+        self.control_server_qos = connection.qos(now, pings);
+        self.control_server_received_messages = statistics.received_messages;
+        self.control_server_jitter_ms = statistics.jitter_ms;
+        self.control_server_gaps = statistics.gaps;
+        self.control_server_longest_gap_ms = statistics.longest_interval_ms;
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn total_uptime_ms(&self) -> i64 {
+        self.total_uptime_ms
+    }
+}
+
+impl Default for Wingman2IOCardStatus {
+    fn default() -> Self {
+        Wingman2IOCardStatus::default()
+    }
 }
 
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
@@ -491,16 +1113,39 @@ pub enum IOAddress {
     AnalogOutputAddress(AnalogOutput),
     PulseWidthModulationAddress(PulseWidthModulation),
 }
+/// `address` is the index into `Wingman2HardwareStatus::digital_inputs`.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct DigitalInput {
     pub address: usize,
 }
+
+impl DigitalInput {
+    /// Port 24 + `line` of the DO bank `bank`. A change wakes the IO scan right away, the e-stop
+    /// and the float switches are wired to these.
+    pub const fn watched(bank: IoBank, line: usize) -> Self {
+        Self {
+            address: 48 + bank as usize * WATCHED_LINES + line,
+        }
+    }
+}
+/// `address` is the index into `Wingman2HardwareStatus::digital_outputs`.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct DigitalOutput {
     pub address: usize,
     pub value_on_error: bool,
     pub bank_voltage: u8,
 }
+
+impl DigitalOutput {
+    /// A 24 V output that is switched off on error.
+    pub const fn new(address: usize) -> Self {
+        Self {
+            address,
+            value_on_error: false,
+            bank_voltage: 24,
+        }
+    }
+}
 #[repr(C)]
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct AnalogInput {
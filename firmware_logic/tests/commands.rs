@@ -0,0 +1,139 @@
+use firmware_logic::data::recording::Recorder;
+use firmware_logic::data::user_commands::UserCommands;
+use firmware_logic::{
+    ButtonCommand, Command, CommandResponse, Configuration, ControllerLogic, DeviceIdentifier,
+    DigitalOutput, FirmwareLogic, IoLevel, NackReason, SwitchCommand, Timestamp, UserCommand,
+    Wingman2HardwareStatus,
+};
+use strum::IntoEnumIterator;
+
+const COMMANDS_BUFFER_SIZE: usize = 32;
+
+fn receive(
+    command: Command,
+    user_commands: &mut UserCommands,
+    hardware_status: &Wingman2HardwareStatus,
+) -> CommandResponse {
+    let received = FirmwareLogic::default().receive_command(
+        UserCommand::new(command, Some(hardware_status.now)),
+        user_commands,
+        &mut Recorder::default(),
+        hardware_status,
+    );
+    CommandResponse::for_command(command, received)
+}
+
+#[test]
+fn a_supported_command_is_acked_and_queued() {
+    let hardware_status = Wingman2HardwareStatus::new(Configuration::UnitTest);
+    let mut user_commands = UserCommands::default();
+    let command = Command::AnchorLight(SwitchCommand::On);
+    assert_eq!(
+        receive(command, &mut user_commands, &hardware_status),
+        CommandResponse::Ack(command)
+    );
+    assert_eq!(user_commands.len(), 1);
+}
+
+#[test]
+fn every_vessel_has_its_devices_mapped() {
+    let command = Command::AnchorLight(SwitchCommand::On);
+    for configuration in Configuration::iter().filter(|&c| c != Configuration::Unconfigured) {
+        let hardware_status = Wingman2HardwareStatus::new(configuration);
+        assert_eq!(
+            receive(command, &mut UserCommands::default(), &hardware_status),
+            CommandResponse::Ack(command),
+            "{configuration}"
+        );
+    }
+}
+
+#[test]
+fn a_command_for_a_device_not_fitted_is_nacked() {
+    // A card not configured for a vessel yet drives nothing
+    let unconfigured = Wingman2HardwareStatus::new(Configuration::Unconfigured);
+    let mut user_commands = UserCommands::default();
+    let command = Command::AnchorLight(SwitchCommand::On);
+    assert_eq!(
+        receive(command, &mut user_commands, &unconfigured),
+        CommandResponse::Nack(Some(command), NackReason::Unsupported)
+    );
+
+    // The unit test board has three bilge pumps
+    let hardware_status = Wingman2HardwareStatus::new(Configuration::UnitTest);
+    let command = Command::BilgePump(DeviceIdentifier::Device(4), SwitchCommand::On);
+    assert_eq!(
+        receive(command, &mut user_commands, &hardware_status),
+        CommandResponse::Nack(Some(command), NackReason::Unsupported)
+    );
+    assert!(user_commands.is_empty());
+}
+
+#[test]
+fn a_full_queue_nacks_new_commands_but_refreshes_queued_ones() {
+    let mut user_commands = UserCommands::default();
+    let command = |position| {
+        UserCommand::new(
+            Command::FoilUp(DeviceIdentifier::Device(position), ButtonCommand::On),
+            Some(Timestamp::new(0)),
+        )
+    };
+    for position in 0..COMMANDS_BUFFER_SIZE {
+        user_commands.push(command(position)).unwrap();
+    }
+    assert_eq!(
+        user_commands.push(command(COMMANDS_BUFFER_SIZE)),
+        Err(NackReason::QueueFull)
+    );
+
+    // A held button keeps being accepted
+    let repeated = UserCommand::new(command(3).command, Some(Timestamp::new(100)));
+    user_commands.push(repeated).unwrap();
+    assert_eq!(user_commands.len(), COMMANDS_BUFFER_SIZE);
+    assert_eq!(
+        user_commands
+            .iter()
+            .find(|queued| queued.command == repeated.command)
+            .unwrap()
+            .timestamp,
+        repeated.timestamp
+    );
+}
+
+#[test]
+fn the_windlass_is_interlocked_while_driven() {
+    let mut logic = FirmwareLogic::default();
+    let mut hardware_status = Wingman2HardwareStatus::new(Configuration::UnitTest);
+    let mut user_commands = UserCommands::default();
+    logic.initialize(&mut hardware_status);
+
+    let down = Command::AnchorDown(SwitchCommand::On);
+    assert_eq!(
+        receive(down, &mut user_commands, &hardware_status),
+        CommandResponse::Ack(down)
+    );
+    logic.apply_user_commands(&mut user_commands, &mut hardware_status);
+    assert_eq!(
+        hardware_status.get_digital_output(&DigitalOutput::new(1)),
+        Some(IoLevel::High)
+    );
+
+    let up = Command::AnchorUp(SwitchCommand::On);
+    assert_eq!(
+        receive(up, &mut user_commands, &hardware_status),
+        CommandResponse::Nack(Some(up), NackReason::Interlocked)
+    );
+}
+
+#[test]
+fn the_longest_response_fits_its_frame() {
+    let command = Command::RudderTiltOut(DeviceIdentifier::Device(usize::MAX), ButtonCommand::On);
+    for response in [
+        CommandResponse::Ack(command),
+        CommandResponse::Nack(Some(command), NackReason::Interlocked),
+        CommandResponse::Nack(None, NackReason::DecodeError),
+    ] {
+        let mut buf = [0u8; CommandResponse::MAX_FRAME_SIZE];
+        assert!(postcard::to_slice_cobs(&response, &mut buf).is_ok());
+    }
+}
@@ -0,0 +1,107 @@
+//! Arbitrary bytes into every decode path of the card and of the control server.
+//!
+//! Run on the host: `cargo test -p firmware_logic --target x86_64-unknown-linux-gnu`.
+
+use firmware_logic::{
+    data::{
+        frames::CommandFrames,
+        recording::RecordedCycle,
+        status_report::StatusReport,
+    },
+    Command, FirmwareDatagram, ServerReporting, UserCommand, Wingman2HardwareStatus,
+};
+use proptest::{collection::vec, prelude::*};
+
+/// Valid commands, built by decoding bytes narrowed down to plausible variant and field indices
+/// so that new `Command` variants are covered without being listed here.
+fn user_commands() -> impl Strategy<Value = Vec<UserCommand>> {
+    vec((any::<[u8; 4]>(), any::<Option<u32>>()), 0..16).prop_map(|candidates| {
+        candidates
+            .into_iter()
+            .filter_map(|(bytes, timestamp)| {
+                let bytes = bytes.map(|byte| byte % 32);
+                let command = postcard::from_bytes::<Command>(&bytes).ok()?;
+                let timestamp = timestamp.map(|ms| firmware_logic::Timestamp::new(ms.into()));
+                Some(UserCommand::new(command, timestamp))
+            })
+            .collect()
+    })
+}
+
+proptest! {
+    #[test]
+    fn command_frames_never_panic(chunks in vec(vec(any::<u8>(), 0..600), 0..8)) {
+        let mut frames = CommandFrames::<512>::default();
+        for chunk in &chunks {
+            frames.feed(chunk, |_| {});
+        }
+    }
+
+    #[test]
+    fn command_frames_survive_garbage_and_any_split(
+        user_commands in user_commands(),
+        garbage in vec(1..=u8::MAX, 0..400),
+        split in any::<prop::sample::Index>(),
+        chunk_size in 1..64usize,
+    ) {
+        // Garbage terminated by a frame delimiter is reported as one bad frame, the commands after
+        // it are still decoded. It starts with an unknown command variant so it never decodes.
+        let mut stream = vec![0xFF, 0x7F];
+        stream.extend(&garbage);
+        stream.push(0);
+        for user_command in &user_commands {
+            stream.extend(postcard::to_stdvec_cobs(user_command).unwrap());
+        }
+        let (first, second) = stream.split_at(split.index(stream.len() + 1).min(stream.len()));
+
+        let mut frames = CommandFrames::<512>::default();
+        let mut decoded = Vec::new();
+        let mut errors = 0;
+        for chunk in first.chunks(chunk_size).chain(second.chunks(chunk_size)) {
+            frames.feed(chunk, |frame| match frame {
+                Ok(user_command) => decoded.push(user_command),
+                Err(_) => errors += 1,
+            });
+        }
+
+        prop_assert_eq!(decoded, user_commands);
+        prop_assert_eq!(errors, 1);
+    }
+
+    #[test]
+    fn server_reporting_never_panics(datagram in vec(any::<u8>(), 0..64)) {
+        let _ = ServerReporting::from_datagram(&datagram);
+    }
+
+    #[test]
+    fn server_reporting_round_trips(journal_request in any::<Option<u32>>(), record in any::<bool>()) {
+        let server_reporting = ServerReporting { journal_request, record };
+        let datagram = postcard::to_stdvec(&server_reporting).unwrap();
+        let decoded = ServerReporting::from_datagram(&datagram).unwrap();
+        prop_assert_eq!(decoded.journal_request, journal_request);
+        prop_assert_eq!(decoded.record, record);
+    }
+
+    #[test]
+    fn firmware_datagrams_never_panic(datagram in vec(any::<u8>(), 0..1_472)) {
+        if let Ok(FirmwareDatagram::Status(StatusReport::Delta(delta))) =
+            postcard::from_bytes::<FirmwareDatagram>(&datagram)
+        {
+            let snapshot = Wingman2HardwareStatus {
+                step: delta.snapshot_step,
+                ..Default::default()
+            };
+            let mut status = Wingman2HardwareStatus::default();
+            prop_assert!(delta.apply_to(&snapshot, &mut status));
+        }
+    }
+
+    #[test]
+    fn recorded_cycles_never_panic(frame in vec(any::<u8>(), 0..1_024)) {
+        if let Ok(cycle) = postcard::from_bytes::<RecordedCycle>(&frame) {
+            let mut status = Wingman2HardwareStatus::default();
+            cycle.restore_inputs(&mut status);
+            let _ = cycle.output_level(usize::MAX);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use firmware_logic::data::journal::{
+    Device, FaultMonitor, Journal, JournalEntry, JournalEvent, Reason, JOURNAL_PAGE_SIZE,
+};
+use firmware_logic::{
+    Configuration, IoState, IoSupply, IoTemp, OutputFault, Timestamp, Wingman2HardwareStatus,
+};
+
+const JOURNAL_CAPACITY: u32 = 128;
+
+fn journal_of(entries: u32) -> Journal {
+    let mut journal = Journal::new(7);
+    for index in 0..entries {
+        journal.record(
+            Timestamp::new(index.into()),
+            Device::Card,
+            JournalEvent::Reboot,
+            Reason::Startup,
+        );
+    }
+    journal
+}
+
+fn sequences(entries: &[JournalEntry]) -> Vec<u32> {
+    entries.iter().map(|entry| entry.sequence).collect()
+}
+
+#[test]
+fn the_oldest_entries_are_overwritten() {
+    let journal = journal_of(JOURNAL_CAPACITY + 5);
+    assert_eq!(journal.first_sequence(), 5);
+    assert_eq!(journal.next_sequence(), JOURNAL_CAPACITY + 5);
+    assert_eq!(journal.iter().count(), JOURNAL_CAPACITY as usize);
+    assert_eq!(journal.iter().next().unwrap().timestamp, Timestamp::new(5));
+
+    let empty = Journal::new(7);
+    assert_eq!(empty.first_sequence(), 0);
+    assert_eq!(empty.page_from(0).entries.len(), 0);
+}
+
+#[test]
+fn pages_start_at_the_cursor() {
+    let journal = journal_of(40);
+    let page = journal.page_from(0);
+    assert_eq!(page.boot_id, 7);
+    assert_eq!(page.first_sequence, 0);
+    assert_eq!(page.next_sequence, 40);
+    assert_eq!(
+        sequences(&page.entries),
+        (0..JOURNAL_PAGE_SIZE as u32).collect::<Vec<_>>()
+    );
+
+    let page = journal.page_from(JOURNAL_PAGE_SIZE as u32);
+    assert_eq!(
+        sequences(&page.entries),
+        (JOURNAL_PAGE_SIZE as u32..40).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn a_cursor_before_the_tail_resumes_at_the_oldest_entry() {
+    let journal = journal_of(JOURNAL_CAPACITY + 30);
+    let page = journal.page_from(10);
+    assert_eq!(page.first_sequence, 30);
+    assert_eq!(page.entries[0].sequence, 30);
+    assert_eq!(page.entries.len(), JOURNAL_PAGE_SIZE);
+}
+
+#[test]
+fn a_cursor_past_the_head_gets_an_empty_page() {
+    // As when the card rebooted since the control server last asked, the boot id tells it
+    let journal = journal_of(10);
+    let page = journal.page_from(500);
+    assert!(page.entries.is_empty());
+    assert_eq!(page.first_sequence, 0);
+    assert_eq!(page.next_sequence, 10);
+
+    let rebooted = Journal::new(8);
+    assert_ne!(rebooted.page_from(10).boot_id, page.boot_id);
+}
+
+#[test]
+fn faults_are_journaled_when_raised_and_cleared() {
+    let mut status = Wingman2HardwareStatus::new(Configuration::UnitTest);
+    let mut journal = Journal::new(1);
+    let mut monitor = FaultMonitor::default();
+    monitor.update(&status, &mut journal);
+    assert_eq!(journal.iter().count(), 0);
+
+    status.now = Timestamp::new(100);
+    status.digital_inputs[4].state = Some(IoState::OpenOrFault);
+    status.digital_inputs[4].supply = Some(IoSupply::Unpowered);
+    status.digital_outputs[9].fault = Some(OutputFault::Stuck);
+    monitor.update(&status, &mut journal);
+    let raised: Vec<_> = journal
+        .iter()
+        .map(|entry| (entry.device, entry.event, entry.reason))
+        .collect();
+    assert_eq!(
+        raised,
+        [
+            (
+                Device::DigitalInput(4),
+                JournalEvent::FaultRaised,
+                Reason::OpenOrFault
+            ),
+            (
+                Device::DigitalInput(4),
+                JournalEvent::FaultRaised,
+                Reason::SupplyLost
+            ),
+            (
+                Device::DigitalOutput(9),
+                JournalEvent::FaultRaised,
+                Reason::OutputStuck
+            ),
+        ]
+    );
+
+    // A fault still present is not journaled again
+    status.now = Timestamp::new(200);
+    monitor.update(&status, &mut journal);
+    assert_eq!(journal.next_sequence(), 3);
+
+    status.now = Timestamp::new(300);
+    status.digital_inputs[4].supply = Some(IoSupply::Powered);
+    status.digital_outputs[9].temp = Some(IoTemp::Hot);
+    monitor.update(&status, &mut journal);
+    let latest: Vec<_> = journal
+        .iter()
+        .skip(3)
+        .map(|entry| (entry.timestamp, entry.device, entry.event, entry.reason))
+        .collect();
+    assert_eq!(
+        latest,
+        [
+            (
+                Timestamp::new(300),
+                Device::DigitalInput(4),
+                JournalEvent::FaultCleared,
+                Reason::SupplyLost
+            ),
+            (
+                Timestamp::new(300),
+                Device::DigitalOutput(9),
+                JournalEvent::FaultRaised,
+                Reason::Overtemperature
+            ),
+        ]
+    );
+}
@@ -0,0 +1,212 @@
+use firmware_logic::data::status_report::{PackedDigital, StatusReport, StatusReporter};
+use firmware_logic::{
+    Configuration, HardwareDigital, IoLevel, IoState, IoSupply, IoTemp, OutputFault, Timestamp,
+    Wingman2HardwareStatus,
+};
+use uom::si::electric_potential::volt;
+use uom::si::f32::ElectricPotential;
+
+fn status_at(ms: u64) -> Wingman2HardwareStatus {
+    let mut status = Wingman2HardwareStatus::new(Configuration::UnitTest);
+    status.now = Timestamp::new(ms);
+    status.step = ms;
+    status
+}
+
+fn volts(volts: f32) -> ElectricPotential {
+    ElectricPotential::new::<volt>(volts)
+}
+
+fn packed(digitals: &[HardwareDigital]) -> Vec<PackedDigital> {
+    digitals.iter().map(PackedDigital::from).collect()
+}
+
+#[test]
+fn every_digital_channel_round_trips_through_its_packed_form() {
+    let levels = [
+        None,
+        Some(IoLevel::High),
+        Some(IoLevel::Low),
+        Some(IoLevel::HiZ),
+    ];
+    let states = [None, Some(IoState::Normal), Some(IoState::OpenOrFault)];
+    let supplies = [
+        None,
+        Some(IoSupply::Volts24),
+        Some(IoSupply::Volts12),
+        Some(IoSupply::Powered),
+        Some(IoSupply::Unpowered),
+    ];
+    let temps = [
+        None,
+        Some(IoTemp::Normal),
+        Some(IoTemp::Warm),
+        Some(IoTemp::Hot),
+    ];
+    let faults = [
+        None,
+        Some(OutputFault::Stuck),
+        Some(OutputFault::OpenLoad),
+        Some(OutputFault::Overload),
+    ];
+
+    let mut seen = std::collections::HashSet::new();
+    for level in levels {
+        for state in states {
+            for supply in supplies {
+                for temp in temps {
+                    for fault in faults {
+                        let digital = HardwareDigital {
+                            state,
+                            level,
+                            supply,
+                            temp,
+                            fault,
+                        };
+                        let bits = PackedDigital::from(&digital);
+                        assert!(bits.0 < 1 << 11, "{digital:?} packs into {:#b}", bits.0);
+                        assert!(
+                            seen.insert(bits.0),
+                            "{digital:?} packs like another channel"
+                        );
+
+                        let unpacked = HardwareDigital::from(bits);
+                        assert_eq!(unpacked.level, level);
+                        assert_eq!(unpacked.state, state);
+                        assert_eq!(unpacked.supply, supply);
+                        assert_eq!(unpacked.temp, temp);
+                        assert_eq!(unpacked.fault, fault);
+                    }
+                }
+            }
+        }
+    }
+    assert_eq!(
+        PackedDigital::from(&HardwareDigital::default()),
+        PackedDigital(0)
+    );
+}
+
+#[test]
+fn a_delta_applied_to_its_snapshot_gives_the_status_it_was_computed_from() {
+    let mut reporter = StatusReporter::default();
+    let snapshot = status_at(0);
+    let Some(StatusReport::Snapshot(received)) = reporter.report(&snapshot) else {
+        panic!("the first report is a snapshot");
+    };
+
+    let mut status = status_at(100);
+    status.digital_inputs[3].level = Some(IoLevel::High);
+    status.digital_inputs[47].supply = Some(IoSupply::Unpowered);
+    status.digital_outputs[12].fault = Some(OutputFault::OpenLoad);
+    status.analog_inputs[5].voltage = volts(11.8);
+    // Below the deadband, left for the next snapshot
+    status.analog_inputs[6].voltage = volts(0.01);
+    status.analog_outputs[35].voltage = volts(4.0);
+    let Some(StatusReport::Delta(delta)) = reporter.report(&status) else {
+        panic!("changes within the snapshot period are sent as a delta");
+    };
+    assert_eq!(delta.snapshot_step, snapshot.step);
+    assert_eq!(delta.digital_inputs.changed, 1 << 3 | 1 << 47);
+    assert_eq!(delta.digital_outputs.channels.len(), 1);
+    assert_eq!(delta.analog_inputs.changed, 1 << 5);
+
+    let mut applied = status_at(50);
+    assert!(delta.apply_to(&received, &mut applied));
+    assert_eq!(applied.step, status.step);
+    assert_eq!(applied.now, status.now);
+    assert_eq!(
+        packed(&applied.digital_inputs),
+        packed(&status.digital_inputs)
+    );
+    assert_eq!(
+        packed(&applied.digital_outputs),
+        packed(&status.digital_outputs)
+    );
+    assert_eq!(applied.analog_inputs[5].voltage, volts(11.8));
+    assert_eq!(applied.analog_inputs[6].voltage, volts(0.0));
+    assert_eq!(applied.analog_outputs[35].voltage, volts(4.0));
+
+    // Another snapshot than the one the delta was computed from
+    let other = status_at(7);
+    let mut untouched = status_at(50);
+    assert!(!delta.apply_to(&other, &mut untouched));
+    assert_eq!(untouched.step, 50);
+    assert_eq!(untouched.digital_inputs[3].level, None);
+}
+
+#[test]
+fn deltas_are_sent_periodically_between_snapshots() {
+    let mut reporter = StatusReporter::default();
+    assert!(matches!(
+        reporter.report(&status_at(0)),
+        Some(StatusReport::Snapshot(_))
+    ));
+    assert!(reporter.report(&status_at(10)).is_none());
+    assert!(reporter.report(&status_at(24)).is_none());
+    assert!(matches!(
+        reporter.report(&status_at(25)),
+        Some(StatusReport::Delta(_))
+    ));
+
+    // Input levels wait for the delta period
+    let mut status = status_at(30);
+    status.digital_inputs[0].level = Some(IoLevel::High);
+    assert!(reporter.report(&status).is_none());
+    status.now = Timestamp::new(50);
+    let Some(StatusReport::Delta(delta)) = reporter.report(&status) else {
+        panic!("the input change is sent with the next delta");
+    };
+    assert_eq!(delta.digital_inputs.changed, 1);
+
+    // A snapshot replaces the deltas every second
+    assert!(matches!(
+        reporter.report(&status_at(999)),
+        Some(StatusReport::Delta(_))
+    ));
+    let Some(StatusReport::Snapshot(snapshot)) = reporter.report(&status_at(1_000)) else {
+        panic!("a snapshot is due a second after the previous one");
+    };
+    assert_eq!(snapshot.step, 1_000);
+    let Some(StatusReport::Delta(delta)) = reporter.report(&status_at(1_025)) else {
+        panic!("deltas resume after the snapshot");
+    };
+    assert_eq!(delta.snapshot_step, 1_000);
+}
+
+#[test]
+fn output_and_fault_changes_are_sent_right_away() {
+    let mut reporter = StatusReporter::default();
+    reporter.report(&status_at(0));
+
+    let mut status = status_at(1);
+    status.digital_outputs[20].level = Some(IoLevel::High);
+    let Some(StatusReport::Delta(delta)) = reporter.report(&status) else {
+        panic!("an output change is urgent");
+    };
+    assert_eq!(delta.digital_outputs.changed, 1 << 20);
+    status.now = Timestamp::new(2);
+    assert!(reporter.report(&status).is_none());
+
+    status.now = Timestamp::new(3);
+    status.digital_inputs[9].state = Some(IoState::OpenOrFault);
+    assert!(matches!(
+        reporter.report(&status),
+        Some(StatusReport::Delta(_))
+    ));
+}
+
+#[test]
+fn a_snapshot_is_sent_on_request() {
+    let mut reporter = StatusReporter::default();
+    reporter.report(&status_at(0));
+    assert!(reporter.report(&status_at(5)).is_none());
+
+    // As after the control server reconnected
+    reporter.request_snapshot();
+    assert!(matches!(
+        reporter.report(&status_at(6)),
+        Some(StatusReport::Snapshot(_))
+    ));
+    assert!(reporter.report(&status_at(7)).is_none());
+}
@@ -0,0 +1,167 @@
+//! Adds the image header to a firmware binary and signs it, see `firmware_logic::data::image`.
+//!
+//! Built for the host, like the simulator:
+//! `cargo run -p image_tool --target x86_64-unknown-linux-gnu -- --help`.
+
+use std::{error::Error, fs, path::PathBuf};
+
+use clap::{Parser, Subcommand};
+use ed25519_compact::{KeyPair, Noise, Seed};
+use firmware_logic::{
+    data::{
+        boot::Slot,
+        image::{verify_signature, BuildInfo, ImageHeader, IMAGE_HEADER_SIZE, PUBLIC_KEY_SIZE},
+        update::image_crc,
+    },
+    Configuration,
+};
+
+#[derive(Parser)]
+#[command(about = "Signs firmware images for the bootloader and the updater")]
+struct Cli {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// Writes a new key pair to `<name>.key` and `<name>.pub`, in hexadecimal. The firmware is built
+    /// with `UPDATE_PUBLIC_KEY=<name>.pub` to accept the images it signs.
+    Keygen { name: PathBuf },
+    /// Prepends the signed header to a raw binary, e.g. from `cargo objcopy -- -O binary`.
+    Sign {
+        /// Secret key written by `keygen`.
+        #[arg(long)]
+        key: PathBuf,
+        /// Configuration of the cards the image is built for, may be repeated.
+        #[arg(long = "configuration", required = true, value_parser = parse_configuration)]
+        configurations: Vec<Configuration>,
+        binary: PathBuf,
+        image: PathBuf,
+    },
+    /// Prints the header of an image and checks it, and its signature if a public key is given.
+    Show {
+        #[arg(long)]
+        public_key: Option<PathBuf>,
+        image: PathBuf,
+    },
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    match Cli::parse().action {
+        Action::Keygen { name } => {
+            let key_pair = KeyPair::from_seed(Seed::generate());
+            fs::write(name.with_extension("key"), hex(key_pair.sk.seed().as_ref()))?;
+            fs::write(name.with_extension("pub"), hex(key_pair.pk.as_ref()))?;
+            Ok(())
+        }
+        Action::Sign {
+            key,
+            configurations,
+            binary,
+            image,
+        } => {
+            let seed: [u8; Seed::BYTES] = read_hex(&key)?;
+            let binary = fs::read(binary)?;
+            let signed = sign(
+                &KeyPair::from_seed(Seed::new(seed)),
+                &configurations,
+                &binary,
+            )?;
+            fs::write(image, signed)?;
+            Ok(())
+        }
+        Action::Show { public_key, image } => {
+            let image = fs::read(image)?;
+            let header = ImageHeader::decode(&image).map_err(|err| format!("{err:?}"))?;
+            let git_hash = header
+                .git_hash
+                .map_or("unknown".to_string(), |hash| hex(&hash));
+            println!("version {}", header.version);
+            println!(
+                "commit {git_hash}{}",
+                if header.git_dirty { " (dirty)" } else { "" }
+            );
+            println!("configurations {:?}", header.configurations);
+            println!("{} bytes, CRC {:08x}", header.length, header.crc);
+            header.check_crc(&image).map_err(|err| format!("{err:?}"))?;
+            println!("linked for {:?}", linked_slot(&image[IMAGE_HEADER_SIZE..]));
+
+            if let Some(public_key) = public_key {
+                let public_key: [u8; PUBLIC_KEY_SIZE] = read_hex(&public_key)?;
+                let header = image[..IMAGE_HEADER_SIZE].try_into().unwrap();
+                verify_signature(&public_key, header, |offset, buf: &mut [u8]| {
+                    let offset = offset as usize;
+                    buf.copy_from_slice(&image[offset..offset + buf.len()]);
+                    Ok::<_, ()>(())
+                })
+                .map_err(|err| format!("{err:?}"))?;
+                println!("signature valid");
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Returns the header followed by `binary`.
+fn sign(
+    key_pair: &KeyPair,
+    configurations: &[Configuration],
+    binary: &[u8],
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (version, git_hash, git_dirty) =
+        BuildInfo::find(binary).ok_or("no build info in the binary, is it the firmware?")?;
+    if linked_slot(binary).is_none() {
+        return Err("the binary is not linked for a slot, build it with FLASH_ORIGIN set".into());
+    }
+    let image_header = ImageHeader {
+        version,
+        git_hash,
+        git_dirty,
+        configurations: heapless::Vec::from_slice(configurations)
+            .map_err(|_| "too many configurations")?,
+        length: binary.len().try_into()?,
+        crc: image_crc(binary),
+    };
+
+    let mut header = [0u8; IMAGE_HEADER_SIZE];
+    let mut message = image_header
+        .encode(&mut header)
+        .ok_or("the header does not fit")?
+        .to_vec();
+    message.extend_from_slice(binary);
+    let signature = key_pair.sk.sign(&message, Some(Noise::generate()));
+    ImageHeader::set_signature(&mut header, &signature);
+
+    let mut image = header.to_vec();
+    image.extend_from_slice(binary);
+    Ok(image)
+}
+
+/// The slot the reset vector of `binary` points into.
+fn linked_slot(binary: &[u8]) -> Option<Slot> {
+    let reset_vector = u32::from_le_bytes(binary.get(4..8)?.try_into().unwrap());
+    Slot::containing(reset_vector)
+}
+
+fn parse_configuration(name: &str) -> Result<Configuration, String> {
+    name.parse()
+        .map_err(|_| format!("unknown configuration {name}"))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn read_hex<const N: usize>(path: &PathBuf) -> Result<[u8; N], Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    let text = text.trim();
+    if text.len() != 2 * N {
+        return Err(format!("{} does not hold {N} bytes in hexadecimal", path.display()).into());
+    }
+    let mut bytes = [0u8; N];
+    for (byte, index) in bytes.iter_mut().zip((0..text.len()).step_by(2)) {
+        *byte = u8::from_str_radix(&text[index..index + 2], 16)?;
+    }
+    Ok(bytes)
+}
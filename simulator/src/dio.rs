@@ -0,0 +1,494 @@
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::blocking::spi::Transfer;
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::spi::{Mode, MODE_0, MODE_2};
+use firmware_logic::drivers::dio::{
+    chip_select, Dio, SpiMode, DO_EN_PORT, DO_INTERRUPT_PORT, DO_STATUS_PORT, DO_STAT_PORT,
+    DO_WATCHED_PORT, MAX7301_CONFIGURATION, MAX7301_CONFIGURATION_TRANSITION_DETECT,
+    MAX7301_MULTI_PORT, MAX7301_PORT_CONFIG, MAX7301_PORT_INPUT_PULLUP, MAX7301_PORT_OUTPUT,
+    MAX7301_READ, MAX7301_SINGLE_PORT, MAX7301_TRANSITION_MASK, SN65HVS881_HEADER,
+    SN65HVS881_PARITY,
+};
+use firmware_logic::{IoBank, IoSupply, IoTemp, IoType};
+
+/// The driver wired to a `SimulatedIoBoard`.
+pub type SimulatedDio = Dio<
+    SimulatedSpi,
+    SimulatedPin,
+    SimulatedPin,
+    SimulatedPin,
+    SimulatedPin,
+    SimulatedPin,
+    SimulatedPin,
+    SimulatedDelay,
+>;
+
+/// Register model of the six IO banks on their SPI bus, cloning gives another handle to the same
+/// board.
+///
+/// The driver owns the bus and the pins, the test keeps a handle to drive the inputs, power the
+/// banks, inject faults and look at the registers. Every bank is fitted and powered at 24 V.
+#[derive(Clone)]
+pub struct SimulatedIoBoard {
+    board: Rc<RefCell<Board>>,
+}
+
+struct Board {
+    mode: Mode,
+    ncs: bool,
+    ld: bool,
+    select: u8,
+    di: [Sn65hvs881; 6],
+    r#do: [Max7301; 6],
+    transfers: u32,
+    elapsed_us: u64,
+}
+
+/// SN65HVS881 serializer of a DI bank.
+struct Sn65hvs881 {
+    fitted: bool,
+    /// Bit n is input n + 1.
+    inputs: u32,
+    hot: bool,
+    powered: bool,
+    corrupt_parity: bool,
+    /// Frame latched by the last LD pulse.
+    latched: u16,
+}
+
+/// MAX7301 port expander of a DO bank.
+struct Max7301 {
+    fitted: bool,
+    registers: [u8; 0x10],
+    /// Output latches of ports 4 to 31, bit n is port n.
+    latches: u32,
+    /// Levels applied to the pins by the board, bit n is port n. A floating pin reads high with
+    /// the pull-up enabled, low otherwise.
+    pins: u32,
+    driven: u32,
+    /// Output latches that no longer follow the writes, e.g. a damaged port.
+    stuck: u32,
+    /// Levels of ports 24 to 30 when transition detection was enabled, bit n is port 24 + n.
+    snapshot: u8,
+    /// Raised on P31 by a transition of a watched port.
+    interrupt: bool,
+    /// Shifted out on DOUT by the next transfer.
+    dout: [u8; 2],
+}
+
+/// Bus lines driven by the MCU.
+#[derive(Clone, Copy)]
+enum Line {
+    Ncs,
+    Sel(u8),
+    Ld,
+}
+
+pub struct SimulatedSpi {
+    board: Rc<RefCell<Board>>,
+}
+
+pub struct SimulatedPin {
+    board: Rc<RefCell<Board>>,
+    line: Line,
+}
+
+/// Busy waits take no time, they are added up instead.
+pub struct SimulatedDelay {
+    board: Rc<RefCell<Board>>,
+}
+
+const BANKS: [IoBank; 6] = [
+    IoBank::Bank1,
+    IoBank::Bank2,
+    IoBank::Bank3,
+    IoBank::Bank4,
+    IoBank::Bank5,
+    IoBank::Bank6,
+];
+
+/// Ports of the DO bank status inputs.
+const SUPPLY_24V_PORT: u8 = DO_STATUS_PORT;
+const SUPPLY_12V_PORT: u8 = DO_STATUS_PORT + 1;
+const NOT_HOT_PORT: u8 = DO_STATUS_PORT + 2;
+const NOT_WARM_PORT: u8 = DO_STATUS_PORT + 3;
+
+impl SimulatedIoBoard {
+    pub fn new() -> Self {
+        let board = Self {
+            board: Rc::new(RefCell::new(Board {
+                mode: MODE_0,
+                ncs: true,
+                ld: true,
+                select: 0,
+                di: std::array::from_fn(|_| Sn65hvs881 {
+                    fitted: true,
+                    inputs: 0,
+                    hot: false,
+                    powered: true,
+                    corrupt_parity: false,
+                    latched: 0,
+                }),
+                r#do: std::array::from_fn(|_| Max7301::new()),
+                transfers: 0,
+                elapsed_us: 0,
+            })),
+        };
+        for bank in BANKS {
+            board.set_do_supply(bank, IoSupply::Volts24);
+            board.set_do_temp(bank, IoTemp::Normal);
+        }
+        board
+    }
+
+    /// A driver on the bus and the pins of the board.
+    pub fn dio(&self) -> SimulatedDio {
+        let pin = |line| SimulatedPin {
+            board: self.board.clone(),
+            line,
+        };
+        Dio::new(
+            pin(Line::Sel(0)),
+            pin(Line::Sel(1)),
+            pin(Line::Sel(2)),
+            pin(Line::Sel(3)),
+            SimulatedSpi {
+                board: self.board.clone(),
+            },
+            pin(Line::Ncs),
+            pin(Line::Ld),
+            SimulatedDelay {
+                board: self.board.clone(),
+            },
+        )
+    }
+
+    /// Fits or removes both controllers of `bank`, nothing drives MISO for a missing one.
+    pub fn set_fitted(&self, bank: IoBank, fitted: bool) {
+        let mut board = self.board.borrow_mut();
+        board.di[bank as usize].fitted = fitted;
+        board.r#do[bank as usize].fitted = fitted;
+    }
+
+    /// Drives input 1 to 8 of `bank`.
+    pub fn set_input(&self, bank: IoBank, input: u8, high: bool) {
+        let di = &mut self.board.borrow_mut().di[bank as usize];
+        set_bit(&mut di.inputs, input - 1, high);
+    }
+
+    pub fn set_di_supply(&self, bank: IoBank, powered: bool) {
+        self.board.borrow_mut().di[bank as usize].powered = powered;
+    }
+
+    pub fn set_di_hot(&self, bank: IoBank, hot: bool) {
+        self.board.borrow_mut().di[bank as usize].hot = hot;
+    }
+
+    /// The serializer of `bank` sends frames with the wrong parity.
+    pub fn corrupt_di_parity(&self, bank: IoBank, corrupt: bool) {
+        self.board.borrow_mut().di[bank as usize].corrupt_parity = corrupt;
+    }
+
+    pub fn set_do_supply(&self, bank: IoBank, supply: IoSupply) {
+        let max7301 = &mut self.board.borrow_mut().r#do[bank as usize];
+        max7301.drive(SUPPLY_24V_PORT, Some(supply == IoSupply::Volts24));
+        max7301.drive(SUPPLY_12V_PORT, Some(supply == IoSupply::Volts12));
+    }
+
+    pub fn set_do_temp(&self, bank: IoBank, temp: IoTemp) {
+        let max7301 = &mut self.board.borrow_mut().r#do[bank as usize];
+        max7301.drive(NOT_HOT_PORT, Some(temp != IoTemp::Hot));
+        max7301.drive(NOT_WARM_PORT, Some(temp == IoTemp::Normal));
+    }
+
+    /// Drives the STAT pin of output 1 to 8 of `bank`, `None` leaves it to the pull-up.
+    pub fn set_stat(&self, bank: IoBank, output: u8, level: Option<bool>) {
+        self.board.borrow_mut().r#do[bank as usize].drive(DO_STAT_PORT + output - 1, level);
+    }
+
+    /// Sticks the EN latch of output 1 to 8 of `bank` at `level`, writes are then ignored.
+    /// `None` frees it.
+    pub fn set_stuck(&self, bank: IoBank, output: u8, level: Option<bool>) {
+        let port = DO_EN_PORT + output - 1;
+        let max7301 = &mut self.board.borrow_mut().r#do[bank as usize];
+        set_bit(&mut max7301.stuck, port, level.is_some());
+        if let Some(level) = level {
+            set_bit(&mut max7301.latches, port, level);
+        }
+    }
+
+    /// Drives the watched input 1 to 7 of `bank`, on ports 24 to 30, `None` leaves it to the
+    /// pull-up.
+    pub fn set_watched_input(&self, bank: IoBank, line: u8, level: Option<bool>) {
+        self.board.borrow_mut().r#do[bank as usize].drive(DO_WATCHED_PORT + line - 1, level);
+    }
+
+    /// Whether the MAX7301 of `bank` raises its interrupt output.
+    pub fn interrupt(&self, bank: IoBank) -> bool {
+        self.board.borrow().r#do[bank as usize].interrupt
+    }
+
+    /// Output latch of the EN pin of output 1 to 8 of `bank`.
+    pub fn output(&self, bank: IoBank, output: u8) -> bool {
+        let port = DO_EN_PORT + output - 1;
+        self.board.borrow().r#do[bank as usize].latches & (1 << port) != 0
+    }
+
+    /// Reads a register of the MAX7301 of `bank` without going through the bus.
+    pub fn max7301_register(&self, bank: IoBank, register: u8) -> u8 {
+        self.board.borrow().r#do[bank as usize].read(register)
+    }
+
+    /// SPI transfers made since the board was built.
+    pub fn transfers(&self) -> u32 {
+        self.board.borrow().transfers
+    }
+
+    /// Time spent busy waiting since the board was built.
+    pub fn elapsed_us(&self) -> u64 {
+        self.board.borrow().elapsed_us
+    }
+}
+
+impl Default for SimulatedIoBoard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Board {
+    /// The controller selected by the decoder, the unused outputs select nothing.
+    fn selected(&self) -> Option<(IoType, usize)> {
+        for bank in BANKS {
+            for direction in [IoType::DigitalIn, IoType::DigitalOut] {
+                if chip_select(direction, bank) == self.select {
+                    return Some((direction, bank as usize));
+                }
+            }
+        }
+        None
+    }
+
+    fn transfer(&mut self, words: &mut [u8]) {
+        self.transfers += 1;
+        let received: Vec<u8> = words.to_vec();
+        words.fill(0xFF); // MISO pulled up
+        if self.ncs {
+            return;
+        }
+        match self.selected() {
+            Some((IoType::DigitalIn, bank)) if self.di[bank].fitted && self.mode == MODE_2 => {
+                let frame = self.di[bank].latched;
+                words[0] = frame as u8;
+                words[1] = ((frame >> 8) as u8).reverse_bits();
+            }
+            Some((IoType::DigitalOut, bank)) if self.r#do[bank].fitted && self.mode == MODE_0 => {
+                let max7301 = &mut self.r#do[bank];
+                words[..2].copy_from_slice(&max7301.dout);
+                max7301.execute([received[0], received[1]]);
+            }
+            _ => {}
+        }
+    }
+
+    fn latch(&mut self) {
+        for di in self.di.iter_mut() {
+            di.latch();
+        }
+    }
+}
+
+impl Sn65hvs881 {
+    fn latch(&mut self) {
+        let mut frame = self.inputs as u16;
+        if !self.hot {
+            frame |= 1 << 8;
+        }
+        if self.powered {
+            frame |= 1 << 9;
+        }
+        // Odd parity over the data bits and the parity bit
+        if frame.count_ones().is_multiple_of(2) {
+            frame |= SN65HVS881_PARITY;
+        }
+        if self.corrupt_parity {
+            frame ^= SN65HVS881_PARITY;
+        }
+        self.latched = SN65HVS881_HEADER | frame;
+    }
+}
+
+impl Max7301 {
+    fn new() -> Self {
+        let mut registers = [0; 0x10];
+        // Every port an input without pull-up, shut down
+        registers[MAX7301_PORT_CONFIG as usize + 1..].fill(0xAA);
+        Self {
+            fitted: true,
+            registers,
+            latches: 0,
+            pins: 0,
+            driven: 0,
+            stuck: 0,
+            snapshot: 0,
+            interrupt: false,
+            dout: [0; 2],
+        }
+    }
+
+    fn drive(&mut self, port: u8, level: Option<bool>) {
+        set_bit(&mut self.driven, port, level.is_some());
+        set_bit(&mut self.pins, port, level.unwrap_or(false));
+        self.detect_transition();
+    }
+
+    fn watched_ports(&self) -> u8 {
+        (0..7)
+            .filter(|n| self.port(DO_WATCHED_PORT + n))
+            .fold(0, |ports, n| ports | 1 << n)
+    }
+
+    /// Compares the watched ports against the snapshot while detection is enabled, the first
+    /// transition raises the interrupt and disables detection.
+    fn detect_transition(&mut self) {
+        let configuration = &mut self.registers[MAX7301_CONFIGURATION as usize];
+        if *configuration & MAX7301_CONFIGURATION_TRANSITION_DETECT == 0 {
+            return;
+        }
+        let mask = self.registers[MAX7301_TRANSITION_MASK as usize] & 0x7F;
+        if (self.watched_ports() ^ self.snapshot) & mask != 0 {
+            self.registers[MAX7301_CONFIGURATION as usize] &=
+                !MAX7301_CONFIGURATION_TRANSITION_DETECT;
+            self.interrupt = true;
+        }
+    }
+
+    fn port_config(&self, port: u8) -> u8 {
+        let register = self.registers[(MAX7301_PORT_CONFIG + port / 4) as usize];
+        (register >> ((port % 4) * 2)) & 0x03
+    }
+
+    fn port(&self, port: u8) -> bool {
+        if !(4..=31).contains(&port) {
+            return false;
+        }
+        match self.port_config(port) {
+            MAX7301_PORT_OUTPUT if port == DO_INTERRUPT_PORT => {
+                self.interrupt || self.latches & (1 << port) != 0
+            }
+            MAX7301_PORT_OUTPUT => self.latches & (1 << port) != 0,
+            _ if self.driven & (1 << port) != 0 => self.pins & (1 << port) != 0,
+            config => config == MAX7301_PORT_INPUT_PULLUP,
+        }
+    }
+
+    fn read(&self, register: u8) -> u8 {
+        match register {
+            0x00..=0x0F => self.registers[register as usize],
+            0x20..=0x3F => self.port(register - MAX7301_SINGLE_PORT) as u8,
+            0x40..=0x5F => (0..8)
+                .filter(|n| self.port(register - MAX7301_MULTI_PORT + n))
+                .fold(0, |ports, n| ports | 1 << n),
+            _ => 0,
+        }
+    }
+
+    fn write_port(&mut self, port: u8, high: bool) {
+        if self.stuck & (1 << port) != 0 {
+            return;
+        }
+        if (4..=31).contains(&port) && self.port_config(port) == MAX7301_PORT_OUTPUT {
+            set_bit(&mut self.latches, port, high);
+        }
+    }
+
+    /// Executes the command clocked in, latched as CS rises.
+    fn execute(&mut self, command: [u8; 2]) {
+        let [address, data] = command;
+        if address & MAX7301_READ != 0 {
+            self.dout = [address, self.read(address & !MAX7301_READ)];
+            return;
+        }
+        self.dout = command;
+        match address {
+            MAX7301_CONFIGURATION => {
+                self.registers[address as usize] = data;
+                // Setting M takes a snapshot of the watched ports and clears the interrupt
+                if data & MAX7301_CONFIGURATION_TRANSITION_DETECT != 0 {
+                    self.snapshot = self.watched_ports();
+                    self.interrupt = false;
+                }
+            }
+            0x06 | 0x09..=0x0F => self.registers[address as usize] = data,
+            0x24..=0x3F => self.write_port(address - MAX7301_SINGLE_PORT, data & 0x01 != 0),
+            0x44..=0x5F => {
+                for n in 0..8 {
+                    self.write_port(address - MAX7301_MULTI_PORT + n, data & (1 << n) != 0);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn set_bit(bits: &mut u32, n: u8, set: bool) {
+    if set {
+        *bits |= 1 << n;
+    } else {
+        *bits &= !(1 << n);
+    }
+}
+
+impl Transfer<u8> for SimulatedSpi {
+    type Error = Infallible;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
+        self.board.borrow_mut().transfer(words);
+        Ok(words)
+    }
+}
+
+impl SpiMode for SimulatedSpi {
+    fn set_spi_mode(&mut self, mode: Mode) {
+        self.board.borrow_mut().mode = mode;
+    }
+}
+
+impl OutputPin for SimulatedPin {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        let mut board = self.board.borrow_mut();
+        match self.line {
+            Line::Ncs => board.ncs = false,
+            Line::Sel(n) => board.select &= !(1 << n),
+            Line::Ld => board.ld = false,
+        }
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        let mut board = self.board.borrow_mut();
+        match self.line {
+            Line::Ncs => board.ncs = true,
+            Line::Sel(n) => board.select |= 1 << n,
+            // The serializers load their inputs as LD rises
+            Line::Ld if !board.ld => {
+                board.ld = true;
+                board.latch();
+            }
+            Line::Ld => {}
+        }
+        Ok(())
+    }
+}
+
+impl DelayUs<u32> for SimulatedDelay {
+    fn delay_us(&mut self, us: u32) {
+        self.board.borrow_mut().elapsed_us += us as u64;
+    }
+}
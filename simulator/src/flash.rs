@@ -0,0 +1,138 @@
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+
+/// In-memory NOR flash with the write and erase sizes of the card's internal flash scaled down.
+///
+/// Like NOR flash, writes can only clear bits. Power can be cut after a number of erase or write
+/// operations to check what a store leaves behind.
+pub struct MemoryFlash {
+    bytes: Vec<u8>,
+    operations_left: Option<usize>,
+    power_lost: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MemoryFlashError {
+    NotAligned,
+    OutOfBounds,
+    /// The operation was interrupted half way, every later one fails too.
+    PowerLost,
+}
+
+impl NorFlashError for MemoryFlashError {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            MemoryFlashError::NotAligned => NorFlashErrorKind::NotAligned,
+            MemoryFlashError::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            MemoryFlashError::PowerLost => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+impl MemoryFlash {
+    /// Blank flash of `sectors` erase sectors.
+    pub fn new(sectors: usize) -> Self {
+        Self {
+            bytes: vec![0xFF; sectors * Self::ERASE_SIZE],
+            operations_left: None,
+            power_lost: false,
+        }
+    }
+
+    /// Lets `operations` erase or write operations complete, the next one is interrupted.
+    pub fn cut_power_after(&mut self, operations: usize) {
+        self.operations_left = Some(operations);
+    }
+
+    pub fn restore_power(&mut self) {
+        self.operations_left = None;
+        self.power_lost = false;
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+
+    fn check(&self, from: u32, length: usize, alignment: usize) -> Result<(), MemoryFlashError> {
+        if !(from as usize).is_multiple_of(alignment) || !length.is_multiple_of(alignment) {
+            return Err(MemoryFlashError::NotAligned);
+        }
+        if from as usize + length > self.bytes.len() {
+            return Err(MemoryFlashError::OutOfBounds);
+        }
+        Ok(())
+    }
+
+    /// Returns whether the operation completes, if not only its first half takes effect.
+    fn use_power(&mut self) -> Result<bool, MemoryFlashError> {
+        if self.power_lost {
+            return Err(MemoryFlashError::PowerLost);
+        }
+        match &mut self.operations_left {
+            None => Ok(true),
+            Some(0) => {
+                self.power_lost = true;
+                Ok(false)
+            }
+            Some(left) => {
+                *left -= 1;
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl ErrorType for MemoryFlash {
+    type Error = MemoryFlashError;
+}
+
+impl ReadNorFlash for MemoryFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.check(offset, bytes.len(), Self::READ_SIZE)?;
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.bytes[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl NorFlash for MemoryFlash {
+    const WRITE_SIZE: usize = 32;
+    const ERASE_SIZE: usize = 4_096;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        self.check(from, to.saturating_sub(from) as usize, Self::ERASE_SIZE)?;
+        let (from, to) = (from as usize, to as usize);
+        if self.use_power()? {
+            self.bytes[from..to].fill(0xFF);
+            Ok(())
+        } else {
+            self.bytes[from..(from + to) / 2].fill(0xFF);
+            Err(MemoryFlashError::PowerLost)
+        }
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.check(offset, bytes.len(), Self::WRITE_SIZE)?;
+        let offset = offset as usize;
+        let completes = self.use_power()?;
+        let written = if completes { bytes } else { &bytes[..bytes.len() / 2] };
+        for (cell, byte) in self.bytes[offset..].iter_mut().zip(written) {
+            *cell &= byte;
+        }
+        match completes {
+            true => Ok(()),
+            false => Err(MemoryFlashError::PowerLost),
+        }
+    }
+}
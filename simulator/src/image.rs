@@ -0,0 +1,50 @@
+use ed25519_compact::{KeyPair, Seed};
+use firmware_logic::{
+    data::{
+        image::{ImageHeader, IMAGE_HEADER_SIZE, PUBLIC_KEY_SIZE},
+        update::image_crc,
+    },
+    Configuration,
+};
+
+/// Signs images as `image_tool sign` does, with a key only the tests use.
+pub struct ImageSigner {
+    key_pair: KeyPair,
+}
+
+impl ImageSigner {
+    pub fn new(seed: u8) -> Self {
+        Self {
+            key_pair: KeyPair::from_seed(Seed::new([seed; Seed::BYTES])),
+        }
+    }
+
+    pub fn public_key(&self) -> [u8; PUBLIC_KEY_SIZE] {
+        *self.key_pair.pk
+    }
+
+    /// The header `image_tool` would write for `binary`, built for `configurations`.
+    pub fn header(configurations: &[Configuration], binary: &[u8]) -> ImageHeader {
+        ImageHeader {
+            version: "0.2.0".try_into().unwrap(),
+            git_hash: Some([0xA5; 20]),
+            git_dirty: false,
+            configurations: heapless::Vec::from_slice(configurations).unwrap(),
+            length: binary.len() as u32,
+            crc: image_crc(binary),
+        }
+    }
+
+    /// Returns `header` signed, followed by `binary`.
+    pub fn sign(&self, header: &ImageHeader, binary: &[u8]) -> Vec<u8> {
+        let mut encoded = [0u8; IMAGE_HEADER_SIZE];
+        let mut message = header.encode(&mut encoded).unwrap().to_vec();
+        message.extend_from_slice(binary);
+        let signature = self.key_pair.sk.sign(&message, None);
+        ImageHeader::set_signature(&mut encoded, &signature);
+
+        let mut image = encoded.to_vec();
+        image.extend_from_slice(binary);
+        image
+    }
+}
@@ -0,0 +1,218 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use firmware_logic::data::link::{LinkMode, LinkSpeed};
+use firmware_logic::drivers::lan8720a::*;
+
+/// Register model of a LAN8720A on the MDIO bus, cloning gives another handle to the same chip.
+///
+/// The driver owns one handle, the test keeps another to plug the cable, inject errors and look
+/// at the registers. Autonegotiation resolves at once to the best mode advertised by both ends.
+#[derive(Clone)]
+pub struct SimulatedPhy {
+    chip: Rc<RefCell<Chip>>,
+}
+
+struct Chip {
+    registers: [u16; 32],
+    /// Best mode of the link partner, it advertises the slower ones too. `None` when unplugged.
+    partner: Option<LinkMode>,
+    /// The link went down since the status register was last read.
+    link_dropped: bool,
+    /// Frames looped back are altered, e.g. a broken RMII line.
+    corrupt_loopback: bool,
+}
+
+const MODES: [(LinkMode, u16, u16); 4] = [
+    (
+        LinkMode {
+            speed: LinkSpeed::Mbps100,
+            full_duplex: true,
+        },
+        PHY_REG_ANTX_100BASE_TX_FD,
+        PHY_REG_SSR_SPEED_100BASE_TX_FD,
+    ),
+    (
+        LinkMode {
+            speed: LinkSpeed::Mbps100,
+            full_duplex: false,
+        },
+        PHY_REG_ANTX_100BASE_TX_HD,
+        PHY_REG_SSR_SPEED_100BASE_TX_HD,
+    ),
+    (
+        LinkMode {
+            speed: LinkSpeed::Mbps10,
+            full_duplex: true,
+        },
+        PHY_REG_ANTX_10BASE_T_FD,
+        PHY_REG_SSR_SPEED_10BASE_T_FD,
+    ),
+    (
+        LinkMode {
+            speed: LinkSpeed::Mbps10,
+            full_duplex: false,
+        },
+        PHY_REG_ANTX_10BASE_T_HD,
+        PHY_REG_SSR_SPEED_10BASE_T_HD,
+    ),
+];
+
+impl SimulatedPhy {
+    /// A LAN8720A of revision 1, unplugged.
+    pub fn new() -> Self {
+        Self::with_id(PhyId {
+            id1: LAN8720A_ID1,
+            id2: LAN8720A_ID2_MODEL | 1,
+        })
+    }
+
+    pub fn with_id(id: PhyId) -> Self {
+        let mut chip = Chip {
+            registers: [0; 32],
+            partner: None,
+            link_dropped: false,
+            corrupt_loopback: false,
+        };
+        chip.reset();
+        chip.registers[PHY_REG_ID1 as usize] = id.id1;
+        chip.registers[PHY_REG_ID2 as usize] = id.id2;
+        Self {
+            chip: Rc::new(RefCell::new(chip)),
+        }
+    }
+
+    /// Plugs the cable into a partner whose best mode is `partner`, or unplugs it with `None`.
+    pub fn connect(&self, partner: Option<LinkMode>) {
+        let mut chip = self.chip.borrow_mut();
+        chip.partner = partner;
+        match partner {
+            Some(_) => chip.negotiate(),
+            None => chip.drop_link(),
+        }
+    }
+
+    pub fn add_symbol_errors(&self, count: u16) {
+        let mut chip = self.chip.borrow_mut();
+        let counter = &mut chip.registers[PHY_REG_SECR as usize];
+        *counter = counter.wrapping_add(count);
+    }
+
+    pub fn raise(&self, interrupts: PhyInterrupts) {
+        self.chip.borrow_mut().registers[PHY_REG_ISR as usize] |= interrupts.0;
+    }
+
+    pub fn corrupt_loopback(&self, corrupt: bool) {
+        self.chip.borrow_mut().corrupt_loopback = corrupt;
+    }
+
+    /// Reads a register without the side effects of an MDIO read.
+    pub fn register(&self, reg: u8) -> u16 {
+        self.chip.borrow().registers[reg as usize]
+    }
+
+    /// Sends `frame` as the MAC would, returning the length of the frame received back. Only
+    /// frames looped back by the PHY come back.
+    pub fn exchange(&self, frame: &[u8], received: &mut [u8]) -> Option<usize> {
+        let chip = self.chip.borrow();
+        if chip.registers[PHY_REG_BCR as usize] & PHY_REG_BCR_LOOPBACK == 0 {
+            return None;
+        }
+        received[..frame.len()].copy_from_slice(frame);
+        if chip.corrupt_loopback {
+            received[frame.len() / 2] ^= 0x10;
+        }
+        Some(frame.len())
+    }
+}
+
+impl Default for SimulatedPhy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chip {
+    fn reset(&mut self) {
+        let id = [self.registers[2], self.registers[3]];
+        self.registers = [0; 32];
+        self.registers[2..4].copy_from_slice(&id);
+        self.registers[PHY_REG_BCR as usize] = PHY_REG_BCR_AN | PHY_REG_BCR_100M | PHY_REG_BCR_FD;
+        self.registers[PHY_REG_BSR as usize] = PHY_REG_BSR_100BASE_TX_FD
+            | PHY_REG_BSR_100BASE_TX_HD
+            | PHY_REG_BSR_10BASE_T_FD
+            | PHY_REG_BSR_10BASE_T_HD
+            | PHY_REG_BSR_ANABLE;
+        self.registers[PHY_REG_ANTX as usize] = PHY_REG_ANTX_SELECTOR_802_3
+            | PHY_REG_ANTX_100BASE_TX_FD
+            | PHY_REG_ANTX_100BASE_TX_HD
+            | PHY_REG_ANTX_10BASE_T_FD
+            | PHY_REG_ANTX_10BASE_T_HD;
+        self.link_dropped = false;
+        self.negotiate();
+    }
+
+    fn negotiate(&mut self) {
+        let bcr = self.registers[PHY_REG_BCR as usize];
+        if bcr & PHY_REG_BCR_AN == 0 || bcr & PHY_REG_BCR_LOOPBACK != 0 {
+            return self.drop_link();
+        }
+        let Some(partner) = self.partner else {
+            return self.drop_link();
+        };
+        let advertised = self.registers[PHY_REG_ANTX as usize];
+        let resolved = MODES
+            .iter()
+            .skip_while(|(mode, _, _)| *mode != partner)
+            .find(|(_, ability, _)| advertised & ability != 0);
+        let Some((_, _, speed)) = resolved else {
+            return self.drop_link();
+        };
+        self.registers[PHY_REG_BSR as usize] |= PHY_REG_BSR_UP | PHY_REG_BSR_ANDONE;
+        self.registers[PHY_REG_SSR as usize] = PHY_REG_SSR_ANDONE | speed;
+        self.registers[PHY_REG_ISR as usize] |= PhyInterrupts::AUTONEGOTIATION_COMPLETE.0;
+    }
+
+    fn drop_link(&mut self) {
+        let bsr = &mut self.registers[PHY_REG_BSR as usize];
+        if *bsr & PHY_REG_BSR_UP != 0 {
+            self.link_dropped = true;
+            self.registers[PHY_REG_ISR as usize] |= PhyInterrupts::LINK_DOWN.0;
+        }
+        self.registers[PHY_REG_BSR as usize] &= !(PHY_REG_BSR_UP | PHY_REG_BSR_ANDONE);
+        self.registers[PHY_REG_SSR as usize] &= !(PHY_REG_SSR_ANDONE | PHY_REG_SSR_SPEED);
+    }
+}
+
+impl StationManagement for SimulatedPhy {
+    fn smi_read(&mut self, reg: u8) -> u16 {
+        let mut chip = self.chip.borrow_mut();
+        let value = chip.registers[reg as usize];
+        match reg {
+            // Latches low: reads down once after a drop
+            PHY_REG_BSR if chip.link_dropped => {
+                chip.link_dropped = false;
+                value & !PHY_REG_BSR_UP
+            }
+            PHY_REG_ISR => {
+                chip.registers[reg as usize] = 0;
+                value
+            }
+            _ => value,
+        }
+    }
+
+    fn smi_write(&mut self, reg: u8, val: u16) {
+        let mut chip = self.chip.borrow_mut();
+        match reg {
+            PHY_REG_BCR if val & PHY_REG_BCR_RESET != 0 => chip.reset(),
+            PHY_REG_BCR => {
+                // Autonegotiation restarts and completes at once, the bit clears itself
+                chip.registers[reg as usize] = val & !PHY_REG_BCR_ANRST;
+                chip.negotiate();
+            }
+            PHY_REG_BSR | PHY_REG_ID1 | PHY_REG_ID2 | PHY_REG_SECR | PHY_REG_ISR | PHY_REG_SSR => {}
+            _ => chip.registers[reg as usize] = val,
+        }
+    }
+}
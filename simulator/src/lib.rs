@@ -0,0 +1,176 @@
+//! Runs `FirmwareLogic` against a simulated vessel on the host.
+//!
+//! Time only advances through `Wingman2HardwareStatus::now`, scenarios are therefore
+//! deterministic and run as fast as the host allows. The workspace builds for the card by
+//! default, so pass the host target:
+//! `cargo test -p simulator --target x86_64-unknown-linux-gnu`.
+
+pub mod dio;
+pub mod flash;
+pub mod image;
+pub mod lan8720a;
+mod plant;
+pub mod replay;
+
+use firmware_logic::{
+    data::{
+        io_population::IoPopulation,
+        recording::{RecordedCycle, Recorder},
+        user_commands::UserCommands,
+    },
+    Command, Configuration, ControllerLogic, DigitalOutput, FirmwareLogic, IoLevel, NackReason,
+    Timestamp, UserCommand, Wingman2HardwareStatus,
+};
+
+pub use plant::{Engine, EngineState, Plant, Pump, Tank, Windlass};
+
+/// The card running `FirmwareLogic` in `Configuration::UnitTest`, wired to a `Plant`.
+pub struct Simulator {
+    logic: FirmwareLogic,
+    user_commands: UserCommands,
+    hardware_status: Wingman2HardwareStatus,
+    plant: Plant,
+    recorder: Recorder,
+    recording: Vec<RecordedCycle>,
+}
+
+impl Simulator {
+    /// Period of the `apply_logic` task on the card.
+    pub const STEP: Timestamp = Timestamp::new(5);
+    /// Period at which the control server repeats a held button.
+    pub const BUTTON_REPEAT: Timestamp = Timestamp::new(100);
+
+    pub fn new(plant: Plant) -> Self {
+        Self::with_io_population(plant, IoPopulation::default())
+    }
+
+    /// Boots with the IO banks found by the self-test, see `Dio::probe`.
+    pub fn with_io_population(plant: Plant, io_population: IoPopulation) -> Self {
+        let mut hardware_status = Wingman2HardwareStatus::new(Configuration::UnitTest);
+        hardware_status.io_population = io_population;
+        let mut logic = FirmwareLogic::default();
+        plant.write_inputs(&mut hardware_status);
+        hardware_status.step += 1;
+        logic.initialize(&mut hardware_status);
+
+        Self {
+            logic,
+            user_commands: UserCommands::default(),
+            hardware_status,
+            plant,
+            recorder: Recorder::default(),
+            recording: Vec::new(),
+        }
+    }
+
+    pub fn now(&self) -> Timestamp {
+        self.hardware_status.now
+    }
+
+    pub fn hardware_status(&self) -> &Wingman2HardwareStatus {
+        &self.hardware_status
+    }
+
+    pub fn output_level(&self, output: DigitalOutput) -> Option<IoLevel> {
+        self.hardware_status.get_digital_output(&output)
+    }
+
+    pub fn logic(&self) -> &FirmwareLogic {
+        &self.logic
+    }
+
+    pub fn plant(&self) -> &Plant {
+        &self.plant
+    }
+
+    /// Gives access to the plant, e.g. to seize a pump or flood the bilge.
+    pub fn plant_mut(&mut self) -> &mut Plant {
+        &mut self.plant
+    }
+
+    /// Sends a command as the control server would, returning the ack or nack reason.
+    pub fn send(&mut self, command: Command) -> Result<(), NackReason> {
+        let user_command = UserCommand::new(command, Some(self.now()));
+        self.logic.receive_command(
+            user_command,
+            &mut self.user_commands,
+            &mut self.recorder,
+            &self.hardware_status,
+        )
+    }
+
+    /// Holds a button for `duration`, repeating it every `BUTTON_REPEAT`.
+    /// Returns the response to the first command sent.
+    pub fn hold(&mut self, command: Command, duration: Timestamp) -> Result<(), NackReason> {
+        let end = self.now() + duration;
+        let result = self.send(command);
+        while self.now() < end {
+            let repeat = (self.now() + Self::BUTTON_REPEAT).min(end);
+            self.run_until_time(repeat);
+            if self.now() < end {
+                let _ = self.send(command);
+            }
+        }
+        result
+    }
+
+    pub fn run_for(&mut self, duration: Timestamp) {
+        self.run_until_time(self.now() + duration);
+    }
+
+    /// Steps until `condition` holds, giving up after `timeout`. Returns whether it held.
+    pub fn run_until(
+        &mut self,
+        timeout: Timestamp,
+        mut condition: impl FnMut(&Self) -> bool,
+    ) -> bool {
+        let end = self.now() + timeout;
+        while !condition(self) {
+            if self.now() >= end {
+                return false;
+            }
+            self.step();
+        }
+        true
+    }
+
+    /// One `apply_logic` cycle: the plant reacts to the outputs, then the logic to the inputs.
+    pub fn step(&mut self) {
+        self.plant.advance(&self.hardware_status, Self::STEP);
+
+        self.hardware_status.step += 1;
+        self.hardware_status.now = self.hardware_status.now + Self::STEP;
+        self.plant.write_inputs(&mut self.hardware_status);
+
+        self.logic.run_cycle(
+            &mut self.user_commands,
+            &mut self.recorder,
+            &mut self.hardware_status,
+        );
+        self.recording
+            .extend(core::iter::from_fn(|| self.recorder.pop()));
+    }
+
+    /// Records every following cycle, as the card does when asked by the control server.
+    pub fn start_recording(&mut self) {
+        self.recorder.set_enabled(true);
+    }
+
+    /// Stops recording and returns the cycles recorded so far.
+    pub fn stop_recording(&mut self) -> Vec<RecordedCycle> {
+        self.recorder.set_enabled(false);
+        core::mem::take(&mut self.recording)
+    }
+
+    fn run_until_time(&mut self, end: Timestamp) {
+        while self.now() < end {
+            self.step();
+        }
+    }
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new(Plant::default())
+    }
+}
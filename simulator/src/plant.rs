@@ -0,0 +1,274 @@
+use firmware_logic::{
+    BoardMap, DigitalInput, DigitalOutput, IoLevel, Timestamp, Wingman2HardwareStatus,
+};
+use uom::si::electric_potential::volt;
+use uom::si::f32::ElectricPotential;
+
+/// The devices wired to the card, laid out as in `BoardMap::UNIT_TEST`.
+#[derive(Clone, Debug)]
+pub struct Plant {
+    pub windlass: Windlass,
+    pub bilge: Tank,
+    pub bilge_pumps: [Pump; 3],
+    pub black_water: Tank,
+    pub black_water_pump: Pump,
+    pub fresh_water: Tank,
+    pub engines: [Engine; 2],
+}
+
+impl Default for Plant {
+    fn default() -> Self {
+        Self {
+            windlass: Windlass::default(),
+            bilge: Tank::new(200.0, 0.0),
+            bilge_pumps: [Pump::new(1.0), Pump::new(1.0), Pump::new(1.0)],
+            black_water: Tank::new(80.0, 40.0),
+            black_water_pump: Pump::new(0.5),
+            fresh_water: Tank::new(300.0, 300.0),
+            engines: [Engine::default(), Engine::default()],
+        }
+    }
+}
+
+impl Plant {
+    const BOARD_MAP: &'static BoardMap = &BoardMap::UNIT_TEST;
+
+    /// Advances the plant by `elapsed`, driven by the outputs currently set by the logic.
+    pub fn advance(&mut self, hardware_status: &Wingman2HardwareStatus, elapsed: Timestamp) {
+        let board_map = Self::BOARD_MAP;
+        let seconds = elapsed.as_millis() as f32 / 1_000.0;
+
+        self.windlass.advance(
+            is_high(hardware_status, board_map.anchor_up),
+            is_high(hardware_status, board_map.anchor_down),
+            elapsed,
+        );
+
+        let mut bilge_flow = 0.0;
+        for (pump, output) in self.bilge_pumps.iter_mut().zip(board_map.bilge_pumps) {
+            pump.switch(is_high(hardware_status, output));
+            bilge_flow += pump.flow();
+        }
+        self.bilge.advance(-bilge_flow, seconds);
+
+        self.black_water_pump
+            .switch(is_high(hardware_status, board_map.black_water_pump));
+        self.black_water
+            .advance(-self.black_water_pump.flow(), seconds);
+
+        self.fresh_water.advance(0.0, seconds);
+
+        for (index, engine) in self.engines.iter_mut().enumerate() {
+            engine.advance(
+                is_high(hardware_status, board_map.engine_ignitions[index]),
+                is_high(hardware_status, board_map.engine_starters[index]),
+                elapsed,
+            );
+        }
+    }
+
+    /// Writes what the card would read back from the plant.
+    pub fn write_inputs(&self, hardware_status: &mut Wingman2HardwareStatus) {
+        let board_map = Self::BOARD_MAP;
+
+        write_input(
+            hardware_status,
+            board_map.engine_battery_port,
+            self.engines[0].battery_on,
+        );
+        write_input(
+            hardware_status,
+            board_map.engine_battery_stbd,
+            self.engines[1].battery_on,
+        );
+        for (pump, input) in self.bilge_pumps.iter().zip(board_map.bilge_pumps_running) {
+            write_input(hardware_status, input, pump.is_running());
+        }
+        for (engine, input) in self.engines.iter().zip(board_map.engines_running) {
+            write_input(hardware_status, input, engine.state == EngineState::Running);
+        }
+
+        let tanks = [
+            (board_map.fresh_water_level, &self.fresh_water),
+            (board_map.black_water_level, &self.black_water),
+        ];
+        for (input, tank) in tanks {
+            if let Some(input) = input {
+                hardware_status.analog_inputs[input.address].voltage =
+                    ElectricPotential::new::<volt>(tank.sender_volts());
+            }
+        }
+    }
+}
+
+fn is_high(hardware_status: &Wingman2HardwareStatus, output: Option<DigitalOutput>) -> bool {
+    output.is_some_and(|output| hardware_status.get_digital_output(&output) == Some(IoLevel::High))
+}
+
+fn write_input(
+    hardware_status: &mut Wingman2HardwareStatus,
+    input: Option<DigitalInput>,
+    high: bool,
+) {
+    if let Some(input) = input {
+        hardware_status.digital_inputs[input.address].level =
+            Some(if high { IoLevel::High } else { IoLevel::Low });
+    }
+}
+
+/// A pump with a running feedback contact.
+#[derive(Clone, Debug)]
+pub struct Pump {
+    /// Litres per second while running.
+    pub flow: f32,
+    /// A seized pump neither pumps nor closes its feedback contact.
+    pub seized: bool,
+    powered: bool,
+}
+
+impl Pump {
+    pub fn new(flow: f32) -> Self {
+        Self {
+            flow,
+            seized: false,
+            powered: false,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.powered && !self.seized
+    }
+
+    fn switch(&mut self, powered: bool) {
+        self.powered = powered;
+    }
+
+    fn flow(&self) -> f32 {
+        if self.is_running() {
+            self.flow
+        } else {
+            0.0
+        }
+    }
+}
+
+/// A tank with a level sender reading 0 V when empty and `SENDER_FULL_VOLTS` when full.
+#[derive(Clone, Debug)]
+pub struct Tank {
+    /// Litres.
+    pub capacity: f32,
+    /// Litres.
+    pub level: f32,
+    /// Litres per second flowing in, negative when the tank is being used.
+    pub inflow: f32,
+}
+
+impl Tank {
+    pub const SENDER_FULL_VOLTS: f32 = 10.0;
+
+    pub fn new(capacity: f32, level: f32) -> Self {
+        Self {
+            capacity,
+            level,
+            inflow: 0.0,
+        }
+    }
+
+    pub fn sender_volts(&self) -> f32 {
+        self.level / self.capacity * Self::SENDER_FULL_VOLTS
+    }
+
+    fn advance(&mut self, pumped: f32, seconds: f32) {
+        self.level = (self.level + (self.inflow + pumped) * seconds).clamp(0.0, self.capacity);
+    }
+}
+
+/// An anchor windlass paying out or hauling in chain at a fixed speed.
+#[derive(Clone, Debug)]
+pub struct Windlass {
+    /// Metres of chain.
+    pub chain_length: f32,
+    /// Metres of chain paid out, 0 when the anchor is home.
+    pub chain_out: f32,
+    /// Metres per second.
+    pub speed: f32,
+    /// Total time both directions were driven at once, the logic must keep it at zero.
+    pub both_driven: Timestamp,
+}
+
+impl Default for Windlass {
+    fn default() -> Self {
+        Self {
+            chain_length: 60.0,
+            chain_out: 0.0,
+            speed: 0.3,
+            both_driven: Timestamp::default(),
+        }
+    }
+}
+
+impl Windlass {
+    fn advance(&mut self, up: bool, down: bool, elapsed: Timestamp) {
+        let travel = self.speed * elapsed.as_millis() as f32 / 1_000.0;
+        match (up, down) {
+            (true, true) => self.both_driven = self.both_driven + elapsed,
+            (true, false) => self.chain_out = (self.chain_out - travel).max(0.0),
+            (false, true) => self.chain_out = (self.chain_out + travel).min(self.chain_length),
+            (false, false) => {}
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EngineState {
+    Stopped,
+    Cranking,
+    Running,
+}
+
+/// An engine that starts after being cranked for `crank_to_start` with the ignition on.
+#[derive(Clone, Debug)]
+pub struct Engine {
+    pub crank_to_start: Timestamp,
+    /// An engine that fails to start keeps cranking until the starter is released.
+    pub fails_to_start: bool,
+    pub battery_on: bool,
+    pub state: EngineState,
+    /// Longest the starter was engaged in one go.
+    pub longest_crank: Timestamp,
+    cranked: Timestamp,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self {
+            crank_to_start: Timestamp::new(1_500),
+            fails_to_start: false,
+            battery_on: true,
+            state: EngineState::Stopped,
+            longest_crank: Timestamp::default(),
+            cranked: Timestamp::default(),
+        }
+    }
+}
+
+impl Engine {
+    fn advance(&mut self, ignition: bool, starter: bool, elapsed: Timestamp) {
+        if starter {
+            self.cranked = self.cranked + elapsed;
+            self.longest_crank = self.longest_crank.max(self.cranked);
+        } else {
+            self.cranked = Timestamp::default();
+        }
+
+        self.state = match (ignition && self.battery_on, starter, self.state) {
+            (false, _, _) => EngineState::Stopped,
+            (true, _, EngineState::Running) => EngineState::Running,
+            (true, true, _) if !self.fails_to_start && self.cranked >= self.crank_to_start => {
+                EngineState::Running
+            }
+            (true, true, _) => EngineState::Cranking,
+            (true, false, _) => EngineState::Stopped,
+        };
+    }
+}
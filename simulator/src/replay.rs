@@ -0,0 +1,85 @@
+use std::io::{self, Read, Write};
+
+use firmware_logic::{
+    data::{recording::RecordedCycle, user_commands::UserCommands},
+    ControllerLogic, FirmwareLogic, IoLevel, Timestamp, Wingman2HardwareStatus,
+};
+
+/// An output the replayed logic drove differently than the recorded one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mismatch {
+    pub step: u64,
+    pub now: Timestamp,
+    pub address: usize,
+    pub recorded: Option<IoLevel>,
+    pub replayed: Option<IoLevel>,
+}
+
+/// Feeds a recording through a fresh `FirmwareLogic` and diffs its outputs against the recorded ones.
+///
+/// The first cycle only seeds the output levels, the state kept inside the controllers is not
+/// recorded. A recording started while e.g. an engine was cranking may therefore diverge at first.
+pub fn replay(cycles: impl IntoIterator<Item = RecordedCycle>) -> Vec<Mismatch> {
+    let mut cycles = cycles.into_iter();
+    let Some(first) = cycles.next() else {
+        return Vec::new();
+    };
+
+    let mut logic = FirmwareLogic::default();
+    let mut user_commands = UserCommands::default();
+    let mut hardware_status = Wingman2HardwareStatus::new(first.configuration);
+    first.restore_inputs(&mut hardware_status);
+    for (address, output) in hardware_status.digital_outputs.iter_mut().enumerate() {
+        output.level = first.output_level(address);
+    }
+
+    let mut mismatches = Vec::new();
+    for cycle in cycles {
+        cycle.restore_inputs(&mut hardware_status);
+        for user_command in &cycle.commands {
+            let _ = user_commands.push(*user_command);
+        }
+        logic.apply_user_commands(&mut user_commands, &mut hardware_status);
+        logic.update(&mut hardware_status);
+
+        for (address, output) in hardware_status.digital_outputs.iter().enumerate() {
+            let recorded = cycle.output_level(address);
+            if output.level != recorded {
+                mismatches.push(Mismatch {
+                    step: cycle.step,
+                    now: cycle.now,
+                    address,
+                    recorded,
+                    replayed: output.level,
+                });
+            }
+        }
+    }
+    mismatches
+}
+
+/// Reads a recording, one COBS framed postcard `RecordedCycle` after the other.
+pub fn read_recording(mut reader: impl Read) -> io::Result<Vec<RecordedCycle>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    bytes
+        .split_inclusive_mut(|byte| *byte == 0)
+        .map(|frame| {
+            postcard::from_bytes_cobs(frame)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+        })
+        .collect()
+}
+
+pub fn write_recording<'a>(
+    mut writer: impl Write,
+    cycles: impl IntoIterator<Item = &'a RecordedCycle>,
+) -> io::Result<()> {
+    for cycle in cycles {
+        let frame = postcard::to_stdvec_cobs(cycle)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        writer.write_all(&frame)?;
+    }
+    Ok(())
+}
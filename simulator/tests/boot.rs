@@ -0,0 +1,219 @@
+use firmware_logic::data::boot::{
+    count_boot, image_is_valid, select_slot, BootAttempts, BootConfirmation, BootRecord, Slot,
+    CONFIRM_AFTER, MAX_BOOT_ATTEMPTS, SLOT_SIZE,
+};
+use firmware_logic::data::image::{ImageError, ImageHeader, IMAGE_HEADER_SIZE};
+use firmware_logic::{Configuration, Timestamp};
+use simulator::image::ImageSigner;
+
+/// A binary linked for `slot`: its vector table, then some code.
+fn binary(slot: Slot) -> Vec<u8> {
+    let mut binary = Vec::new();
+    binary.extend_from_slice(&0x2002_0000u32.to_le_bytes());
+    binary.extend_from_slice(&(slot.vector_table() + 0x401).to_le_bytes());
+    binary.extend((0..2_000u32).map(|i| (i * 13) as u8));
+    binary
+}
+
+/// `binary` with its signed header, as written by the updater.
+fn image(binary: &[u8]) -> Vec<u8> {
+    let header = ImageSigner::header(&[Configuration::UnitTest], binary);
+    ImageSigner::new(1).sign(&header, binary)
+}
+
+/// Slot contents as the bootloader sees them, erased past the image.
+fn slot(image: &[u8]) -> Vec<u8> {
+    let mut contents = vec![0xFF; SLOT_SIZE as usize];
+    contents[..image.len()].copy_from_slice(image);
+    contents
+}
+
+struct Flash {
+    first: Vec<u8>,
+    second: Vec<u8>,
+}
+
+impl Flash {
+    fn new(first: &[u8], second: &[u8]) -> Self {
+        Self {
+            first: slot(first),
+            second: slot(second),
+        }
+    }
+
+    fn select(&self, record: Option<&BootRecord>) -> Option<Slot> {
+        select_slot(record, |slot| match slot {
+            Slot::First => &self.first,
+            Slot::Second => &self.second,
+        })
+    }
+}
+
+#[test]
+fn the_active_slot_of_the_record_is_booted() {
+    let flash = Flash::new(&image(&binary(Slot::First)), &image(&binary(Slot::Second)));
+    for active in [Slot::First, Slot::Second] {
+        let record = BootRecord {
+            active,
+            confirmed: true,
+        };
+        assert_eq!(flash.select(Some(&record)), Some(active));
+    }
+}
+
+#[test]
+fn a_corrupted_image_falls_back_to_the_other_slot() {
+    let record = BootRecord {
+        active: Slot::Second,
+        confirmed: true,
+    };
+    let mut flash = Flash::new(&image(&binary(Slot::First)), &image(&binary(Slot::Second)));
+    flash.second[IMAGE_HEADER_SIZE + 1_000] ^= 0x01;
+    assert_eq!(flash.select(Some(&record)), Some(Slot::First));
+
+    // A corrupted header is as bad
+    flash.first[0] ^= 0x01;
+    assert_eq!(flash.select(Some(&record)), None);
+}
+
+#[test]
+fn an_erased_slot_an_unsigned_binary_or_an_image_linked_elsewhere_is_not_booted() {
+    let first = image(&binary(Slot::First));
+    assert!(image_is_valid(Slot::First, &slot(&first)));
+    assert!(!image_is_valid(Slot::Second, &slot(&first)));
+    assert!(!image_is_valid(Slot::First, &slot(&[])));
+    assert!(!image_is_valid(Slot::First, &slot(&binary(Slot::First))));
+
+    // Slots flashed by a debugger have no record, the first valid one boots
+    let flash = Flash::new(&[], &image(&binary(Slot::Second)));
+    assert_eq!(flash.select(None), Some(Slot::Second));
+    let flash = Flash::new(&first, &image(&binary(Slot::Second)));
+    assert_eq!(flash.select(None), Some(Slot::First));
+}
+
+#[test]
+fn an_image_longer_than_its_slot_is_not_booted() {
+    let binary = binary(Slot::First);
+    let mut header = ImageSigner::header(&[Configuration::UnitTest], &binary);
+    header.length = SLOT_SIZE;
+    let image = ImageSigner::new(1).sign(&header, &binary);
+    assert_eq!(
+        ImageHeader::decode(&image)
+            .unwrap()
+            .check_crc(&slot(&image)),
+        Err(ImageError::Crc)
+    );
+    assert!(!image_is_valid(Slot::First, &slot(&image)));
+}
+
+/// Boots as the bootloader does, through backup SRAM, returning the record it boots.
+fn boot(record: &mut BootRecord, backup_sram: &mut [u32; 3]) -> BootRecord {
+    let mut attempts = BootAttempts::from_words(*backup_sram);
+    if let Some(rollback) = count_boot(record, &mut attempts) {
+        *record = rollback;
+    }
+    *backup_sram = attempts.to_words();
+    record.clone()
+}
+
+#[test]
+fn an_unconfirmed_image_is_rolled_back_after_failed_boots() {
+    let mut record = BootRecord {
+        active: Slot::Second,
+        confirmed: false,
+    };
+    // Random at power up
+    let mut backup_sram = [0x1234_5678, 0x9ABC_DEF0, 0x0F0F_0F0F];
+    assert_eq!(
+        BootAttempts::from_words(backup_sram),
+        BootAttempts::default()
+    );
+
+    for count in 1..=MAX_BOOT_ATTEMPTS {
+        assert_eq!(boot(&mut record, &mut backup_sram).active, Slot::Second);
+        assert_eq!(BootAttempts::from_words(backup_sram).count, count);
+    }
+    let booted = boot(&mut record, &mut backup_sram);
+    assert_eq!(
+        booted,
+        BootRecord {
+            active: Slot::First,
+            confirmed: true
+        }
+    );
+    let attempts = BootAttempts::from_words(backup_sram);
+    assert_eq!(attempts.rolled_back_from, Some(Slot::Second));
+    assert_eq!(attempts.count, 0);
+
+    // The previous image is kept from then on
+    for _ in 0..10 {
+        assert_eq!(boot(&mut record, &mut backup_sram).active, Slot::First);
+    }
+}
+
+#[test]
+fn a_confirmed_image_is_never_rolled_back() {
+    let mut record = BootRecord {
+        active: Slot::Second,
+        confirmed: false,
+    };
+    let mut backup_sram = [0; 3];
+    boot(&mut record, &mut backup_sram);
+    boot(&mut record, &mut backup_sram);
+
+    record.confirmed = true;
+    for _ in 0..10 {
+        assert_eq!(boot(&mut record, &mut backup_sram).active, Slot::Second);
+    }
+    assert_eq!(
+        BootAttempts::from_words(backup_sram),
+        BootAttempts::default()
+    );
+
+    // A new update gets all its attempts
+    record = BootRecord {
+        active: Slot::First,
+        confirmed: false,
+    };
+    for _ in 0..MAX_BOOT_ATTEMPTS {
+        assert_eq!(boot(&mut record, &mut backup_sram).active, Slot::First);
+    }
+}
+
+#[test]
+fn corrupted_boot_attempts_start_over() {
+    let attempts = BootAttempts {
+        slot: Some(Slot::First),
+        count: 2,
+        rolled_back_from: Some(Slot::Second),
+    };
+    let mut words = attempts.to_words();
+    assert_eq!(BootAttempts::from_words(words), attempts);
+    words[1] ^= 0x100;
+    assert_eq!(BootAttempts::from_words(words), BootAttempts::default());
+}
+
+#[test]
+fn an_image_confirms_itself_after_running_connected() {
+    let second = |seconds: u64| Timestamp::new(seconds * 1_000);
+    let mut confirmation = BootConfirmation::new(true);
+    assert!(!confirmation.update(second(0), false));
+    assert!(!confirmation.update(second(10), true));
+
+    // Disconnecting starts the wait over
+    let halfway = Timestamp::new(10_000 + CONFIRM_AFTER.as_millis() / 2);
+    assert!(!confirmation.update(halfway, true));
+    assert!(!confirmation.update(halfway + second(1), false));
+    let reconnected = halfway + second(2);
+    assert!(!confirmation.update(reconnected, true));
+    assert!(!confirmation.update(second(10) + CONFIRM_AFTER, true));
+
+    assert!(confirmation.update(reconnected + CONFIRM_AFTER, true));
+    assert!(!confirmation.is_pending());
+    assert!(!confirmation.update(reconnected + CONFIRM_AFTER + second(1), true));
+
+    // Confirmed images have nothing to do
+    let mut confirmation = BootConfirmation::new(false);
+    assert!(!confirmation.update(second(0), true));
+    assert!(!confirmation.update(CONFIRM_AFTER + second(1), true));
+}
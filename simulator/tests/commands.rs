@@ -0,0 +1,111 @@
+use firmware_logic::data::journal::{Device, JournalEvent, Reason};
+use firmware_logic::{Command, DigitalOutput, IoLevel, NackReason, SwitchCommand};
+use simulator::Simulator;
+
+/// Outputs of the windlass in the unit test board map.
+const ANCHOR_UP: DigitalOutput = DigitalOutput::new(0);
+const ANCHOR_DOWN: DigitalOutput = DigitalOutput::new(1);
+
+fn anchor_down() -> Simulator {
+    let mut simulator = Simulator::default();
+    simulator
+        .send(Command::AnchorDown(SwitchCommand::On))
+        .unwrap();
+    simulator.run_for(Simulator::STEP);
+    assert_eq!(simulator.output_level(ANCHOR_DOWN), Some(IoLevel::High));
+    simulator
+}
+
+#[test]
+fn an_acked_command_is_applied() {
+    let mut simulator = Simulator::default();
+    simulator
+        .send(Command::AnchorLight(SwitchCommand::On))
+        .unwrap();
+    simulator.run_for(Simulator::STEP);
+    assert_eq!(
+        simulator.output_level(DigitalOutput::new(2)),
+        Some(IoLevel::High)
+    );
+}
+
+#[test]
+fn the_windlass_cannot_be_reversed_while_driven() {
+    let mut simulator = anchor_down();
+    assert_eq!(
+        simulator.send(Command::AnchorUp(SwitchCommand::On)),
+        Err(NackReason::Interlocked)
+    );
+    assert_eq!(
+        simulator.send(Command::AnchorUp(SwitchCommand::Toggle)),
+        Err(NackReason::Interlocked)
+    );
+    // Stopping is always accepted
+    simulator
+        .send(Command::AnchorUp(SwitchCommand::Off))
+        .unwrap();
+    simulator
+        .send(Command::AnchorDown(SwitchCommand::Off))
+        .unwrap();
+    simulator.run_for(Simulator::STEP);
+    assert_eq!(simulator.output_level(ANCHOR_DOWN), Some(IoLevel::Low));
+
+    simulator
+        .send(Command::AnchorUp(SwitchCommand::On))
+        .unwrap();
+    simulator.run_for(Simulator::STEP);
+    assert_eq!(simulator.output_level(ANCHOR_UP), Some(IoLevel::High));
+    assert_eq!(
+        simulator.send(Command::AnchorDown(SwitchCommand::On)),
+        Err(NackReason::Interlocked)
+    );
+}
+
+#[test]
+fn both_directions_queued_in_one_cycle_are_never_driven_together() {
+    let mut simulator = Simulator::default();
+    simulator
+        .send(Command::AnchorDown(SwitchCommand::On))
+        .unwrap();
+    simulator
+        .send(Command::AnchorUp(SwitchCommand::On))
+        .unwrap();
+    simulator.run_for(Simulator::STEP);
+    assert_eq!(simulator.output_level(ANCHOR_DOWN), Some(IoLevel::High));
+    assert_eq!(simulator.output_level(ANCHOR_UP), Some(IoLevel::Low));
+}
+
+#[test]
+fn an_interlocked_command_is_journaled_on_its_outputs() {
+    let mut simulator = anchor_down();
+    let applied = simulator.logic().journal().next_sequence();
+
+    assert_eq!(
+        simulator.send(Command::AnchorUp(SwitchCommand::On)),
+        Err(NackReason::Interlocked)
+    );
+    let journal = simulator.logic().journal();
+    let tripped: Vec<_> = journal
+        .iter()
+        .filter(|entry| entry.sequence >= applied)
+        .collect();
+    assert!(!tripped.is_empty());
+    for entry in tripped {
+        assert!(matches!(entry.device, Device::DigitalOutput(_)));
+        assert_eq!(
+            entry.event,
+            JournalEvent::InterlockTripped(Command::AnchorUp(SwitchCommand::On))
+        );
+        assert_eq!(entry.reason, Reason::Interlock);
+    }
+
+    // The reboot, then the windlass driven down
+    let first: Vec<_> = journal.iter().take(2).map(|entry| entry.event).collect();
+    assert_eq!(
+        first,
+        [
+            JournalEvent::Reboot,
+            JournalEvent::CommandApplied(Command::AnchorDown(SwitchCommand::On))
+        ]
+    );
+}
@@ -0,0 +1,5 @@
+use firmware_logic::Timestamp;
+
+pub fn ms(ms: u64) -> Timestamp {
+    Timestamp::new(ms)
+}
@@ -0,0 +1,174 @@
+mod common;
+
+use common::ms;
+use firmware_logic::data::connection::{
+    ConnectionMonitor, ConnectionStatistics, CONNECTION_WINDOW,
+};
+use firmware_logic::data::ping::PingStatistics;
+use firmware_logic::{FirmwareReporting, Timestamp, Wingman2IOCardStatus};
+
+const TIMEOUT: Timestamp = Timestamp::new(5_000);
+const PERIOD: u64 = 500;
+
+/// Heartbeats every `PERIOD`, every other one `late`, returns the time of the last.
+fn heartbeats(monitor: &mut ConnectionMonitor, count: u64, late: u64) -> u64 {
+    let mut now = 0;
+    for i in 0..count {
+        now = i * PERIOD + if i % 2 == 0 { 0 } else { late };
+        monitor.heartbeat(ms(now));
+    }
+    now
+}
+
+fn pings(round_trip_ms: u32, loss_percent: u8) -> PingStatistics {
+    PingStatistics {
+        round_trip_ms: Some(round_trip_ms),
+        loss_percent,
+        samples: 16,
+    }
+}
+
+#[test]
+fn the_connection_is_lost_when_nothing_is_received_within_the_timeout() {
+    let mut monitor = ConnectionMonitor::new(TIMEOUT);
+    assert!(!monitor.is_connected(ms(0)));
+    assert_eq!(monitor.qos(ms(0), &PingStatistics::default()), 0);
+
+    monitor.heartbeat(ms(1_000));
+    assert!(monitor.is_connected(ms(1_000)));
+    assert!(monitor.is_connected(ms(5_999)));
+    assert!(!monitor.is_connected(ms(6_000)));
+
+    // Commands keep the connection as well
+    monitor.command(ms(5_000));
+    assert!(monitor.is_connected(ms(9_999)));
+    assert_eq!(monitor.qos(ms(10_000), &pings(5, 0)), 0);
+    assert_eq!(monitor.statistics().received_messages, 2);
+}
+
+#[test]
+fn regular_heartbeats_rate_the_connection_by_the_pings() {
+    let mut monitor = ConnectionMonitor::new(TIMEOUT);
+    let mut now = 0;
+    for _ in 0..10 {
+        monitor.heartbeat(ms(now));
+        now += PERIOD;
+    }
+    assert_eq!(
+        monitor.statistics(),
+        ConnectionStatistics {
+            received_messages: 10,
+            jitter_ms: 0,
+            gaps: 0,
+            recent_gaps: 0,
+            longest_interval_ms: PERIOD,
+        }
+    );
+    assert_eq!(monitor.qos(ms(now), &PingStatistics::default()), 100);
+    assert_eq!(monitor.qos(ms(now), &pings(5, 0)), 100);
+    assert_eq!(monitor.qos(ms(now), &pings(5, 25)), 75);
+
+    // Commands count but do not disturb the heartbeat intervals
+    monitor.command(ms(now + 17));
+    monitor.command(ms(now + 333));
+    let statistics = monitor.statistics();
+    assert_eq!(statistics.received_messages, 12);
+    assert_eq!(statistics.jitter_ms, 0);
+}
+
+#[test]
+fn jitter_lowers_the_qos() {
+    let qos = |late| {
+        let mut monitor = ConnectionMonitor::new(TIMEOUT);
+        let now = heartbeats(&mut monitor, CONNECTION_WINDOW as u64 + 1, late);
+        (
+            monitor.statistics().jitter_ms,
+            monitor.qos(ms(now), &PingStatistics::default()),
+        )
+    };
+    assert_eq!(qos(0), (0, 100));
+    assert_eq!(qos(10), (10, 100));
+    assert_eq!(qos(250), (250, 50));
+    assert_eq!(qos(400), (400, 50));
+    let (jitter, moderate) = qos(130);
+    assert_eq!(jitter, 130);
+    assert_eq!(moderate, 75);
+}
+
+#[test]
+fn gaps_are_counted_and_forgotten_as_the_window_moves() {
+    let mut monitor = ConnectionMonitor::new(TIMEOUT);
+    monitor.heartbeat(ms(0));
+    monitor.heartbeat(ms(500));
+    // Half the timeout is not a gap yet
+    monitor.heartbeat(ms(3_000));
+    assert_eq!(monitor.statistics().gaps, 0);
+    monitor.heartbeat(ms(6_000));
+    monitor.heartbeat(ms(13_000));
+    let statistics = monitor.statistics();
+    assert_eq!(statistics.gaps, 2);
+    assert_eq!(statistics.recent_gaps, 2);
+    assert_eq!(statistics.longest_interval_ms, 7_000);
+
+    let mut now = 13_000;
+    for _ in 0..CONNECTION_WINDOW - 1 {
+        now += PERIOD;
+        monitor.heartbeat(ms(now));
+    }
+    assert_eq!(monitor.statistics().recent_gaps, 1);
+    now += PERIOD;
+    monitor.heartbeat(ms(now));
+    let statistics = monitor.statistics();
+    assert_eq!(statistics.recent_gaps, 0);
+    assert_eq!(statistics.gaps, 2);
+    assert_eq!(statistics.jitter_ms, 0);
+    assert_eq!(monitor.qos(ms(now), &PingStatistics::default()), 100);
+}
+
+#[test]
+fn recent_gaps_lower_the_qos() {
+    let mut monitor = ConnectionMonitor::new(TIMEOUT);
+    let mut now = 0;
+    for _ in 0..3 {
+        monitor.heartbeat(ms(now));
+        now += 3_000;
+    }
+    monitor.heartbeat(ms(now));
+    assert_eq!(monitor.statistics().recent_gaps, 3);
+    assert_eq!(monitor.statistics().jitter_ms, 0);
+    assert_eq!(monitor.qos(ms(now), &PingStatistics::default()), 70);
+}
+
+#[test]
+fn the_card_status_follows_the_connection() {
+    let mut monitor = ConnectionMonitor::new(TIMEOUT);
+    let mut status = Wingman2IOCardStatus::default();
+    status.update(ms(0), &monitor, &PingStatistics::default());
+    assert!(!status.is_connected_to_control_server);
+    assert_eq!(status.control_server_qos, 0);
+    assert_eq!(status.tick(), 1);
+
+    monitor.heartbeat(ms(100));
+    monitor.heartbeat(ms(3_100));
+    monitor.command(ms(3_200));
+    status.update(ms(3_300), &monitor, &pings(5, 50));
+    assert!(status.is_connected_to_control_server);
+    assert_eq!(status.control_server_qos, 45);
+    assert_eq!(status.control_server_received_messages, 3);
+    assert_eq!(status.control_server_gaps, 1);
+    assert_eq!(status.control_server_longest_gap_ms, 3_000);
+    assert_eq!(status.total_uptime_ms(), 3_300);
+    assert_eq!(status.tick(), 2);
+
+    status.update(ms(8_200), &monitor, &pings(5, 50));
+    assert!(!status.is_connected_to_control_server);
+    assert_eq!(status.control_server_qos, 0);
+
+    // Published with the reporting
+    let reporting = FirmwareReporting {
+        card: status,
+        ..FirmwareReporting::default()
+    };
+    assert_eq!(reporting.card.control_server_received_messages, 3);
+    assert_eq!(FirmwareReporting::default().card.tick(), 0);
+}
@@ -0,0 +1,326 @@
+use firmware_logic::drivers::dio::{
+    update_watched_inputs, DiBank, MAX7301_CONFIGURATION, MAX7301_PORT_CONFIG,
+    MAX7301_TRANSITION_MASK,
+};
+use firmware_logic::{
+    Configuration, DigitalInput, IoBank, IoError, IoLevel, IoState, IoSupply, IoTemp, OutputFault,
+    Wingman2HardwareStatus,
+};
+use simulator::dio::SimulatedIoBoard;
+
+fn initialized() -> (SimulatedIoBoard, simulator::dio::SimulatedDio) {
+    let board = SimulatedIoBoard::new();
+    let mut dio = board.dio();
+    dio.init().unwrap();
+    (board, dio)
+}
+
+#[test]
+fn init_configures_the_output_controllers() {
+    let (board, _dio) = initialized();
+    for bank in [IoBank::Bank1, IoBank::Bank6] {
+        // EN outputs on ports 4 to 11, STAT inputs with pull-up on 12 to 19
+        assert_eq!(board.max7301_register(bank, MAX7301_PORT_CONFIG + 1), 0x55);
+        assert_eq!(board.max7301_register(bank, MAX7301_PORT_CONFIG + 2), 0x55);
+        assert_eq!(board.max7301_register(bank, MAX7301_PORT_CONFIG + 3), 0xFF);
+        assert_eq!(board.max7301_register(bank, MAX7301_PORT_CONFIG + 4), 0xFF);
+        // Supply detection without pull-up, nHot and nWarm with
+        assert_eq!(board.max7301_register(bank, MAX7301_PORT_CONFIG + 5), 0xFA);
+        assert_eq!(board.max7301_register(bank, MAX7301_CONFIGURATION), 0x01);
+        assert!(!board.output(bank, 1));
+    }
+}
+
+#[test]
+fn inputs_are_read_one_by_one_and_by_bank() {
+    let (board, mut dio) = initialized();
+    board.set_input(IoBank::Bank2, 1, true);
+    board.set_input(IoBank::Bank2, 8, true);
+    board.set_di_hot(IoBank::Bank2, true);
+
+    assert_eq!(dio.get_di_by_bank(IoBank::Bank2, 1), Ok(IoLevel::High));
+    assert_eq!(dio.get_di_by_bank(IoBank::Bank2, 2), Ok(IoLevel::Low));
+    assert_eq!(dio.get_di_by_num(16), Ok(IoLevel::High));
+    assert_eq!(dio.get_di_by_num(1), Ok(IoLevel::Low));
+    assert_eq!(dio.get_di_temp(IoBank::Bank2), Ok(IoTemp::Hot));
+    assert_eq!(dio.get_di_supply(IoBank::Bank2), Ok(IoSupply::Powered));
+
+    let mut inputs = [IoLevel::Low; 8];
+    inputs[0] = IoLevel::High;
+    inputs[7] = IoLevel::High;
+    assert_eq!(
+        dio.read_di_bank(IoBank::Bank2),
+        Ok(DiBank {
+            inputs,
+            temp: IoTemp::Hot,
+            supply: IoSupply::Powered,
+        })
+    );
+
+    board.set_di_supply(IoBank::Bank3, false);
+    let bank = dio.read_di_bank(IoBank::Bank3).unwrap();
+    assert_eq!(bank.supply, IoSupply::Unpowered);
+    assert_eq!(bank.temp, IoTemp::Normal);
+}
+
+#[test]
+fn a_corrupted_frame_is_a_parity_error() {
+    let (board, mut dio) = initialized();
+    board.corrupt_di_parity(IoBank::Bank4, true);
+    assert_eq!(dio.read_di_bank(IoBank::Bank4), Err(IoError::DiParity));
+    assert_eq!(dio.get_di_by_bank(IoBank::Bank4, 3), Err(IoError::DiParity));
+    assert!(dio.read_di_bank(IoBank::Bank5).is_ok());
+
+    board.corrupt_di_parity(IoBank::Bank4, false);
+    assert!(dio.read_di_bank(IoBank::Bank4).is_ok());
+}
+
+#[test]
+fn a_missing_bank_is_not_present() {
+    let board = SimulatedIoBoard::new();
+    board.set_fitted(IoBank::Bank3, false);
+    let mut dio = board.dio();
+
+    // The other banks are initialized all the same
+    assert_eq!(dio.init(), Err(IoError::NotPresent));
+    assert_eq!(
+        board.max7301_register(IoBank::Bank4, MAX7301_CONFIGURATION),
+        0x01
+    );
+
+    assert_eq!(dio.read_di_bank(IoBank::Bank3), Err(IoError::NotPresent));
+    assert_eq!(dio.read_do_bank(IoBank::Bank3), Err(IoError::NotPresent));
+    assert_eq!(dio.get_do_supply(IoBank::Bank3), Err(IoError::NotPresent));
+    assert!(dio.read_do_bank(IoBank::Bank4).is_ok());
+}
+
+#[test]
+fn output_banks_report_their_status() {
+    let (board, mut dio) = initialized();
+    board.set_stat(IoBank::Bank1, 3, Some(false));
+    board.set_do_temp(IoBank::Bank1, IoTemp::Warm);
+
+    let bank = dio.read_do_bank(IoBank::Bank1).unwrap();
+    assert_eq!(bank.supply, IoSupply::Volts24);
+    assert_eq!(bank.temp, IoTemp::Warm);
+    let states = bank.states.unwrap();
+    assert_eq!(states[2], IoState::OpenOrFault);
+    assert_eq!(states[0], IoState::Normal);
+    assert_eq!(
+        dio.get_do_state_by_bank(IoBank::Bank1, 3),
+        Ok(IoState::OpenOrFault)
+    );
+    assert_eq!(dio.get_do_temp(IoBank::Bank1), Ok(IoTemp::Warm));
+
+    board.set_do_supply(IoBank::Bank2, IoSupply::Volts12);
+    assert_eq!(dio.get_do_supply(IoBank::Bank2), Ok(IoSupply::Volts12));
+
+    // Nothing is known of the outputs of an unpowered bank
+    board.set_do_supply(IoBank::Bank2, IoSupply::Unpowered);
+    assert_eq!(dio.read_do_bank(IoBank::Bank2).unwrap().states, None);
+    assert_eq!(dio.get_do_state_by_num(9), Err(IoError::IllegalOutput));
+}
+
+#[test]
+fn outputs_are_written_one_by_one_and_by_bank() {
+    let (board, mut dio) = initialized();
+    dio.set_do_by_bank(IoBank::Bank5, 2, IoLevel::High).unwrap();
+    assert!(board.output(IoBank::Bank5, 2));
+    dio.set_do_by_num(34, IoLevel::HiZ).unwrap();
+    assert!(!board.output(IoBank::Bank5, 2));
+
+    let mut levels = [IoLevel::HiZ; 8];
+    levels[0] = IoLevel::High;
+    levels[6] = IoLevel::High;
+    dio.write_do_bank(IoBank::Bank6, levels).unwrap();
+    for output in 1..=8 {
+        assert_eq!(
+            board.output(IoBank::Bank6, output),
+            output == 1 || output == 7
+        );
+    }
+
+    // The STAT inputs next to the EN outputs are left alone
+    assert_eq!(
+        board.max7301_register(IoBank::Bank6, MAX7301_PORT_CONFIG + 3),
+        0xFF
+    );
+    assert_eq!(
+        dio.set_do_by_num(49, IoLevel::High),
+        Err(IoError::IllegalOutput)
+    );
+}
+
+#[test]
+fn bank_reads_take_a_fraction_of_the_transfers() {
+    let (board, mut dio) = initialized();
+
+    let before = board.transfers();
+    for input in 1..=8 {
+        dio.get_di_by_bank(IoBank::Bank1, input).unwrap();
+    }
+    dio.get_di_temp(IoBank::Bank1).unwrap();
+    dio.get_di_supply(IoBank::Bank1).unwrap();
+    for output in 1..=8 {
+        dio.get_do_state_by_bank(IoBank::Bank1, output).unwrap();
+    }
+    dio.get_do_temp(IoBank::Bank1).unwrap();
+    dio.get_do_supply(IoBank::Bank1).unwrap();
+    let one_by_one = board.transfers() - before;
+
+    let before = board.transfers();
+    dio.read_di_bank(IoBank::Bank1).unwrap();
+    dio.read_do_bank(IoBank::Bank1).unwrap();
+    let by_bank = board.transfers() - before;
+
+    // The DO bank read brings the watched inputs along
+    assert_eq!(by_bank, 7);
+    assert_eq!(one_by_one, 48);
+}
+
+#[test]
+fn low_turns_an_output_off() {
+    let (board, mut dio) = initialized();
+    dio.set_do_by_bank(IoBank::Bank1, 4, IoLevel::High).unwrap();
+    dio.set_do_by_bank(IoBank::Bank1, 4, IoLevel::Low).unwrap();
+    assert!(!board.output(IoBank::Bank1, 4));
+
+    let mut levels = [IoLevel::Low; 8];
+    levels[1] = IoLevel::High;
+    assert_eq!(dio.write_do_bank(IoBank::Bank1, levels), Ok([None; 8]));
+    assert!(board.output(IoBank::Bank1, 2));
+    assert!(!board.output(IoBank::Bank1, 1));
+}
+
+#[test]
+fn write_verify_finds_stuck_outputs() {
+    let (board, mut dio) = initialized();
+    dio.set_write_verify(true);
+    board.set_stuck(IoBank::Bank2, 5, Some(false));
+
+    assert_eq!(
+        dio.set_do_by_bank(IoBank::Bank2, 5, IoLevel::High),
+        Err(IoError::Stuck)
+    );
+    // Off is where it is stuck, nothing to tell
+    assert_eq!(dio.set_do_by_bank(IoBank::Bank2, 5, IoLevel::Low), Ok(()));
+    assert_eq!(dio.set_do_by_bank(IoBank::Bank2, 6, IoLevel::High), Ok(()));
+
+    let faults = dio
+        .write_do_bank(IoBank::Bank2, [IoLevel::High; 8])
+        .unwrap();
+    assert_eq!(faults[4], Some(OutputFault::Stuck));
+    assert_eq!(faults.iter().flatten().count(), 1);
+
+    // Without write-verify the port is not read back
+    dio.set_write_verify(false);
+    assert_eq!(dio.set_do_by_bank(IoBank::Bank2, 5, IoLevel::High), Ok(()));
+}
+
+#[test]
+fn write_verify_tells_an_open_load_from_an_overload() {
+    let (board, mut dio) = initialized();
+    dio.set_write_verify(true);
+    board.set_stat(IoBank::Bank3, 1, Some(false));
+    board.set_stat(IoBank::Bank3, 2, Some(false));
+
+    let mut levels = [IoLevel::Low; 8];
+    levels[1] = IoLevel::High;
+    let faults = dio.write_do_bank(IoBank::Bank3, levels).unwrap();
+    assert_eq!(faults[0], Some(OutputFault::OpenLoad));
+    assert_eq!(faults[1], Some(OutputFault::Overload));
+    assert_eq!(faults[2], None);
+
+    // A stuck output is stuck whatever STAT says
+    board.set_stuck(IoBank::Bank3, 1, Some(false));
+    levels[0] = IoLevel::High;
+    let faults = dio.write_do_bank(IoBank::Bank3, levels).unwrap();
+    assert_eq!(faults[0], Some(OutputFault::Stuck));
+
+    // STAT tells nothing while the bank is unpowered
+    board.set_do_supply(IoBank::Bank3, IoSupply::Unpowered);
+    let faults = dio.write_do_bank(IoBank::Bank3, levels).unwrap();
+    assert_eq!(faults[1], None);
+    assert_eq!(faults[0], Some(OutputFault::Stuck));
+}
+
+#[test]
+fn a_watched_input_change_raises_the_interrupt() {
+    let (board, mut dio) = initialized();
+    dio.enable_change_detection(IoBank::Bank2, 0b0000_0101)
+        .unwrap();
+    assert_eq!(
+        board.max7301_register(IoBank::Bank2, MAX7301_TRANSITION_MASK),
+        0x05
+    );
+    assert!(!board.interrupt(IoBank::Bank2));
+
+    // Ports not in the mask are not watched
+    board.set_watched_input(IoBank::Bank2, 2, Some(false));
+    assert!(!board.interrupt(IoBank::Bank2));
+
+    board.set_watched_input(IoBank::Bank2, 3, Some(false));
+    assert!(board.interrupt(IoBank::Bank2));
+    assert!(!board.interrupt(IoBank::Bank1));
+
+    // Detection stops at the first transition until rearmed against the new levels
+    board.set_watched_input(IoBank::Bank2, 3, None);
+    assert!(board.interrupt(IoBank::Bank2));
+    dio.rearm_change_detection(IoBank::Bank2).unwrap();
+    assert!(!board.interrupt(IoBank::Bank2));
+    board.set_watched_input(IoBank::Bank2, 1, Some(false));
+    assert!(board.interrupt(IoBank::Bank2));
+}
+
+#[test]
+fn change_detection_leaves_the_outputs_alone() {
+    let (board, mut dio) = initialized();
+    dio.set_do_by_bank(IoBank::Bank4, 1, IoLevel::High).unwrap();
+    dio.enable_change_detection(IoBank::Bank4, 0x7F).unwrap();
+
+    assert!(board.output(IoBank::Bank4, 1));
+    assert_eq!(
+        board.max7301_register(IoBank::Bank4, MAX7301_CONFIGURATION),
+        0x81
+    );
+    assert!(dio.read_do_bank(IoBank::Bank4).is_ok());
+    assert_eq!(
+        board.max7301_register(IoBank::Bank4, MAX7301_CONFIGURATION),
+        0x81
+    );
+}
+
+#[test]
+fn a_watched_input_change_reaches_the_hardware_status() {
+    let (board, mut dio) = initialized();
+    dio.enable_change_detection(IoBank::Bank5, 0x7F).unwrap();
+    let mut hardware_status = Wingman2HardwareStatus::new(Configuration::UnitTest);
+    let mut scan = |dio: &mut simulator::dio::SimulatedDio| {
+        let read = dio.read_do_bank(IoBank::Bank5).unwrap();
+        update_watched_inputs(
+            &mut hardware_status.digital_inputs,
+            IoBank::Bank5,
+            Some(&read),
+        );
+        hardware_status.clone()
+    };
+    // Port 26, pulled up until driven
+    let watched = DigitalInput::watched(IoBank::Bank5, 2);
+    assert_eq!(
+        scan(&mut dio).get_digital_input(&watched),
+        Some(IoLevel::High)
+    );
+
+    board.set_watched_input(IoBank::Bank5, 3, Some(false));
+    assert!(board.interrupt(IoBank::Bank5));
+    let scanned = scan(&mut dio);
+    assert_eq!(scanned.get_digital_input(&watched), Some(IoLevel::Low));
+    let low: Vec<_> = (0..scanned.digital_inputs.len())
+        .filter(|&address| scanned.digital_inputs[address].level == Some(IoLevel::Low))
+        .collect();
+    assert_eq!(low, [watched.address]);
+
+    // A failed read leaves them unknown
+    update_watched_inputs(&mut hardware_status.digital_inputs, IoBank::Bank5, None);
+    assert_eq!(hardware_status.get_digital_input(&watched), None);
+}
@@ -0,0 +1,149 @@
+use firmware_logic::{
+    ButtonCommand, Command, Configuration, ControllerLogic, DeviceIdentifier, DigitalOutput,
+    FirmwareLogic, IoLevel, NackReason, SwitchCommand, Timestamp, Wingman2HardwareStatus,
+    ENGINE_MAX_CRANK,
+};
+use simulator::{EngineState, Simulator};
+
+const PORT: DeviceIdentifier = DeviceIdentifier::Device(1);
+const STBD: DeviceIdentifier = DeviceIdentifier::Device(2);
+/// Outputs of the engines in the unit test board map.
+const PORT_IGNITION: DigitalOutput = DigitalOutput::new(13);
+const STBD_IGNITION: DigitalOutput = DigitalOutput::new(14);
+const PORT_STARTER: DigitalOutput = DigitalOutput::new(15);
+const STBD_STARTER: DigitalOutput = DigitalOutput::new(16);
+
+fn ignition_on(engine: DeviceIdentifier) -> Simulator {
+    let mut simulator = Simulator::default();
+    simulator
+        .send(Command::EngineIgnition(engine, SwitchCommand::On))
+        .unwrap();
+    simulator.run_for(Simulator::STEP);
+    simulator
+}
+
+#[test]
+fn the_starter_is_interlocked_with_the_ignition() {
+    let mut simulator = Simulator::default();
+    assert_eq!(
+        simulator.send(Command::EngineStart(PORT, ButtonCommand::On)),
+        Err(NackReason::Interlocked)
+    );
+    // Releasing is always accepted
+    simulator
+        .send(Command::EngineStart(PORT, ButtonCommand::Off))
+        .unwrap();
+
+    // Only the engine addressed has its ignition on
+    let mut simulator = ignition_on(STBD);
+    assert_eq!(
+        simulator.send(Command::EngineStart(PORT, ButtonCommand::On)),
+        Err(NackReason::Interlocked)
+    );
+    simulator
+        .send(Command::EngineStart(STBD, ButtonCommand::On))
+        .unwrap();
+    simulator.run_for(Simulator::STEP);
+    assert_eq!(simulator.output_level(STBD_STARTER), Some(IoLevel::High));
+    assert_eq!(simulator.output_level(PORT_STARTER), Some(IoLevel::Low));
+}
+
+#[test]
+fn both_ignitions_are_switched_together() {
+    let mut simulator = ignition_on(DeviceIdentifier::All);
+    assert_eq!(simulator.output_level(PORT_IGNITION), Some(IoLevel::High));
+    assert_eq!(simulator.output_level(STBD_IGNITION), Some(IoLevel::High));
+
+    simulator
+        .send(Command::EngineIgnition(PORT, SwitchCommand::Off))
+        .unwrap();
+    simulator.run_for(Simulator::STEP);
+    assert_eq!(simulator.output_level(PORT_IGNITION), Some(IoLevel::Low));
+    assert_eq!(simulator.output_level(STBD_IGNITION), Some(IoLevel::High));
+}
+
+#[test]
+fn the_starter_is_released_with_the_button() {
+    let mut simulator = ignition_on(PORT);
+    simulator
+        .hold(
+            Command::EngineStart(PORT, ButtonCommand::On),
+            Timestamp::new(500),
+        )
+        .unwrap();
+    assert_eq!(simulator.output_level(PORT_STARTER), Some(IoLevel::High));
+    assert_eq!(simulator.plant().engines[0].state, EngineState::Cranking);
+
+    simulator
+        .send(Command::EngineStart(PORT, ButtonCommand::Off))
+        .unwrap();
+    simulator.run_for(Simulator::STEP);
+    assert_eq!(simulator.output_level(PORT_STARTER), Some(IoLevel::Low));
+    // The plant follows the outputs on the next step
+    simulator.run_for(Simulator::STEP);
+    assert_eq!(simulator.plant().engines[0].state, EngineState::Stopped);
+}
+
+#[test]
+fn the_starter_disengages_once_the_engine_runs() {
+    let mut simulator = ignition_on(PORT);
+    simulator
+        .hold(
+            Command::EngineStart(PORT, ButtonCommand::On),
+            Timestamp::new(3_000),
+        )
+        .unwrap();
+    assert_eq!(simulator.plant().engines[0].state, EngineState::Running);
+    assert_eq!(simulator.output_level(PORT_STARTER), Some(IoLevel::Low));
+
+    // Cranking a running engine is refused until it stops
+    let longest_crank = simulator.plant().engines[0].longest_crank;
+    simulator
+        .hold(
+            Command::EngineStart(PORT, ButtonCommand::On),
+            Timestamp::new(1_000),
+        )
+        .unwrap();
+    assert_eq!(simulator.plant().engines[0].longest_crank, longest_crank);
+}
+
+#[test]
+fn the_button_must_be_released_after_the_crank_limit() {
+    let mut simulator = ignition_on(PORT);
+    simulator.plant_mut().engines[0].fails_to_start = true;
+    simulator
+        .hold(
+            Command::EngineStart(PORT, ButtonCommand::On),
+            ENGINE_MAX_CRANK + Timestamp::new(2_000),
+        )
+        .unwrap();
+    assert_eq!(simulator.output_level(PORT_STARTER), Some(IoLevel::Low));
+    assert!(simulator.plant().engines[0].longest_crank <= ENGINE_MAX_CRANK);
+
+    // The held button expires, which releases it
+    simulator.run_for(Timestamp::new(1_000));
+    simulator
+        .hold(
+            Command::EngineStart(PORT, ButtonCommand::On),
+            Timestamp::new(500),
+        )
+        .unwrap();
+    assert_eq!(simulator.output_level(PORT_STARTER), Some(IoLevel::High));
+}
+
+#[test]
+fn a_reboot_releases_the_starters_but_keeps_the_ignitions() {
+    let mut hardware_status = Wingman2HardwareStatus::new(Configuration::UnitTest);
+    hardware_status.set_digital_output(&PORT_IGNITION, IoLevel::High);
+    hardware_status.set_digital_output(&PORT_STARTER, IoLevel::High);
+
+    FirmwareLogic::default().initialize(&mut hardware_status);
+    assert_eq!(
+        hardware_status.get_digital_output(&PORT_IGNITION),
+        Some(IoLevel::High)
+    );
+    assert_eq!(
+        hardware_status.get_digital_output(&PORT_STARTER),
+        Some(IoLevel::Low)
+    );
+}
@@ -0,0 +1,88 @@
+use firmware_logic::data::image::{
+    verify_signature, BuildInfo, ImageError, ImageHeader, IMAGE_HEADER_SIZE,
+};
+use firmware_logic::Configuration;
+use simulator::image::ImageSigner;
+
+/// Bytes of `value` as they end up in the firmware binary.
+fn bytes_of<T>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts((value as *const T).cast(), core::mem::size_of::<T>()) }
+}
+
+fn verify(public_key: &[u8; 32], image: &[u8]) -> Result<(), ImageError> {
+    let header = image[..IMAGE_HEADER_SIZE].try_into().unwrap();
+    verify_signature(public_key, header, |offset, buf: &mut [u8]| {
+        let offset = offset as usize;
+        buf.copy_from_slice(&image[offset..offset + buf.len()]);
+        Ok::<_, ()>(())
+    })
+}
+
+#[test]
+fn a_header_decodes_as_encoded_and_its_signature_verifies() {
+    let binary: Vec<u8> = (0..5_000u32).map(|i| (i * 31) as u8).collect();
+    let header = ImageSigner::header(&[Configuration::UnitTest], &binary);
+    let signer = ImageSigner::new(1);
+    let image = signer.sign(&header, &binary);
+
+    assert_eq!(ImageHeader::decode(&image), Ok(header.clone()));
+    assert_eq!(header.check_crc(&image), Ok(()));
+    assert_eq!(verify(&signer.public_key(), &image), Ok(()));
+    assert_eq!(
+        verify(&ImageSigner::new(2).public_key(), &image),
+        Err(ImageError::Signature)
+    );
+
+    // The erased end of the header is not signed, the fields are
+    let mut image = image;
+    image[IMAGE_HEADER_SIZE - 1] = 0x00;
+    assert_eq!(verify(&signer.public_key(), &image), Ok(()));
+    let signature: [u8; 64] = image[4..68].try_into().unwrap();
+    let mut changed = header;
+    changed.git_dirty = true;
+    let encoded: &mut [u8; IMAGE_HEADER_SIZE] =
+        (&mut image[..IMAGE_HEADER_SIZE]).try_into().unwrap();
+    changed.encode(&mut *encoded).unwrap();
+    ImageHeader::set_signature(encoded, &signature);
+    assert_eq!(
+        verify(&signer.public_key(), &image),
+        Err(ImageError::Signature)
+    );
+}
+
+#[test]
+fn a_binary_without_a_header_is_not_decoded() {
+    assert_eq!(
+        ImageHeader::decode(&[0xFF; 2 * IMAGE_HEADER_SIZE]),
+        Err(ImageError::Header)
+    );
+    assert_eq!(ImageHeader::decode(&[0x00; 100]), Err(ImageError::Header));
+}
+
+#[test]
+fn build_info_is_found_in_a_binary() {
+    let info = BuildInfo::new(
+        "0.2.0",
+        Some("0123456789abcdef0123456789abcdef01234567"),
+        Some(true),
+    );
+    let mut binary = vec![0x5A; 300];
+    binary.extend_from_slice(bytes_of(&info));
+    binary.extend_from_slice(&[0xA5; 300]);
+
+    let (version, git_hash, git_dirty) = BuildInfo::find(&binary).unwrap();
+    assert_eq!(version.as_str(), "0.2.0");
+    assert_eq!(
+        git_hash,
+        Some([
+            0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab,
+            0xcd, 0xef, 0x01, 0x23, 0x45, 0x67
+        ])
+    );
+    assert!(git_dirty);
+
+    let info = BuildInfo::new("0.2.0", None, None);
+    let (_, git_hash, git_dirty) = BuildInfo::find(bytes_of(&info)).unwrap();
+    assert_eq!((git_hash, git_dirty), (None, false));
+    assert_eq!(BuildInfo::find(&[0x5A; 300]), None);
+}
@@ -0,0 +1,83 @@
+//! Arbitrary command sequences into `FirmwareLogic`, checking the interlocks hold on the plant.
+
+use firmware_logic::{
+    BoardMap, ButtonCommand, Command, IoLevel, SwitchCommand, Timestamp, ENGINE_MAX_CRANK,
+};
+use proptest::{collection::vec, prelude::*};
+use simulator::{Plant, Simulator};
+
+/// A command and how long to wait, or hold it for a button, before the next one.
+///
+/// Commands are built by decoding bytes narrowed down to plausible variant and field indices so
+/// that new `Command` variants are covered without being listed here. The commands of interlocked
+/// devices are mixed in, they would rarely come up in the right order otherwise.
+fn steps() -> impl Strategy<Value = Vec<(Command, Timestamp)>> {
+    let decoded = any::<[u8; 4]>()
+        .prop_map(|bytes| postcard::from_bytes::<Command>(&bytes.map(|byte| byte % 32)).ok());
+    let interlocked = prop::sample::select(vec![
+        Command::AnchorUp(SwitchCommand::On),
+        Command::AnchorDown(SwitchCommand::On),
+        Command::AnchorUp(SwitchCommand::Off),
+        Command::AnchorDown(SwitchCommand::Off),
+        Command::EngineIgnition(1.into(), SwitchCommand::On),
+        Command::EngineIgnition(2.into(), SwitchCommand::On),
+        Command::EngineStart(1.into(), ButtonCommand::On),
+        Command::EngineStart(2.into(), ButtonCommand::On),
+    ])
+    .prop_map(Some);
+    let command = prop_oneof![decoded, interlocked];
+    let millis = prop_oneof![0..1_500u64, 5_000..8_000u64];
+    vec((command, millis), 1..16).prop_map(|candidates| {
+        candidates
+            .into_iter()
+            .filter_map(|(command, millis)| Some((command?, Timestamp::new(millis))))
+            .collect()
+    })
+}
+
+fn is_high(simulator: &Simulator, output: Option<firmware_logic::DigitalOutput>) -> bool {
+    output.is_some_and(|output| simulator.output_level(output) == Some(IoLevel::High))
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn interlocks_hold_for_any_commands(
+        steps in steps(),
+        // An engine that starts ends the cranking before the limit can be reached.
+        fails_to_start in prop::array::uniform2(prop::bool::weighted(0.75)),
+    ) {
+        let mut plant = Plant::default();
+        for (engine, fails_to_start) in plant.engines.iter_mut().zip(fails_to_start) {
+            engine.fails_to_start = fails_to_start;
+        }
+        let mut simulator = Simulator::new(plant);
+        let board_map = &BoardMap::UNIT_TEST;
+
+        for (command, duration) in steps {
+            if command.is_button() {
+                let _ = simulator.hold(command, duration);
+            } else {
+                let _ = simulator.send(command);
+                let end = simulator.now() + duration;
+                while simulator.now() < end {
+                    simulator.step();
+                    prop_assert!(
+                        !(is_high(&simulator, board_map.anchor_up)
+                            && is_high(&simulator, board_map.anchor_down)),
+                        "anchor driven up and down at {:?}",
+                        simulator.now()
+                    );
+                }
+            }
+        }
+        // Let a held starter run into its limit.
+        simulator.run_for(ENGINE_MAX_CRANK);
+
+        prop_assert_eq!(simulator.plant().windlass.both_driven, Timestamp::default());
+        for engine in &simulator.plant().engines {
+            prop_assert!(engine.longest_crank <= ENGINE_MAX_CRANK);
+        }
+    }
+}
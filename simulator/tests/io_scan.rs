@@ -0,0 +1,53 @@
+use firmware_logic::data::io_scan::{IoScanTiming, IO_SCAN_INTERVAL, IO_SCAN_WINDOW};
+
+#[test]
+fn scans_are_timed() {
+    let mut timing = IoScanTiming::default();
+    timing.record(1_000, 0, 0);
+    timing.record(3_000, 0, 0);
+    timing.record(800, 0, 0);
+
+    let status = timing.status();
+    assert_eq!(status.scans, 3);
+    assert_eq!(status.latest_us, 800);
+    assert_eq!(status.mean_us, 1_600);
+    assert_eq!(status.longest_us, 3_000);
+    assert_eq!(status.overruns, 0);
+}
+
+#[test]
+fn the_mean_follows_the_recent_scans() {
+    let mut timing = IoScanTiming::default();
+    timing.record(4_000, 0, 0);
+    for _ in 0..IO_SCAN_WINDOW {
+        timing.record(500, 0, 0);
+    }
+    assert_eq!(timing.status().mean_us, 500);
+    assert_eq!(timing.status().longest_us, 4_000);
+}
+
+#[test]
+fn overruns_and_failures_are_counted() {
+    let interval_us = IO_SCAN_INTERVAL.as_millis() as u32 * 1_000;
+    let mut timing = IoScanTiming::default();
+    timing.record(interval_us, 2, 0);
+    timing.record(interval_us + 1, 0, 1);
+    timing.record(100, 1, 3);
+
+    let status = timing.status();
+    assert_eq!(status.overruns, 1);
+    assert_eq!(status.failed_reads, 3);
+    assert_eq!(status.failed_writes, 4);
+}
+
+#[test]
+fn change_wakeups_are_counted() {
+    let mut timing = IoScanTiming::default();
+    timing.record(100, 0, 0);
+    timing.record_change_wakeup();
+    timing.record(100, 0, 0);
+
+    let status = timing.status();
+    assert_eq!(status.change_wakeups, 1);
+    assert_eq!(status.scans, 2);
+}
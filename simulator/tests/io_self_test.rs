@@ -0,0 +1,128 @@
+use firmware_logic::data::io_population::{ControllerProbe, IoPopulation, IoSelfTest};
+use firmware_logic::{
+    BoardMap, Command, Configuration, Controller, DeviceIdentifier, DigitalOutput, IoBank, IoLevel,
+    IoSupply, IoTemp, IoType, NackReason, SwitchCommand,
+};
+use simulator::dio::SimulatedIoBoard;
+use simulator::{Plant, Simulator};
+
+fn fitted() -> ControllerProbe {
+    ControllerProbe::Fitted {
+        supply: IoSupply::Volts24,
+        temp: IoTemp::Normal,
+    }
+}
+
+#[test]
+fn probe_maps_the_banks() {
+    let board = SimulatedIoBoard::new();
+    board.set_fitted(IoBank::Bank2, false);
+    board.corrupt_di_parity(IoBank::Bank4, true);
+    board.set_di_supply(IoBank::Bank5, false);
+    board.set_do_temp(IoBank::Bank6, IoTemp::Hot);
+    let mut dio = board.dio();
+    let _ = dio.init();
+
+    let population = dio.probe();
+    let bank = |bank: IoBank| population.banks[bank as usize];
+    assert_eq!(bank(IoBank::Bank1).outputs, fitted());
+    assert_eq!(
+        bank(IoBank::Bank1).inputs,
+        ControllerProbe::Fitted {
+            supply: IoSupply::Powered,
+            temp: IoTemp::Normal,
+        }
+    );
+    assert_eq!(bank(IoBank::Bank2).inputs, ControllerProbe::Missing);
+    assert_eq!(bank(IoBank::Bank2).outputs, ControllerProbe::Missing);
+    assert_eq!(bank(IoBank::Bank4).inputs, ControllerProbe::Faulty);
+    assert_eq!(bank(IoBank::Bank4).outputs, fitted());
+    assert_eq!(
+        bank(IoBank::Bank5).inputs,
+        ControllerProbe::Fitted {
+            supply: IoSupply::Unpowered,
+            temp: IoTemp::Normal,
+        }
+    );
+    assert_eq!(
+        bank(IoBank::Bank6).outputs,
+        ControllerProbe::Fitted {
+            supply: IoSupply::Volts24,
+            temp: IoTemp::Hot,
+        }
+    );
+}
+
+#[test]
+fn an_output_controller_that_lost_its_configuration_is_faulty() {
+    let board = SimulatedIoBoard::new();
+    let mut dio = board.dio();
+
+    // Still shut down with every port an input, as after a reset
+    let population = dio.probe();
+    assert_eq!(population.banks[0].outputs, ControllerProbe::Faulty);
+    assert!(!population.banks[0].outputs.is_usable());
+}
+
+#[test]
+fn controllers_with_devices_on_a_missing_bank_are_refused() {
+    // Outputs 16 to 23, engine starter 2 in the unit test board map
+    let mut population = IoPopulation::default();
+    population.banks[IoBank::Bank3 as usize].outputs = ControllerProbe::Missing;
+    let self_test = IoSelfTest::new(population, Configuration::UnitTest);
+    assert_eq!(self_test.refused.as_slice(), [Controller::EngineIgnition]);
+
+    // Inputs 0 to 7, the engines running inputs
+    let mut population = IoPopulation::default();
+    population.banks[IoBank::Bank1 as usize].inputs = ControllerProbe::Faulty;
+    assert!(!population.runs(Controller::EngineIgnition, &BoardMap::UNIT_TEST));
+    assert!(population.runs(Controller::Lights, &BoardMap::UNIT_TEST));
+    assert_eq!(
+        population.controller(IoType::DigitalIn, 7),
+        Some(ControllerProbe::Faulty)
+    );
+
+    // Nothing is refused without a board map
+    population.banks[IoBank::Bank1 as usize].outputs = ControllerProbe::Missing;
+    let self_test = IoSelfTest::new(population, Configuration::Unconfigured);
+    assert!(self_test.refused.is_empty());
+}
+
+#[test]
+fn a_refused_controller_does_not_run() {
+    let mut population = IoPopulation::default();
+    population.banks[IoBank::Bank1 as usize].outputs = ControllerProbe::Missing;
+    let mut simulator = Simulator::with_io_population(Plant::default(), population);
+
+    // Bilge pump 1 is on bank 1, the pumps are not started
+    let bilge_pump_2 = DigitalOutput::new(8);
+    assert_eq!(
+        simulator
+            .hardware_status()
+            .get_digital_output(&bilge_pump_2),
+        None
+    );
+    assert_eq!(
+        simulator.send(Command::BilgePump(DeviceIdentifier::All, SwitchCommand::On)),
+        Err(NackReason::BankMissing)
+    );
+    assert_eq!(
+        simulator.send(Command::NavigationLight(SwitchCommand::On)),
+        Err(NackReason::BankMissing)
+    );
+
+    // The engines are all on bank 2 and 3
+    simulator
+        .send(Command::EngineIgnition(
+            DeviceIdentifier::Device(1),
+            SwitchCommand::On,
+        ))
+        .unwrap();
+    simulator.run_for(Simulator::STEP);
+    assert_eq!(
+        simulator
+            .hardware_status()
+            .get_digital_output(&DigitalOutput::new(13)),
+        Some(IoLevel::High)
+    );
+}
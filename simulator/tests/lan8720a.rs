@@ -0,0 +1,198 @@
+use firmware_logic::data::link::{LinkEvent, LinkMode, LinkMonitor, LinkPhy, LinkSpeed};
+use firmware_logic::drivers::lan8720a::{
+    PhyDiagnostics, PhyError, PhyId, PhyInterrupts, LAN8720A, PHY_REG_ANTX, PHY_REG_BCR,
+    PHY_REG_BCR_AN, PHY_REG_BCR_LOOPBACK, PHY_REG_IMR,
+};
+use firmware_logic::Timestamp;
+use simulator::lan8720a::SimulatedPhy;
+
+const MAC_ADDRESS: [u8; 6] = [0x02, 0x00, 0x11, 0x22, 0x33, 0x44];
+
+const MODES: [LinkMode; 4] = [
+    LinkMode {
+        speed: LinkSpeed::Mbps100,
+        full_duplex: true,
+    },
+    LinkMode {
+        speed: LinkSpeed::Mbps100,
+        full_duplex: false,
+    },
+    LinkMode {
+        speed: LinkSpeed::Mbps10,
+        full_duplex: true,
+    },
+    LinkMode {
+        speed: LinkSpeed::Mbps10,
+        full_duplex: false,
+    },
+];
+
+/// The driver on a freshly reset and initialized chip, with a handle to the chip.
+fn phy() -> (LAN8720A<SimulatedPhy>, SimulatedPhy) {
+    let chip = SimulatedPhy::new();
+    let mut phy = LAN8720A::new(chip.clone());
+    phy.phy_reset();
+    phy.phy_init();
+    (phy, chip)
+}
+
+#[test]
+fn the_chip_is_identified() {
+    let (mut phy, _chip) = phy();
+    let id = phy.identify().unwrap();
+    assert!(id.is_lan8720a());
+    assert_eq!(id.revision(), 1);
+
+    // Nothing answers on an unpowered bus
+    let unknown = PhyId {
+        id1: 0xFFFF,
+        id2: 0xFFFF,
+    };
+    let mut phy = LAN8720A::new(SimulatedPhy::with_id(unknown));
+    assert_eq!(phy.identify(), Err(PhyError::UnknownId(unknown)));
+}
+
+#[test]
+fn every_mode_is_advertised_and_resolved_from_the_special_status() {
+    let (mut phy, chip) = phy();
+    assert_eq!(chip.register(PHY_REG_ANTX), 0x01E1);
+    assert_eq!(phy.link_mode(), None);
+
+    for mode in MODES {
+        chip.connect(Some(mode));
+        assert!(phy.poll_link());
+        assert_eq!(phy.link_mode(), Some(mode));
+    }
+}
+
+#[test]
+fn a_link_dropped_between_polls_is_reported_up_once_back() {
+    let (mut phy, chip) = phy();
+    chip.connect(Some(MODES[0]));
+    assert_eq!(phy.link_mode(), Some(MODES[0]));
+
+    // The status register latched the drop, the driver reads it through
+    chip.connect(None);
+    chip.connect(Some(MODES[2]));
+    assert_eq!(phy.link_mode(), Some(MODES[2]));
+
+    chip.connect(None);
+    assert!(!phy.poll_link());
+    assert_eq!(phy.link_mode(), None);
+}
+
+#[test]
+fn symbol_errors_and_interrupt_sources_are_accumulated() {
+    let (mut phy, chip) = phy();
+    let mut diagnostics = PhyDiagnostics::default();
+    chip.connect(Some(MODES[0]));
+    phy.update_diagnostics(&mut diagnostics);
+    assert_eq!(diagnostics, PhyDiagnostics::default());
+
+    chip.add_symbol_errors(65_530);
+    chip.connect(None);
+    chip.raise(PhyInterrupts::REMOTE_FAULT | PhyInterrupts::PARALLEL_DETECTION_FAULT);
+    phy.update_diagnostics(&mut diagnostics);
+    assert_eq!(diagnostics.symbol_errors, 65_530);
+    assert_eq!(diagnostics.link_downs, 1);
+    assert_eq!(diagnostics.remote_faults, 1);
+    assert_eq!(diagnostics.parallel_detection_faults, 1);
+
+    // The counter wraps, the sources were cleared by the read
+    chip.add_symbol_errors(10);
+    phy.update_diagnostics(&mut diagnostics);
+    assert_eq!(diagnostics.symbol_errors, 65_540);
+    assert_eq!(diagnostics.link_downs, 1);
+    assert_eq!(diagnostics.remote_faults, 1);
+}
+
+#[test]
+fn interrupt_sources_are_latched_until_taken() {
+    let (mut phy, chip) = phy();
+    phy.set_interrupt_mask(PhyInterrupts::LINK_DOWN | PhyInterrupts::AUTONEGOTIATION_COMPLETE);
+    assert_eq!(chip.register(PHY_REG_IMR), (1 << 4) | (1 << 6));
+
+    phy.take_interrupts();
+    chip.connect(Some(MODES[0]));
+    chip.connect(None);
+    let interrupts = phy.take_interrupts();
+    assert!(interrupts.contains(PhyInterrupts::AUTONEGOTIATION_COMPLETE));
+    assert!(interrupts.contains(PhyInterrupts::LINK_DOWN));
+    assert!(!interrupts.contains(PhyInterrupts::REMOTE_FAULT));
+    assert_eq!(phy.take_interrupts(), PhyInterrupts::default());
+}
+
+#[test]
+fn the_loopback_self_test_checks_the_frame_and_restores_autonegotiation() {
+    let chip = SimulatedPhy::new();
+    chip.connect(Some(MODES[0]));
+    let mut phy = LAN8720A::new(chip.clone());
+    phy.phy_reset();
+
+    let mut looped_back = false;
+    let result = phy.loopback_self_test(MAC_ADDRESS, |frame, received| {
+        looped_back = chip.register(PHY_REG_BCR) & PHY_REG_BCR_LOOPBACK != 0;
+        assert_eq!(frame[..6], MAC_ADDRESS);
+        assert_eq!(frame[6..12], MAC_ADDRESS);
+        chip.exchange(frame, received)
+    });
+    assert_eq!(result, Ok(()));
+    assert!(looped_back);
+    let bcr = chip.register(PHY_REG_BCR);
+    assert_eq!(bcr & PHY_REG_BCR_LOOPBACK, 0);
+    assert_ne!(bcr & PHY_REG_BCR_AN, 0);
+    phy.phy_init();
+    assert_eq!(phy.link_mode(), Some(MODES[0]));
+
+    // Frames sent without loopback do not come back
+    let mut received = [0; 68];
+    assert_eq!(chip.exchange(&[0; 64], &mut received), None);
+}
+
+#[test]
+fn a_broken_loopback_fails_the_self_test() {
+    let chip = SimulatedPhy::new();
+    let mut phy = LAN8720A::new(chip.clone());
+    phy.phy_reset();
+    assert_eq!(
+        phy.loopback_self_test(MAC_ADDRESS, |_, _| None),
+        Err(PhyError::LoopbackLost)
+    );
+
+    chip.corrupt_loopback(true);
+    assert_eq!(
+        phy.loopback_self_test(MAC_ADDRESS, |frame, received| chip
+            .exchange(frame, received)),
+        Err(PhyError::LoopbackCorrupted)
+    );
+    assert_eq!(
+        phy.loopback_self_test(MAC_ADDRESS, |_, _| Some(10)),
+        Err(PhyError::LoopbackCorrupted)
+    );
+}
+
+#[test]
+fn the_link_monitor_follows_the_chip() {
+    let (mut phy, chip) = phy();
+    let mut monitor = LinkMonitor::default();
+    let second = |seconds: u64| Timestamp::new(seconds * 1_000);
+
+    assert_eq!(monitor.poll(second(0), &mut phy), None);
+    chip.connect(Some(MODES[3]));
+    assert_eq!(
+        monitor.poll(second(1), &mut phy),
+        Some(LinkEvent::Up(MODES[3]))
+    );
+    chip.connect(None);
+    assert_eq!(monitor.poll(second(2), &mut phy), Some(LinkEvent::Down));
+
+    // Renegotiating keeps autonegotiation enabled on the chip
+    monitor.poll(second(6), &mut phy);
+    assert_eq!(monitor.status().renegotiations, 1);
+    assert_ne!(chip.register(PHY_REG_BCR) & PHY_REG_BCR_AN, 0);
+    chip.connect(Some(MODES[0]));
+    assert_eq!(
+        monitor.poll(second(7), &mut phy),
+        Some(LinkEvent::Up(MODES[0]))
+    );
+}
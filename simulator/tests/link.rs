@@ -0,0 +1,137 @@
+mod common;
+
+use common::ms;
+use firmware_logic::data::link::{
+    LinkEvent, LinkMode, LinkMonitor, LinkPhy, LinkSpeed, LINK_POLL_INTERVAL, RENEGOTIATE_AFTER,
+};
+
+const FAST_FULL: LinkMode = LinkMode {
+    speed: LinkSpeed::Mbps100,
+    full_duplex: true,
+};
+const SLOW_HALF: LinkMode = LinkMode {
+    speed: LinkSpeed::Mbps10,
+    full_duplex: false,
+};
+
+#[derive(Default)]
+struct FakePhy {
+    mode: Option<LinkMode>,
+    reads: u32,
+    renegotiations: u32,
+}
+
+impl LinkPhy for FakePhy {
+    fn link_mode(&mut self) -> Option<LinkMode> {
+        self.reads += 1;
+        self.mode
+    }
+
+    fn restart_autonegotiation(&mut self) {
+        self.renegotiations += 1;
+    }
+}
+
+#[test]
+fn the_phy_is_polled_at_the_interval() {
+    let mut monitor = LinkMonitor::default();
+    let mut phy = FakePhy::default();
+    monitor.poll(ms(0), &mut phy);
+    monitor.poll(ms(1), &mut phy);
+    monitor.poll(ms(LINK_POLL_INTERVAL.as_millis() - 1), &mut phy);
+    assert_eq!(phy.reads, 1);
+    monitor.poll(LINK_POLL_INTERVAL, &mut phy);
+    assert_eq!(phy.reads, 2);
+}
+
+#[test]
+fn the_link_comes_up_in_any_mode() {
+    for mode in [
+        FAST_FULL,
+        SLOW_HALF,
+        LinkMode {
+            speed: LinkSpeed::Mbps100,
+            full_duplex: false,
+        },
+        LinkMode {
+            speed: LinkSpeed::Mbps10,
+            full_duplex: true,
+        },
+    ] {
+        let mut monitor = LinkMonitor::default();
+        let mut phy = FakePhy {
+            mode: Some(mode),
+            ..Default::default()
+        };
+        assert_eq!(monitor.poll(ms(0), &mut phy), Some(LinkEvent::Up(mode)));
+        assert_eq!(monitor.status().mode, Some(mode));
+        assert_eq!(monitor.poll(ms(1_000), &mut phy), None);
+    }
+}
+
+#[test]
+fn a_pulled_cable_is_detected_and_the_new_mode_reported() {
+    let mut monitor = LinkMonitor::default();
+    let mut phy = FakePhy {
+        mode: Some(FAST_FULL),
+        ..Default::default()
+    };
+    monitor.poll(ms(0), &mut phy);
+
+    phy.mode = None;
+    assert_eq!(monitor.poll(ms(1_000), &mut phy), Some(LinkEvent::Down));
+    assert_eq!(monitor.status().mode, None);
+    assert_eq!(monitor.status().drops, 1);
+    assert_eq!(monitor.poll(ms(1_500), &mut phy), None);
+
+    // Plugged into a slower switch
+    phy.mode = Some(SLOW_HALF);
+    assert_eq!(
+        monitor.poll(ms(2_000), &mut phy),
+        Some(LinkEvent::Up(SLOW_HALF))
+    );
+    assert_eq!(monitor.status().drops, 1);
+    assert_eq!(phy.renegotiations, 0);
+
+    // Renegotiated by the partner without dropping
+    phy.mode = Some(FAST_FULL);
+    assert_eq!(
+        monitor.poll(ms(3_000), &mut phy),
+        Some(LinkEvent::Up(FAST_FULL))
+    );
+}
+
+#[test]
+fn autonegotiation_is_restarted_while_the_link_is_down() {
+    let mut monitor = LinkMonitor::default();
+    let mut phy = FakePhy::default();
+    let mut now = 0;
+    while now < RENEGOTIATE_AFTER.as_millis() {
+        assert_eq!(monitor.poll(ms(now), &mut phy), None);
+        now += LINK_POLL_INTERVAL.as_millis();
+    }
+    assert_eq!(phy.renegotiations, 0);
+    monitor.poll(ms(now), &mut phy);
+    assert_eq!(phy.renegotiations, 1);
+    assert_eq!(monitor.status().renegotiations, 1);
+
+    // Then again at the same interval
+    monitor.poll(
+        ms(now + RENEGOTIATE_AFTER.as_millis() - LINK_POLL_INTERVAL.as_millis()),
+        &mut phy,
+    );
+    assert_eq!(phy.renegotiations, 1);
+    monitor.poll(ms(now + RENEGOTIATE_AFTER.as_millis()), &mut phy);
+    assert_eq!(phy.renegotiations, 2);
+
+    // Not once the link is up
+    phy.mode = Some(FAST_FULL);
+    let up = now + RENEGOTIATE_AFTER.as_millis() + LINK_POLL_INTERVAL.as_millis();
+    assert_eq!(
+        monitor.poll(ms(up), &mut phy),
+        Some(LinkEvent::Up(FAST_FULL))
+    );
+    monitor.poll(ms(up + 10 * RENEGOTIATE_AFTER.as_millis()), &mut phy);
+    assert_eq!(phy.renegotiations, 2);
+    assert_eq!(monitor.status().drops, 0);
+}
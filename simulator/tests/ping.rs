@@ -0,0 +1,149 @@
+mod common;
+
+use common::ms;
+use firmware_logic::data::ping::{PingStatistics, ServerProbe, PING_WINDOW};
+use firmware_logic::Timestamp;
+
+const INTERVAL: Timestamp = Timestamp::new(1_000);
+const TIMEOUT: Timestamp = Timestamp::new(2_000);
+
+/// Pings every `INTERVAL` for `count` requests, answering each after `round_trip` if any.
+fn ping(probe: &mut ServerProbe, start: u64, count: u64, round_trip: Option<u64>) -> u64 {
+    let mut now = start;
+    for _ in 0..count {
+        let sequence = probe.poll(ms(now)).expect("a request is due");
+        if let Some(round_trip) = round_trip {
+            probe.reply(sequence, ms(now + round_trip));
+        }
+        now += INTERVAL.as_millis();
+    }
+    now
+}
+
+#[test]
+fn requests_are_sent_at_the_interval() {
+    let mut probe = ServerProbe::new(INTERVAL, TIMEOUT);
+    assert_eq!(probe.poll(ms(0)), Some(0));
+    assert_eq!(probe.poll(ms(1)), None);
+    assert_eq!(probe.poll(ms(999)), None);
+    assert_eq!(probe.poll(ms(1_000)), Some(1));
+
+    // Nothing measured until a request was answered or timed out
+    assert_eq!(probe.statistics(), PingStatistics::default());
+    assert_eq!(probe.qos(), 0);
+
+    assert_eq!(probe.poll(ms(2_500)), Some(2));
+    assert_eq!(probe.statistics().samples, 1);
+}
+
+#[test]
+fn answered_requests_measure_the_round_trip() {
+    let mut probe = ServerProbe::new(INTERVAL, TIMEOUT);
+    ping(&mut probe, 0, 4, Some(10));
+    assert_eq!(
+        probe.statistics(),
+        PingStatistics {
+            round_trip_ms: Some(10),
+            loss_percent: 0,
+            samples: 4,
+        }
+    );
+    assert_eq!(probe.qos(), 100);
+}
+
+#[test]
+fn unanswered_requests_are_lost_after_the_timeout() {
+    let mut probe = ServerProbe::new(INTERVAL, TIMEOUT);
+    let now = ping(&mut probe, 0, 2, Some(10));
+    let now = ping(&mut probe, now, 2, None);
+    // The request sent at 3_000 times out at 5_000
+    assert_eq!(probe.poll(ms(now)), Some(4));
+    assert_eq!(probe.statistics().samples, 3);
+    probe.reply(4, ms(now + 10));
+    assert_eq!(probe.poll(ms(now + 1_000)), Some(5));
+    assert_eq!(
+        probe.statistics(),
+        PingStatistics {
+            round_trip_ms: Some(10),
+            loss_percent: 40,
+            samples: 5,
+        }
+    );
+    assert_eq!(probe.qos(), 60);
+
+    // Nothing answered within the window: unreachable
+    let mut probe = ServerProbe::new(INTERVAL, TIMEOUT);
+    let now = ping(&mut probe, 0, PING_WINDOW as u64 + 2, None);
+    probe.poll(ms(now + TIMEOUT.as_millis()));
+    assert_eq!(probe.statistics().loss_percent, 100);
+    assert_eq!(probe.statistics().samples, PING_WINDOW as u8);
+    assert_eq!(probe.qos(), 0);
+}
+
+#[test]
+fn late_duplicate_or_unknown_replies_are_ignored() {
+    let mut probe = ServerProbe::new(INTERVAL, TIMEOUT);
+    let sequence = probe.poll(ms(0)).unwrap();
+    probe.poll(ms(2_000));
+    probe.reply(sequence, ms(2_001));
+    probe.reply(1_234, ms(2_002));
+    assert_eq!(
+        probe.statistics(),
+        PingStatistics {
+            round_trip_ms: None,
+            loss_percent: 100,
+            samples: 1,
+        }
+    );
+
+    probe.reply(1, ms(2_020));
+    probe.reply(1, ms(2_030));
+    assert_eq!(probe.statistics().samples, 2);
+    assert_eq!(probe.statistics().round_trip_ms, Some(20));
+}
+
+#[test]
+fn a_reply_overtaking_earlier_requests_counts_them_as_lost() {
+    let mut probe = ServerProbe::new(INTERVAL, Timestamp::new(5_000));
+    probe.poll(ms(0));
+    probe.poll(ms(1_000));
+    let latest = probe.poll(ms(2_000)).unwrap();
+    probe.reply(latest, ms(2_050));
+    assert_eq!(
+        probe.statistics(),
+        PingStatistics {
+            round_trip_ms: Some(50),
+            loss_percent: 66,
+            samples: 3,
+        }
+    );
+}
+
+#[test]
+fn slow_round_trips_lower_the_qos() {
+    let qos = |round_trip| {
+        let mut probe = ServerProbe::new(INTERVAL, TIMEOUT);
+        ping(&mut probe, 0, 8, Some(round_trip));
+        probe.qos()
+    };
+    assert_eq!(qos(1), 100);
+    assert_eq!(qos(20), 100);
+    assert_eq!(qos(260), 50);
+    assert_eq!(qos(500), 0);
+    assert_eq!(qos(1_500), 0);
+    assert!(qos(100) > qos(200));
+}
+
+#[test]
+fn reset_forgets_the_requests() {
+    let mut probe = ServerProbe::new(INTERVAL, TIMEOUT);
+    let now = ping(&mut probe, 0, 3, Some(10));
+    let pending = probe.poll(ms(now)).unwrap();
+    probe.reset();
+    assert_eq!(probe.statistics(), PingStatistics::default());
+    probe.reply(pending, ms(now + 10));
+    assert_eq!(probe.statistics(), PingStatistics::default());
+
+    // The next request goes out right away
+    assert_eq!(probe.poll(ms(now + 1)), Some(pending + 1));
+}
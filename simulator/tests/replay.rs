@@ -0,0 +1,63 @@
+use firmware_logic::{ButtonCommand, Command, DeviceIdentifier, IoLevel, SwitchCommand, Timestamp};
+use simulator::{
+    replay::{read_recording, replay, write_recording},
+    Simulator,
+};
+
+const STBD: DeviceIdentifier = DeviceIdentifier::Device(2);
+
+fn record_session() -> Vec<firmware_logic::data::recording::RecordedCycle> {
+    let mut simulator = Simulator::default();
+    simulator.run_for(Timestamp::new(100));
+    simulator.start_recording();
+
+    simulator
+        .send(Command::AnchorDown(SwitchCommand::On))
+        .unwrap();
+    simulator.run_for(Timestamp::new(2_000));
+    simulator
+        .send(Command::AnchorDown(SwitchCommand::Off))
+        .unwrap();
+    simulator
+        .send(Command::EngineIgnition(STBD, SwitchCommand::On))
+        .unwrap();
+    simulator.run_for(Simulator::STEP);
+    simulator
+        .hold(
+            Command::EngineStart(STBD, ButtonCommand::On),
+            Timestamp::new(2_000),
+        )
+        .unwrap();
+    simulator.plant_mut().bilge_pumps[0].seized = true;
+    simulator.run_for(Timestamp::new(1_000));
+
+    simulator.stop_recording()
+}
+
+#[test]
+fn replayed_recording_matches() {
+    let recording = record_session();
+    assert!(recording.len() > 1_000);
+
+    let mut file = Vec::new();
+    write_recording(&mut file, &recording).unwrap();
+    let read_back = read_recording(file.as_slice()).unwrap();
+    assert_eq!(read_back, recording);
+
+    assert_eq!(replay(read_back), []);
+}
+
+#[test]
+fn replay_reports_diverging_outputs() {
+    let mut recording = record_session();
+    let tampered = &mut recording[200];
+    let anchor_down = 1;
+    assert_eq!(tampered.output_level(anchor_down), Some(IoLevel::High));
+    tampered.digital_outputs[anchor_down] = Default::default();
+
+    let mismatches = replay(recording.clone());
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].step, recording[200].step);
+    assert_eq!(mismatches[0].address, anchor_down);
+    assert_eq!(mismatches[0].replayed, Some(IoLevel::High));
+}
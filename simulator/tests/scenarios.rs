@@ -0,0 +1,138 @@
+use firmware_logic::{
+    ButtonCommand, Command, DeviceIdentifier, NackReason, SwitchCommand, Timestamp,
+    ENGINE_MAX_CRANK,
+};
+use simulator::{EngineState, Simulator};
+
+const PORT: DeviceIdentifier = DeviceIdentifier::Device(1);
+
+#[test]
+fn bilge_pumps_keep_the_bilge_dry() {
+    let mut simulator = Simulator::default();
+    simulator.plant_mut().bilge.inflow = 2.0;
+
+    simulator.run_for(Timestamp::new(10_000));
+    assert_eq!(simulator.plant().bilge.level, 0.0);
+
+    simulator
+        .send(Command::BilgePump(
+            DeviceIdentifier::All,
+            SwitchCommand::Off,
+        ))
+        .unwrap();
+    simulator.run_for(Timestamp::new(10_000));
+    assert!(simulator.plant().bilge.level > 19.0);
+}
+
+#[test]
+fn seized_bilge_pump_does_not_report_running() {
+    let mut simulator = Simulator::default();
+    simulator.plant_mut().bilge_pumps[1].seized = true;
+    simulator.run_for(Timestamp::new(100));
+
+    let running: Vec<bool> = simulator
+        .plant()
+        .bilge_pumps
+        .iter()
+        .map(|pump| pump.is_running())
+        .collect();
+    assert_eq!(running, [true, false, true]);
+}
+
+#[test]
+fn windlass_pays_out_chain_and_is_interlocked() {
+    let mut simulator = Simulator::default();
+
+    simulator
+        .send(Command::AnchorDown(SwitchCommand::On))
+        .unwrap();
+    simulator.run_for(Timestamp::new(10_000));
+    let chain_out = simulator.plant().windlass.chain_out;
+    assert!((2.9..=3.1).contains(&chain_out));
+
+    assert_eq!(
+        simulator.send(Command::AnchorUp(SwitchCommand::On)),
+        Err(NackReason::Interlocked)
+    );
+    simulator
+        .send(Command::AnchorDown(SwitchCommand::Off))
+        .unwrap();
+    simulator.run_for(Simulator::STEP);
+    simulator
+        .send(Command::AnchorUp(SwitchCommand::On))
+        .unwrap();
+    simulator.run_for(Timestamp::new(20_000));
+
+    assert_eq!(simulator.plant().windlass.chain_out, 0.0);
+    assert_eq!(simulator.plant().windlass.both_driven, Timestamp::default());
+}
+
+#[test]
+fn engine_starts_after_cranking() {
+    let mut simulator = Simulator::default();
+
+    assert_eq!(
+        simulator.hold(
+            Command::EngineStart(PORT, ButtonCommand::On),
+            Timestamp::new(500)
+        ),
+        Err(NackReason::Interlocked)
+    );
+    assert_eq!(simulator.plant().engines[0].state, EngineState::Stopped);
+
+    simulator
+        .send(Command::EngineIgnition(PORT, SwitchCommand::On))
+        .unwrap();
+    simulator.run_for(Simulator::STEP);
+    simulator
+        .hold(
+            Command::EngineStart(PORT, ButtonCommand::On),
+            Timestamp::new(3_000),
+        )
+        .unwrap();
+
+    let engine = &simulator.plant().engines[0];
+    assert_eq!(engine.state, EngineState::Running);
+    assert!(engine.longest_crank < Timestamp::new(1_600));
+    assert_eq!(simulator.plant().engines[1].state, EngineState::Stopped);
+}
+
+#[test]
+fn starter_stops_at_crank_limit() {
+    let mut simulator = Simulator::default();
+    simulator.plant_mut().engines[0].fails_to_start = true;
+
+    simulator
+        .send(Command::EngineIgnition(PORT, SwitchCommand::On))
+        .unwrap();
+    simulator.run_for(Simulator::STEP);
+    simulator
+        .hold(
+            Command::EngineStart(PORT, ButtonCommand::On),
+            Timestamp::new(8_000),
+        )
+        .unwrap();
+
+    let engine = &simulator.plant().engines[0];
+    assert_eq!(engine.state, EngineState::Stopped);
+    assert!(engine.longest_crank <= ENGINE_MAX_CRANK);
+}
+
+#[test]
+fn black_water_pump_only_runs_while_held() {
+    let mut simulator = Simulator::default();
+
+    simulator
+        .hold(
+            Command::BlackWaterPump(ButtonCommand::On),
+            Timestamp::new(10_000),
+        )
+        .unwrap();
+    let level = simulator.plant().black_water.level;
+    assert!((34.9..=35.1).contains(&level));
+
+    let stopped = simulator.run_until(Timestamp::new(1_000), |simulator| {
+        !simulator.plant().black_water_pump.is_running()
+    });
+    assert!(stopped);
+}
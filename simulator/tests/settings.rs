@@ -0,0 +1,131 @@
+use embedded_storage::nor_flash::NorFlash;
+use firmware_logic::{
+    data::settings::{derive_mac_address, NetworkSettings, Settings, SettingsStore},
+    Configuration,
+};
+use simulator::flash::MemoryFlash;
+
+const SECOND_COPY: u32 = MemoryFlash::ERASE_SIZE as u32;
+
+fn settings_store() -> SettingsStore {
+    SettingsStore::new(0, SECOND_COPY)
+}
+
+fn settings(card_id: u32) -> Settings {
+    Settings {
+        configuration: Configuration::Spirit103,
+        card_id,
+        ..Default::default()
+    }
+}
+
+/// Loads from a fresh store, as on boot.
+fn load(flash: &mut MemoryFlash) -> Option<Settings> {
+    settings_store().load(flash).unwrap()
+}
+
+#[test]
+fn blank_flash_has_no_settings() {
+    let mut flash = MemoryFlash::new(2);
+    assert_eq!(load(&mut flash), None);
+}
+
+#[test]
+fn saved_settings_survive_a_reboot() {
+    let mut flash = MemoryFlash::new(2);
+    let mut store = settings_store();
+    assert_eq!(store.load(&mut flash).unwrap(), None);
+    store.save(&mut flash, &settings(7)).unwrap();
+
+    assert_eq!(load(&mut flash), Some(settings(7)));
+}
+
+#[test]
+fn saves_alternate_between_copies_and_the_latest_wins() {
+    let mut flash = MemoryFlash::new(2);
+    let mut store = settings_store();
+    store.load(&mut flash).unwrap();
+    for card_id in 1..=5 {
+        store.save(&mut flash, &settings(card_id)).unwrap();
+        assert_eq!(load(&mut flash), Some(settings(card_id)));
+    }
+
+    // Both copies hold a record
+    let second_copy = &flash.bytes()[SECOND_COPY as usize..];
+    assert_ne!(flash.bytes()[0], 0xFF);
+    assert_ne!(second_copy[0], 0xFF);
+}
+
+#[test]
+fn a_corrupted_copy_falls_back_to_the_other() {
+    let mut flash = MemoryFlash::new(2);
+    let mut store = settings_store();
+    store.load(&mut flash).unwrap();
+    store.save(&mut flash, &settings(1)).unwrap();
+    store.save(&mut flash, &settings(2)).unwrap();
+
+    // The latest copy is the second one, flip a bit in its payload
+    flash.bytes_mut()[SECOND_COPY as usize + 14] ^= 0x01;
+    assert_eq!(load(&mut flash), Some(settings(1)));
+
+    flash.bytes_mut()[14] ^= 0x01;
+    assert_eq!(load(&mut flash), None);
+}
+
+#[test]
+fn losing_power_while_saving_keeps_the_previous_settings() {
+    for operations in 0..2 {
+        let mut flash = MemoryFlash::new(2);
+        let mut store = settings_store();
+        store.load(&mut flash).unwrap();
+        store.save(&mut flash, &settings(1)).unwrap();
+        store.save(&mut flash, &settings(2)).unwrap();
+
+        flash.cut_power_after(operations);
+        assert!(store.save(&mut flash, &settings(3)).is_err());
+        flash.restore_power();
+
+        assert_eq!(
+            load(&mut flash),
+            Some(settings(2)),
+            "power cut after {operations}"
+        );
+
+        // The next save still works, whichever copy got damaged
+        let mut rebooted = settings_store();
+        rebooted.load(&mut flash).unwrap();
+        rebooted.save(&mut flash, &settings(4)).unwrap();
+        assert_eq!(load(&mut flash), Some(settings(4)));
+    }
+}
+
+#[test]
+fn mac_addresses_are_unique_locally_administered_and_stable() {
+    let unique_id = *b"\x2a\x00\x3c\x00\x0b\x51\x33\x31\x34\x36\x37\x39";
+    let mac_address = derive_mac_address(&unique_id);
+    assert_eq!(mac_address[0] & 0b11, 0b10, "locally administered unicast");
+    assert_eq!(derive_mac_address(&unique_id), mac_address);
+
+    // Cards of a batch differ in a few bits only
+    for byte in 0..12 {
+        let mut neighbour = unique_id;
+        neighbour[byte] ^= 0x01;
+        assert_ne!(derive_mac_address(&neighbour), mac_address);
+    }
+}
+
+#[test]
+fn mac_address_override_wins() {
+    let unique_id = [7; 12];
+    let mut network = NetworkSettings::default();
+    assert_eq!(
+        network.mac_address(&unique_id),
+        derive_mac_address(&unique_id)
+    );
+
+    network.mac_address = Some([0x02, 0x00, 0x11, 0x22, 0x33, 0x44]);
+    assert_eq!(
+        network.mac_address(&unique_id),
+        [0x02, 0x00, 0x11, 0x22, 0x33, 0x44]
+    );
+}
@@ -0,0 +1,410 @@
+use embedded_storage::nor_flash::NorFlash;
+use firmware_logic::data::{
+    boot::{BootRecord, BootRecords, Slot},
+    image::{ImageError, ImageHeader, IMAGE_HEADER_SIZE},
+    settings::Settings,
+    update::{image_crc, UpdateError, UpdateRequest, UpdateResponse, Updater, UPDATE_CHUNK_SIZE},
+};
+use firmware_logic::Configuration;
+use simulator::{flash::MemoryFlash, image::ImageSigner};
+
+const SECTOR: u32 = MemoryFlash::ERASE_SIZE as u32;
+/// The slot written by the updater follows a sector left untouched.
+const SLOT_OFFSET: u32 = SECTOR;
+const SLOT_SIZE: u32 = 4 * SECTOR;
+
+fn binary(length: usize) -> Vec<u8> {
+    (0..length).map(|i| (i * 7 + i / 251) as u8).collect()
+}
+
+/// A binary of `length` bytes signed for the unit test configuration, header included.
+fn image(length: usize) -> Vec<u8> {
+    let binary = binary(length - IMAGE_HEADER_SIZE);
+    let header = ImageSigner::header(&[Configuration::UnitTest], &binary);
+    ImageSigner::new(1).sign(&header, &binary)
+}
+
+fn updater() -> Updater {
+    Updater::new(
+        SLOT_OFFSET,
+        SLOT_SIZE,
+        Configuration::UnitTest,
+        ImageSigner::new(1).public_key(),
+    )
+}
+
+fn begin(image: &[u8]) -> UpdateRequest {
+    UpdateRequest::Begin {
+        length: image.len() as u32,
+        crc: image_crc(image),
+    }
+}
+
+fn chunks(image: &[u8]) -> impl Iterator<Item = UpdateRequest> + '_ {
+    image
+        .chunks(UPDATE_CHUNK_SIZE)
+        .enumerate()
+        .map(|(index, data)| UpdateRequest::Chunk {
+            offset: (index * UPDATE_CHUNK_SIZE) as u32,
+            crc: image_crc(data),
+            data: data.try_into().unwrap(),
+        })
+}
+
+/// Flash holding a previous image in the slot, which the update must erase.
+fn flash_with_previous_image() -> MemoryFlash {
+    let mut flash = MemoryFlash::new(5);
+    flash.bytes_mut()[SLOT_OFFSET as usize..].fill(0x5A);
+    flash
+}
+
+fn upload(updater: &mut Updater, flash: &mut MemoryFlash, image: &[u8]) -> UpdateResponse {
+    assert_eq!(updater.handle(flash, &begin(image)), UpdateResponse::Ready);
+    for chunk in chunks(image) {
+        let response = updater.handle(flash, &chunk);
+        assert!(
+            matches!(response, UpdateResponse::ChunkWritten { .. }),
+            "{response:?}"
+        );
+    }
+    updater.handle(flash, &UpdateRequest::Finish)
+}
+
+#[test]
+fn an_image_is_written_to_the_slot_and_verified() {
+    // Not a whole number of chunks nor of flash writes, and spanning several sectors
+    let image = image(3 * UPDATE_CHUNK_SIZE * 4 + 101);
+    let mut flash = flash_with_previous_image();
+    let mut updater = updater();
+
+    let response = upload(&mut updater, &mut flash, &image);
+    let binary = &image[IMAGE_HEADER_SIZE..];
+    assert_eq!(
+        response,
+        UpdateResponse::Verified(ImageSigner::header(&[Configuration::UnitTest], binary))
+    );
+    assert!(!updater.is_updating());
+
+    let slot = &flash.bytes()[SLOT_OFFSET as usize..];
+    assert_eq!(&slot[..image.len()], &image[..]);
+    assert!(flash.bytes()[..SLOT_OFFSET as usize]
+        .iter()
+        .all(|&byte| byte == 0xFF));
+}
+
+#[test]
+fn a_corrupted_chunk_is_refused_and_may_be_sent_again() {
+    let image = image(2 * UPDATE_CHUNK_SIZE);
+    let mut flash = MemoryFlash::new(5);
+    let mut updater = updater();
+    updater.handle(&mut flash, &begin(&image));
+
+    let mut chunks = chunks(&image);
+    let first = chunks.next().unwrap();
+    let UpdateRequest::Chunk {
+        offset,
+        crc,
+        mut data,
+    } = first.clone()
+    else {
+        unreachable!()
+    };
+    data[10] ^= 0x01;
+    let corrupted = UpdateRequest::Chunk { offset, crc, data };
+    assert_eq!(
+        updater.handle(&mut flash, &corrupted),
+        UpdateResponse::Failed(UpdateError::ChunkCrc)
+    );
+
+    assert_eq!(
+        updater.handle(&mut flash, &first),
+        UpdateResponse::ChunkWritten {
+            next_offset: UPDATE_CHUNK_SIZE as u32
+        }
+    );
+    updater.handle(&mut flash, &chunks.next().unwrap());
+    assert!(matches!(
+        updater.handle(&mut flash, &UpdateRequest::Finish),
+        UpdateResponse::Verified(_)
+    ));
+}
+
+#[test]
+fn chunks_must_be_sent_in_order_and_complete() {
+    let image = image(3 * UPDATE_CHUNK_SIZE);
+    let mut flash = MemoryFlash::new(5);
+    let mut updater = updater();
+    updater.handle(&mut flash, &begin(&image));
+    let chunks: Vec<_> = chunks(&image).collect();
+
+    assert_eq!(
+        updater.handle(&mut flash, &chunks[1]),
+        UpdateResponse::Failed(UpdateError::OutOfOrder { expected: 0 })
+    );
+    updater.handle(&mut flash, &chunks[0]);
+    assert_eq!(
+        updater.handle(&mut flash, &UpdateRequest::Finish),
+        UpdateResponse::Failed(UpdateError::Incomplete {
+            expected: UPDATE_CHUNK_SIZE as u32
+        })
+    );
+
+    // The update resumes where it stopped
+    updater.handle(&mut flash, &chunks[1]);
+    updater.handle(&mut flash, &chunks[2]);
+    assert!(matches!(
+        updater.handle(&mut flash, &UpdateRequest::Finish),
+        UpdateResponse::Verified(_)
+    ));
+}
+
+#[test]
+fn an_image_not_matching_its_crc_is_not_verified() {
+    let image = image(2 * UPDATE_CHUNK_SIZE);
+    let mut flash = MemoryFlash::new(5);
+    let mut updater = updater();
+    let begin = UpdateRequest::Begin {
+        length: image.len() as u32,
+        crc: image_crc(&image) ^ 1,
+    };
+    updater.handle(&mut flash, &begin);
+    for chunk in chunks(&image) {
+        updater.handle(&mut flash, &chunk);
+    }
+
+    assert_eq!(
+        updater.handle(&mut flash, &UpdateRequest::Finish),
+        UpdateResponse::Failed(UpdateError::ImageCrc)
+    );
+    // The update must be begun again
+    assert_eq!(
+        updater.handle(&mut flash, &UpdateRequest::Finish),
+        UpdateResponse::Failed(UpdateError::NotStarted)
+    );
+}
+
+#[test]
+fn saving_settings_does_not_drop_an_update_in_progress() {
+    let image = image(2 * UPDATE_CHUNK_SIZE + 100);
+    let mut flash = flash_with_previous_image();
+    let mut updater = updater();
+    let mut chunks = chunks(&image);
+    updater.handle(&mut flash, &begin(&image));
+    updater.handle(&mut flash, &chunks.next().unwrap());
+
+    // Saved by the owner of the settings store, not by the updater
+    assert_eq!(
+        updater.handle(
+            &mut flash,
+            &UpdateRequest::SaveSettings(Settings::default())
+        ),
+        UpdateResponse::Failed(UpdateError::Settings)
+    );
+    assert!(updater.is_updating());
+    for chunk in chunks {
+        updater.handle(&mut flash, &chunk);
+    }
+    assert!(matches!(
+        updater.handle(&mut flash, &UpdateRequest::Finish),
+        UpdateResponse::Verified(_)
+    ));
+}
+
+#[test]
+fn images_larger_than_the_slot_and_short_chunks_are_refused() {
+    let mut flash = MemoryFlash::new(5);
+    let mut updater = updater();
+    let too_large = binary(SLOT_SIZE as usize + 1);
+    assert_eq!(
+        updater.handle(&mut flash, &begin(&too_large)),
+        UpdateResponse::Failed(UpdateError::TooLarge)
+    );
+
+    let image = image(2 * UPDATE_CHUNK_SIZE);
+    updater.handle(&mut flash, &begin(&image));
+    let data = &image[..100];
+    let short = UpdateRequest::Chunk {
+        offset: 0,
+        crc: image_crc(data),
+        data: data.try_into().unwrap(),
+    };
+    assert_eq!(
+        updater.handle(&mut flash, &short),
+        UpdateResponse::Failed(UpdateError::ShortChunk)
+    );
+    assert!(!updater.is_updating());
+}
+
+#[test]
+fn a_flash_failure_drops_the_update() {
+    let image = image(2 * UPDATE_CHUNK_SIZE);
+    let mut flash = MemoryFlash::new(5);
+    let mut updater = updater();
+    updater.handle(&mut flash, &begin(&image));
+
+    flash.cut_power_after(0);
+    let first = chunks(&image).next().unwrap();
+    assert_eq!(
+        updater.handle(&mut flash, &first),
+        UpdateResponse::Failed(UpdateError::Flash)
+    );
+    flash.restore_power();
+    assert!(!updater.is_updating());
+
+    let response = upload(&mut updater, &mut flash, &image);
+    assert!(matches!(response, UpdateResponse::Verified(_)));
+}
+
+/// Uploads `binary` under `header` signed by `signer`, returning the response to `Finish`.
+fn upload_signed(
+    signer: &ImageSigner,
+    header: &ImageHeader,
+    binary: &[u8],
+) -> (Updater, UpdateResponse) {
+    let mut flash = MemoryFlash::new(5);
+    let mut updater = updater();
+    let image = signer.sign(header, binary);
+    let response = upload(&mut updater, &mut flash, &image);
+    (updater, response)
+}
+
+#[test]
+fn an_image_signed_with_another_key_is_refused() {
+    let binary = binary(3 * UPDATE_CHUNK_SIZE);
+    let header = ImageSigner::header(&[Configuration::UnitTest], &binary);
+    let (rejecting, response) = upload_signed(&ImageSigner::new(2), &header, &binary);
+    assert_eq!(
+        response,
+        UpdateResponse::Failed(UpdateError::Image(ImageError::Signature))
+    );
+    assert!(!rejecting.is_updating());
+
+    // Changing the image after signing it is as bad
+    let mut image = ImageSigner::new(1).sign(&header, &binary);
+    image[IMAGE_HEADER_SIZE + 100] ^= 0x01;
+    let mut flash = MemoryFlash::new(5);
+    assert_eq!(
+        upload(&mut updater(), &mut flash, &image),
+        UpdateResponse::Failed(UpdateError::Image(ImageError::Signature))
+    );
+}
+
+#[test]
+fn an_image_built_for_another_configuration_is_refused() {
+    let binary = binary(UPDATE_CHUNK_SIZE);
+    let header = ImageSigner::header(
+        &[Configuration::Spirit101, Configuration::Spirit102],
+        &binary,
+    );
+    let (_, response) = upload_signed(&ImageSigner::new(1), &header, &binary);
+    assert_eq!(response, UpdateResponse::Failed(UpdateError::Incompatible));
+
+    let header = ImageSigner::header(
+        &[Configuration::Spirit101, Configuration::UnitTest],
+        &binary,
+    );
+    let (_, response) = upload_signed(&ImageSigner::new(1), &header, &binary);
+    assert!(
+        matches!(response, UpdateResponse::Verified(_)),
+        "{response:?}"
+    );
+}
+
+#[test]
+fn an_image_without_a_header_or_not_matching_it_is_refused() {
+    let mut flash = MemoryFlash::new(5);
+    let mut updater = updater();
+    assert_eq!(
+        upload(&mut updater, &mut flash, &binary(2 * UPDATE_CHUNK_SIZE)),
+        UpdateResponse::Failed(UpdateError::Image(ImageError::Header))
+    );
+    assert_eq!(
+        upload(&mut updater, &mut flash, &binary(100)),
+        UpdateResponse::Failed(UpdateError::Image(ImageError::Header))
+    );
+
+    let binary = binary(UPDATE_CHUNK_SIZE);
+    let mut header = ImageSigner::header(&[Configuration::UnitTest], &binary);
+    header.length += 1;
+    let (_, response) = upload_signed(&ImageSigner::new(1), &header, &binary);
+    assert_eq!(
+        response,
+        UpdateResponse::Failed(UpdateError::Image(ImageError::Crc))
+    );
+}
+
+#[test]
+fn a_signed_image_not_matching_the_crc_of_its_header_is_not_activated() {
+    let binary = binary(2 * UPDATE_CHUNK_SIZE);
+    let mut header = ImageSigner::header(&[Configuration::UnitTest], &binary);
+    header.crc ^= 1;
+    let (updater, response) = upload_signed(&ImageSigner::new(1), &header, &binary);
+    assert_eq!(
+        response,
+        UpdateResponse::Failed(UpdateError::Image(ImageError::Crc))
+    );
+    assert!(!updater.is_updating());
+}
+
+fn activated(active: Slot) -> BootRecord {
+    BootRecord {
+        active,
+        confirmed: false,
+    }
+}
+
+#[test]
+fn the_latest_boot_record_wins_across_sector_erases() {
+    let mut flash = MemoryFlash::new(1);
+    assert_eq!(BootRecords::new(0).load(&mut flash).unwrap(), None);
+
+    let mut boot_records = BootRecords::new(0);
+    // More saves than entries in a sector
+    for index in 0..200 {
+        let slot = [Slot::First, Slot::Second][index % 2];
+        boot_records.save(&mut flash, &activated(slot)).unwrap();
+        assert_eq!(
+            BootRecords::new(0).load(&mut flash).unwrap(),
+            Some(activated(slot))
+        );
+    }
+}
+
+#[test]
+fn losing_power_while_saving_a_boot_record_keeps_a_valid_one() {
+    let mut flash = MemoryFlash::new(1);
+    let mut boot_records = BootRecords::new(0);
+    boot_records
+        .save(&mut flash, &activated(Slot::Second))
+        .unwrap();
+
+    flash.cut_power_after(0);
+    assert!(boot_records
+        .save(&mut flash, &activated(Slot::First))
+        .is_err());
+    flash.restore_power();
+
+    // The interrupted entry is either complete or ignored
+    let mut rebooted = BootRecords::new(0);
+    let loaded = rebooted.load(&mut flash).unwrap();
+    assert!(
+        [Some(activated(Slot::Second)), Some(activated(Slot::First))].contains(&loaded),
+        "{loaded:?}"
+    );
+    rebooted.save(&mut flash, &activated(Slot::First)).unwrap();
+    assert_eq!(
+        BootRecords::new(0).load(&mut flash).unwrap(),
+        Some(activated(Slot::First))
+    );
+}
+
+#[test]
+fn slots_are_found_from_an_address() {
+    assert_eq!(Slot::containing(0x0800_0400), None);
+    assert_eq!(Slot::containing(Slot::First.address()), Some(Slot::First));
+    assert_eq!(Slot::containing(0x0810_0400), Some(Slot::Second));
+    // Bank 2 sectors 6 and 7 hold the settings
+    assert_eq!(Slot::containing(0x081C_0000), None);
+    assert_eq!(Slot::First.bank_offset(), 0x4_0000);
+    assert_eq!(Slot::Second.bank_offset(), 0);
+}